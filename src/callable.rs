@@ -1,29 +1,133 @@
-use value::Value;
+use std::rc::Rc;
+
 use function::LoxFunction;
+use value::Value;
+
+/// The signature of functions implemented in rust.  For methods, the first
+/// argument is the value the method was looked up on.  Errors are returned
+/// as a message, which the interpreter turns into a runtime error at the
+/// call site.
+pub type NativeFn = fn(Vec<Rc<Value>>) -> Result<Rc<Value>, String>;
+
+#[derive(Debug, Clone)]
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    /// How many more arguments can be given after the required ones.
+    optional: usize,
+    function: NativeFn,
+    receiver: Option<Rc<Value>>,
+}
+
+impl NativeFunction {
+    pub fn new(name: &str, arity: usize, function: NativeFn) -> NativeFunction {
+        NativeFunction {
+            name: String::from(name),
+            arity,
+            optional: 0,
+            function,
+            receiver: None,
+        }
+    }
+
+    /// Makes a function whose last few arguments can be left out, in which
+    /// case it's called with fewer arguments.
+    pub fn with_optional(name: &str, arity: usize, optional: usize, function: NativeFn) -> NativeFunction {
+        NativeFunction {
+            optional,
+            ..NativeFunction::new(name, arity, function)
+        }
+    }
+
+    /// Makes a method bound to the value it was looked up on.
+    pub fn bind(&self, receiver: Rc<Value>) -> NativeFunction {
+        NativeFunction {
+            receiver: Some(receiver),
+            ..self.clone()
+        }
+    }
+
+    pub fn call(&self, arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+        let mut all_arguments = Vec::new();
+        if let Some(ref receiver) = self.receiver {
+            all_arguments.push(Rc::clone(receiver));
+        }
+        all_arguments.extend(arguments);
+        (self.function)(all_arguments)
+    }
+}
+
+// function pointers can't be compared reliably, since the same function
+// can end up with more than one address, so natives are compared by what
+// they're called and what they're bound to
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &NativeFunction) -> bool {
+        self.name == other.name && self.arity == other.arity && self.optional == other.optional && self.receiver == other.receiver
+    }
+}
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum LoxCallable {
     Function(LoxFunction),
+    Native(NativeFunction),
 }
 
 impl LoxCallable {
     pub fn name(&self) -> &String {
         match *self {
-            LoxCallable::Function(ref f) => f.name()
+            LoxCallable::Function(ref f) => f.name(),
+            LoxCallable::Native(ref n) => n.name(),
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match *self {
+            LoxCallable::Function(ref f) => f.arity(),
+            LoxCallable::Native(ref n) => n.arity(),
+        }
+    }
+
+    /// Checks a call's arguments against the callable's parameters, and
+    /// matches them up: the result has the value of each parameter in order,
+    /// or `None` where the parameter's default should be used.  Native
+    /// functions don't take named arguments, and are simply called with
+    /// fewer arguments when optional ones are left out.
+    pub fn bind(&self, positional: Vec<Rc<Value>>, named: Vec<(String, Rc<Value>)>) -> Result<Vec<Option<Rc<Value>>>, String> {
+        match *self {
+            LoxCallable::Function(ref f) => f.bind(positional, named),
+            LoxCallable::Native(ref n) => {
+                if !named.is_empty() {
+                    Err(format!("{} doesn't take named arguments", n.name()))
+                } else if n.optional > 0 && (positional.len() < n.arity() || positional.len() > n.arity() + n.optional) {
+                    Err(format!("{} expects {} to {} arguments but got {}", n.name(), n.arity(), n.arity() + n.optional, positional.len()))
+                } else if n.optional == 0 && positional.len() != n.arity() {
+                    Err(format!("{} expects {} but got {}", n.name(), arguments(n.arity()), positional.len()))
+                } else {
+                    Ok(positional.into_iter().map(Some).collect())
+                }
+            },
         }
     }
 }
 
 pub trait Callable {
     fn name(&self) -> &String;
-    fn call(arguments: &Vec<&Value>) -> Value;
+    /// The number of arguments the callable takes (not counting a bound
+    /// receiver).
+    fn arity(&self) -> usize;
 }
 
-impl Callable for LoxCallable {
+impl Callable for NativeFunction {
     fn name(&self) -> &String {
-        unimplemented!()
+        &self.name
     }
-    fn call(arguments: &Vec<&Value>) -> Value {
-        unimplemented!()
+
+    fn arity(&self) -> usize {
+        self.arity
     }
 }
+
+/// A count of arguments, for error messages: "1 argument", "2 arguments".
+pub fn arguments(count: usize) -> String {
+    format!("{} argument{}", count, if count == 1 { "" } else { "s" })
+}
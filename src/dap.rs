@@ -0,0 +1,482 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use serde_json::Value as Json;
+
+use debugger::{evaluate_source, statement_lines, Controller, StepMode, StopReason};
+use environment::Environment;
+use interpreter::{interpret_with_debugger, CallFrame, Debugger};
+use module::Loader;
+use parser::parse;
+use scanner::scan;
+use statement::Stmt;
+use transport::{read_message, write_message};
+
+// Lox programs are single-threaded, so there's only ever one thread to
+// report
+const THREAD_ID: u64 = 1;
+
+enum Action {
+    Reply(Json),
+    Resume(StepMode),
+    Disconnect,
+}
+
+type RequestResult = Result<Action, String>;
+
+// what to do after handling a request
+enum Control {
+    Wait,
+    Resume(StepMode),
+    Disconnect,
+}
+
+struct Session<R, W> {
+    input: R,
+    output: W,
+    seq: u64,
+    controller: Controller,
+    program: Option<Vec<Stmt>>,
+    path: Option<String>,
+    executable_lines: BTreeSet<u32>,
+    stop_on_entry: bool,
+    configured: bool,
+    started: bool,
+    disconnected: bool,
+    globals: Rc<RefCell<Environment>>,
+    loader: Rc<RefCell<Loader>>,
+    // scopes handed out to the client while stopped; a variablesReference
+    // is an index into this, plus one
+    variable_scopes: Vec<Rc<RefCell<Environment>>>,
+}
+
+/// Runs a Debug Adapter Protocol server, reading requests from `input` and
+/// writing responses and events to `output`, until the client disconnects
+/// or closes the input.  The program named in the `launch` request starts
+/// once the client sends `configurationDone`.
+pub fn serve<R: BufRead, W: Write>(input: R, output: W) -> io::Result<()> {
+    let mut session = Session {
+        input,
+        output,
+        seq: 0,
+        controller: Controller::new(),
+        program: None,
+        path: None,
+        executable_lines: BTreeSet::new(),
+        stop_on_entry: false,
+        configured: false,
+        started: false,
+        disconnected: false,
+        globals: Rc::new(RefCell::new(Environment::new())),
+        loader: Rc::new(RefCell::new(Loader::new(Vec::new()))),
+        variable_scopes: Vec::new(),
+    };
+
+    while !session.disconnected {
+        let request = match read_message(&mut session.input)? {
+            Some(request) => request,
+            None => break,
+        };
+        if let Control::Disconnect = session.handle(&request, &[])? {
+            session.disconnected = true;
+        }
+
+        if session.configured && !session.started && session.program.is_some() {
+            session.run()?;
+        }
+    }
+    Ok(())
+}
+
+impl<R: BufRead, W: Write> Session<R, W> {
+    fn send(&mut self, mut message: Json) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.output, &message)
+    }
+
+    fn send_event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        self.send(json!({"type": "event", "event": event, "body": body}))
+    }
+
+    /// Handles one request and sends the response.  `frames` is the call
+    /// stack if the program is stopped, or empty if it hasn't started yet.
+    fn handle(&mut self, request: &Json, frames: &[CallFrame]) -> io::Result<Control> {
+        let command = request["command"].as_str().unwrap_or("");
+        let result = self.dispatch(command, &request["arguments"], frames);
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": result.is_ok(),
+        });
+        let control = match result {
+            Ok(Action::Reply(body)) => {
+                response["body"] = body;
+                Control::Wait
+            },
+            Ok(Action::Resume(mode)) => {
+                response["body"] = json!({"allThreadsContinued": true});
+                Control::Resume(mode)
+            },
+            Ok(Action::Disconnect) => Control::Disconnect,
+            Err(message) => {
+                response["message"] = json!(message);
+                Control::Wait
+            },
+        };
+        self.send(response)?;
+
+        if command == "initialize" {
+            self.send_event("initialized", json!({}))?;
+        }
+        Ok(control)
+    }
+
+    fn dispatch(&mut self, command: &str, arguments: &Json, frames: &[CallFrame]) -> RequestResult {
+        let stopped = !frames.is_empty();
+        match command {
+            "initialize" => Ok(Action::Reply(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsEvaluateForHovers": true,
+            }))),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => Ok(Action::Reply(self.set_breakpoints(arguments))),
+            "configurationDone" => {
+                self.configured = true;
+                Ok(Action::Reply(json!({})))
+            },
+            "threads" => Ok(Action::Reply(json!({"threads": [{"id": THREAD_ID, "name": "main"}]}))),
+            "stackTrace" => Ok(Action::Reply(self.stack_trace(frames))),
+            "scopes" => self.scopes(arguments, frames),
+            "variables" => self.variables(arguments),
+            "evaluate" => self.evaluate(arguments, frames),
+            "continue" if stopped => Ok(Action::Resume(StepMode::Continue)),
+            "next" if stopped => Ok(Action::Resume(StepMode::StepOver)),
+            "stepIn" if stopped => Ok(Action::Resume(StepMode::StepIn)),
+            "stepOut" if stopped => Ok(Action::Resume(StepMode::StepOut)),
+            "continue" | "next" | "stepIn" | "stepOut" => Err(String::from("The program is not stopped")),
+            "disconnect" | "terminate" => Ok(Action::Disconnect),
+            _ => Err(format!("Unsupported request '{}'", command)),
+        }
+    }
+
+    fn launch(&mut self, arguments: &Json) -> RequestResult {
+        let path = arguments["program"].as_str().ok_or("Missing 'program' argument")?;
+        let mut source = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut source))
+            .map_err(|err| format!("Failed to read file '{}': {}", path, err))?;
+        let statements = scan(&source).map_err(|error| vec![error]).and_then(parse).map_err(|errors| {
+            errors.iter().map(|error| error.to_string()).collect::<Vec<String>>().join("\n")
+        })?;
+
+        let strings = |key: &str| arguments[key].as_array()
+            .map(|items| items.iter().filter_map(|item| item.as_str()).map(String::from).collect())
+            .unwrap_or(vec![]);
+        let module_path = strings("modulePath").into_iter().map(PathBuf::from).collect();
+        self.loader = Rc::new(RefCell::new(Loader::new(module_path)));
+        self.loader.borrow_mut().set_args(strings("args"));
+
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        self.executable_lines = statement_lines(&statements);
+        self.path = Some(String::from(path));
+        self.program = Some(statements);
+        Ok(Action::Reply(json!({})))
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Json) -> Json {
+        self.controller.clear_breakpoints();
+        let requested: Vec<u64> = arguments["breakpoints"].as_array()
+            .map(|breakpoints| breakpoints.iter().filter_map(|b| b["line"].as_u64()).collect())
+            .unwrap_or(vec![]);
+        let mut breakpoints = Vec::new();
+        for line in requested.into_iter() {
+            let line = line as u32;
+            // before launch we can't tell which lines have code on them
+            let verified = self.path.is_none() || self.executable_lines.contains(&line);
+            if verified {
+                self.controller.set_breakpoint(line);
+            }
+            breakpoints.push(json!({"verified": verified, "line": line}));
+        }
+        json!({"breakpoints": breakpoints})
+    }
+
+    // frame ids count up from the innermost frame, and each frame's source
+    // is the file its function or module came from
+    fn stack_trace(&self, frames: &[CallFrame]) -> Json {
+        let stack_frames: Vec<Json> = frames.iter().rev().enumerate().map(|(i, frame)| json!({
+            "id": i,
+            "name": frame.function,
+            "line": frame.line,
+            "column": 1,
+            "source": source(frame.file.as_ref().or(self.path.as_ref()).map(|p| p.as_str()).unwrap_or("")),
+        })).collect();
+        json!({"stackFrames": stack_frames, "totalFrames": frames.len()})
+    }
+
+    fn frame<'a>(&self, arguments: &Json, frames: &'a [CallFrame]) -> Result<&'a CallFrame, String> {
+        let id = arguments["frameId"].as_u64().unwrap_or(0) as usize;
+        if id < frames.len() {
+            Ok(&frames[frames.len() - 1 - id])
+        } else {
+            Err(format!("Unknown frame {}", id))
+        }
+    }
+
+    fn scopes(&mut self, arguments: &Json, frames: &[CallFrame]) -> RequestResult {
+        let frame = self.frame(arguments, frames)?;
+        let mut result = Vec::new();
+        let mut current = Some(Rc::clone(&frame.environment));
+        while let Some(env) = current {
+            current = env.borrow().enclosing();
+            self.variable_scopes.push(Rc::clone(&env));
+            result.push(json!({
+                "name": if current.is_some() { "Locals" } else { "Globals" },
+                "presentationHint": if current.is_some() { "locals" } else { "globals" },
+                "variablesReference": self.variable_scopes.len(),
+                "expensive": false,
+            }));
+        }
+        Ok(Action::Reply(json!({"scopes": result})))
+    }
+
+    fn variables(&self, arguments: &Json) -> RequestResult {
+        let reference = arguments["variablesReference"].as_u64().unwrap_or(0) as usize;
+        let environment = match reference {
+            0 => None,
+            n => self.variable_scopes.get(n - 1),
+        }.ok_or(format!("Unknown variablesReference {}", reference))?;
+        let variables: Vec<Json> = environment.borrow().values().iter().map(|&(ref name, ref value)| json!({
+            "name": name,
+            "value": value.to_string(),
+            "variablesReference": 0,
+        })).collect();
+        Ok(Action::Reply(json!({"variables": variables})))
+    }
+
+    fn evaluate(&self, arguments: &Json, frames: &[CallFrame]) -> RequestResult {
+        let expression = arguments["expression"].as_str().ok_or("Missing 'expression' argument")?;
+        let environment = if frames.is_empty() {
+            Rc::clone(&self.globals)
+        } else {
+            Rc::clone(&self.frame(arguments, frames)?.environment)
+        };
+        evaluate_source(environment, expression)
+            .map(|value| Action::Reply(json!({"result": value.to_string(), "variablesReference": 0})))
+            .map_err(|error| error.to_string())
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        self.started = true;
+        let statements = self.program.take().unwrap();
+        if !self.stop_on_entry {
+            self.controller.resume(StepMode::Continue);
+        }
+
+        let globals = Rc::clone(&self.globals);
+        let loader = Rc::clone(&self.loader);
+        let path = self.path.clone();
+        let result = interpret_with_debugger(globals, statements, path.as_ref().map(|p| p.as_str()), loader, self);
+        if self.disconnected {
+            return Ok(());
+        }
+        let exit_code = match result {
+            Ok(_) => 0,
+            Err(error) => {
+                self.send_event("output", json!({"category": "stderr", "output": format!("{}\n", error)}))?;
+                1
+            },
+        };
+        self.send_event("exited", json!({"exitCode": exit_code}))?;
+        self.send_event("terminated", json!({}))
+    }
+}
+
+fn source(path: &str) -> Json {
+    let name = Path::new(path).file_name().and_then(|n| n.to_str()).unwrap_or(path);
+    json!({"name": name, "path": path})
+}
+
+impl<R: BufRead, W: Write> Debugger for Session<R, W> {
+    fn on_statement(&mut self, line: u32, frames: &[CallFrame]) -> Result<(), Box<Error>> {
+        let reason = match self.controller.should_stop(line, frames.len()) {
+            Some(StopReason::Breakpoint) => "breakpoint",
+            Some(StopReason::Step) if self.stop_on_entry => "entry",
+            Some(StopReason::Step) => "step",
+            None => return Ok(()),
+        };
+        self.stop_on_entry = false;
+        self.send_event("stopped", json!({"reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true}))?;
+
+        loop {
+            let request = match read_message(&mut self.input)? {
+                Some(request) => request,
+                None => {
+                    self.disconnected = true;
+                    break;
+                },
+            };
+            match self.handle(&request, frames)? {
+                Control::Wait => (),
+                Control::Resume(mode) => {
+                    self.variable_scopes.clear();
+                    self.controller.resume(mode);
+                    return Ok(());
+                },
+                Control::Disconnect => {
+                    self.disconnected = true;
+                    break;
+                },
+            }
+        }
+        Err(From::from("Debugging session ended"))
+    }
+
+    fn on_output(&mut self, text: &str) -> Result<(), Box<Error>> {
+        self.send_event("output", json!({"category": "stdout", "output": text}))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Cursor;
+
+    fn request(seq: u64, command: &str, arguments: Json) -> Json {
+        json!({"seq": seq, "type": "request", "command": command, "arguments": arguments})
+    }
+
+    // plays a scripted session against the adapter, returning everything it
+    // sent back
+    fn run_session(name: &str, source: &str, requests: Vec<Json>) -> Vec<Json> {
+        let path = env::temp_dir().join(format!("rlox-dap-{}.lox", name));
+        fs::write(&path, source).unwrap();
+        let requests: Vec<Json> = requests.into_iter().map(|mut r| {
+            if r["command"] == "launch" {
+                r["arguments"]["program"] = json!(path.to_str().unwrap());
+            }
+            r
+        }).collect();
+
+        let mut input = Vec::new();
+        for r in requests.iter() {
+            write_message(&mut input, r).unwrap();
+        }
+        let mut output = Vec::new();
+        serve(Cursor::new(input), &mut output).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut reader = Cursor::new(output);
+        let mut replies = Vec::new();
+        while let Some(reply) = read_message(&mut reader).unwrap() {
+            replies.push(reply);
+        }
+        replies
+    }
+
+    fn summarize(message: &Json) -> String {
+        match message["type"].as_str() {
+            Some("event") => format!("event {}", message["event"].as_str().unwrap()),
+            _ => format!("{} {}", message["command"].as_str().unwrap(), message["success"]),
+        }
+    }
+
+    #[test]
+    fn runs_to_breakpoints_and_inspects_state() {
+        let replies = run_session(
+            "breakpoints",
+            "var a = 1;\n{\n  var b = a + 1;\n  print b;\n}\nprint a;\n",
+            vec![
+                request(1, "initialize", json!({"adapterID": "rlox"})),
+                request(2, "launch", json!({})),
+                request(3, "setBreakpoints", json!({"source": {}, "breakpoints": [{"line": 4}, {"line": 5}]})),
+                request(4, "configurationDone", json!({})),
+                request(5, "threads", json!({})),
+                request(6, "stackTrace", json!({"threadId": 1})),
+                request(7, "scopes", json!({"frameId": 0})),
+                request(8, "variables", json!({"variablesReference": 1})),
+                request(9, "evaluate", json!({"expression": "b * 10", "frameId": 0})),
+                request(10, "next", json!({"threadId": 1})),
+                request(11, "continue", json!({"threadId": 1})),
+                request(12, "disconnect", json!({})),
+            ]
+        );
+
+        let summary: Vec<String> = replies.iter().map(summarize).collect();
+        assert_eq!(
+            vec!["initialize true", "event initialized", "launch true", "setBreakpoints true",
+                 "configurationDone true", "event stopped", "threads true", "stackTrace true",
+                 "scopes true", "variables true", "evaluate true", "next true", "event output",
+                 "event stopped", "continue true", "event output", "event exited",
+                 "event terminated", "disconnect true"],
+            summary
+        );
+
+        assert_eq!(json!([{"verified": true, "line": 4}, {"verified": false, "line": 5}]), replies[3]["body"]["breakpoints"]);
+        assert_eq!("breakpoint", replies[5]["body"]["reason"]);
+        assert_eq!(4, replies[7]["body"]["stackFrames"][0]["line"]);
+        assert_eq!(2, replies[8]["body"]["scopes"].as_array().unwrap().len());
+        assert_eq!(json!([{"name": "b", "value": "2", "variablesReference": 0}]), replies[9]["body"]["variables"]);
+        assert_eq!("20", replies[10]["body"]["result"]);
+        assert_eq!("2\n", replies[12]["body"]["output"]);
+        assert_eq!("step", replies[13]["body"]["reason"]);
+        assert_eq!(0, replies[16]["body"]["exitCode"]);
+    }
+
+    #[test]
+    fn stops_on_entry_and_steps() {
+        let replies = run_session(
+            "entry",
+            "var a = 1;\nprint a;\n",
+            vec![
+                request(1, "initialize", json!({})),
+                request(2, "launch", json!({"stopOnEntry": true})),
+                request(3, "configurationDone", json!({})),
+                request(4, "stepIn", json!({"threadId": 1})),
+                request(5, "stackTrace", json!({"threadId": 1})),
+                request(6, "disconnect", json!({})),
+            ]
+        );
+
+        let stops: Vec<&Json> = replies.iter().filter(|r| r["event"] == "stopped").map(|r| &r["body"]["reason"]).collect();
+        assert_eq!(vec!["entry", "step"], stops);
+        assert_eq!(2, replies[7]["body"]["stackFrames"][0]["line"]);
+        assert_eq!("disconnect", replies.last().unwrap()["command"]);
+    }
+
+    #[test]
+    fn imports_next_to_the_program_and_traces_each_frame_to_its_file() {
+        let helper = env::temp_dir().join("rlox-dap-imported-helper.lox");
+        fs::write(&helper, "export fun f() {\n  print 1;\n}\n").unwrap();
+        let replies = run_session(
+            "imports",
+            "import \"rlox-dap-imported-helper.lox\" as helper;\nhelper.f();\n",
+            vec![
+                request(1, "initialize", json!({})),
+                request(2, "launch", json!({})),
+                request(3, "setBreakpoints", json!({"source": {}, "breakpoints": [{"line": 2}]})),
+                request(4, "configurationDone", json!({})),
+                request(5, "stepIn", json!({"threadId": 1})),
+                request(6, "stackTrace", json!({"threadId": 1})),
+                request(7, "disconnect", json!({})),
+            ]
+        );
+        fs::remove_file(&helper).unwrap();
+
+        let frames = &replies.iter().find(|r| r["command"] == "stackTrace").unwrap()["body"]["stackFrames"];
+        assert_eq!("f", frames[0]["name"]);
+        assert_eq!("rlox-dap-imported-helper.lox", frames[0]["source"]["name"]);
+        assert_eq!("rlox-dap-imports.lox", frames[1]["source"]["name"]);
+    }
+}
@@ -0,0 +1,470 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::io;
+use std::io::prelude::*;
+use std::rc::Rc;
+
+use environment::Environment;
+use expression::Expr;
+use interpreter::{evaluate, CallFrame, Debugger};
+use parser::parse_expr;
+use scanner::scan;
+use statement::{Parameter, Stmt};
+use value::Value;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StepMode {
+    Continue,
+    /// Stop at the next statement on a different line, even in a called
+    /// function.
+    StepIn,
+    /// Stop at the next statement on a different line in the current
+    /// function (or its caller, if it returns).
+    StepOver,
+    /// Stop once the current function returns.
+    StepOut,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StopReason {
+    Breakpoint,
+    Step,
+}
+
+/// Keeps track of breakpoints and the stepping mode, and decides when the
+/// program should stop.  This is shared by the debugger front ends.
+pub struct Controller {
+    breakpoints: BTreeSet<u32>,
+    mode: StepMode,
+    stopped_line: u32,
+    stopped_depth: usize,
+    last_line: u32,
+    last_depth: usize,
+}
+
+impl Controller {
+    /// Creates a controller with no breakpoints, in `StepIn` mode so that it
+    /// stops at the first statement.
+    pub fn new() -> Controller {
+        Controller {
+            breakpoints: BTreeSet::new(),
+            mode: StepMode::StepIn,
+            stopped_line: 0,
+            stopped_depth: 0,
+            last_line: 0,
+            last_depth: 0,
+        }
+    }
+
+    pub fn set_breakpoint(&mut self, line: u32) {
+        self.breakpoints.insert(line);
+    }
+
+    pub fn clear_breakpoint(&mut self, line: u32) -> bool {
+        self.breakpoints.remove(&line)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> Vec<u32> {
+        self.breakpoints.iter().cloned().collect()
+    }
+
+    pub fn resume(&mut self, mode: StepMode) {
+        self.mode = mode;
+    }
+
+    /// Called before each statement runs, with the number of frames on the
+    /// call stack; returns why execution should stop there, if it should.
+    pub fn should_stop(&mut self, line: u32, depth: usize) -> Option<StopReason> {
+        let new_line = line != self.last_line || depth != self.last_depth;
+        self.last_line = line;
+        self.last_depth = depth;
+
+        let moved = line != self.stopped_line || depth != self.stopped_depth;
+        let stepped = match self.mode {
+            StepMode::Continue => false,
+            StepMode::StepIn => moved,
+            StepMode::StepOver => depth < self.stopped_depth || (depth == self.stopped_depth && moved),
+            StepMode::StepOut => depth < self.stopped_depth,
+        };
+        let reason = if stepped {
+            Some(StopReason::Step)
+        } else if new_line && self.breakpoints.contains(&line) {
+            Some(StopReason::Breakpoint)
+        } else {
+            None
+        };
+
+        if reason.is_some() {
+            self.mode = StepMode::Continue;
+            self.stopped_line = line;
+            self.stopped_depth = depth;
+        }
+        reason
+    }
+}
+
+/// Returns the lines that have a statement on them, which are the only
+/// lines where a breakpoint can be hit.
+pub fn statement_lines(statements: &Vec<Stmt>) -> BTreeSet<u32> {
+    let mut lines = BTreeSet::new();
+    for stmt in statements.iter() {
+        add_statement_lines(&mut lines, stmt);
+    }
+    lines
+}
+
+fn add_statement_lines(lines: &mut BTreeSet<u32>, stmt: &Stmt) {
+    match stmt {
+        &Stmt::Block { ref statements, .. } => for statement in statements.iter() {
+            add_statement_lines(lines, statement);
+        },
+        &Stmt::Expression { ref expression, line } | &Stmt::Print { ref expression, line } | &Stmt::Throw { ref expression, line } => {
+            lines.insert(line);
+            add_expression_lines(lines, expression);
+        },
+        &Stmt::For { ref initializer, ref condition, ref increment, ref body, line } => {
+            lines.insert(line);
+            add_expression_lines(lines, condition);
+            for s in initializer.iter().chain(increment.iter()) {
+                add_statement_lines(lines, s);
+            }
+            add_statement_lines(lines, body);
+        },
+        &Stmt::ForIn { ref iterable, ref body, line, .. } => {
+            lines.insert(line);
+            add_expression_lines(lines, iterable);
+            add_statement_lines(lines, body);
+        },
+        &Stmt::Export { ref declaration, .. } => add_statement_lines(lines, declaration),
+        &Stmt::Function { ref name, ref params, ref body } => {
+            lines.insert(name.line);
+            add_function_lines(lines, params, body);
+        },
+        &Stmt::If { ref expression, ref then_branch, ref else_branch, line } => {
+            lines.insert(line);
+            add_expression_lines(lines, expression);
+            add_statement_lines(lines, then_branch);
+            if let &Some(ref eb) = else_branch {
+                add_statement_lines(lines, eb);
+            }
+        },
+        &Stmt::Try { ref body, ref catch_branch, ref finally_branch, line } => {
+            lines.insert(line);
+            add_statement_lines(lines, body);
+            if let &Some((_, ref cb)) = catch_branch {
+                add_statement_lines(lines, cb);
+            }
+            if let &Some(ref fb) = finally_branch {
+                add_statement_lines(lines, fb);
+            }
+        },
+        &Stmt::Return { value: Some(ref expression), .. } | &Stmt::Var { initializer: Some(ref expression), .. } => {
+            lines.insert(stmt.line());
+            add_expression_lines(lines, expression);
+        },
+        _ => {
+            lines.insert(stmt.line());
+        },
+    }
+}
+
+fn add_function_lines(lines: &mut BTreeSet<u32>, params: &Vec<Parameter>, body: &Vec<Stmt>) {
+    for default in params.iter().filter_map(|p| p.default.as_ref()) {
+        add_expression_lines(lines, default);
+    }
+    for statement in body.iter() {
+        add_statement_lines(lines, statement);
+    }
+}
+
+// the bodies of anonymous functions have statements of their own
+fn add_expression_lines(lines: &mut BTreeSet<u32>, expr: &Expr) {
+    match expr {
+        &Expr::Lambda { ref params, ref body, .. } => add_function_lines(lines, params, body),
+        &Expr::Assign { ref value, .. } => add_expression_lines(lines, value),
+        &Expr::Binary { ref left, ref right, .. } | &Expr::Logical { ref left, ref right, .. } => {
+            add_expression_lines(lines, left);
+            add_expression_lines(lines, right);
+        },
+        &Expr::Call { ref callee, ref arguments, ref named, .. } => {
+            add_expression_lines(lines, callee);
+            for argument in arguments.iter().chain(named.iter().map(|&(_, ref value)| value)) {
+                add_expression_lines(lines, argument);
+            }
+        },
+        &Expr::Conditional { ref condition, ref then_branch, ref else_branch, .. } => {
+            add_expression_lines(lines, condition);
+            add_expression_lines(lines, then_branch);
+            add_expression_lines(lines, else_branch);
+        },
+        &Expr::Get { ref object, .. } => add_expression_lines(lines, object),
+        &Expr::Grouping { ref expression } | &Expr::Unary { right: ref expression, .. } => add_expression_lines(lines, expression),
+        &Expr::Index { ref object, ref index, .. } => {
+            add_expression_lines(lines, object);
+            add_expression_lines(lines, index);
+        },
+        &Expr::Interpolation { parts: ref exprs } | &Expr::List { elements: ref exprs } => for e in exprs.iter() {
+            add_expression_lines(lines, e);
+        },
+        &Expr::Map { ref entries, .. } => for &(ref key, ref value) in entries.iter() {
+            add_expression_lines(lines, key);
+            add_expression_lines(lines, value);
+        },
+        &Expr::Range { ref start, ref end, ref step, .. } => {
+            add_expression_lines(lines, start);
+            add_expression_lines(lines, end);
+            if let &Some(ref s) = step {
+                add_expression_lines(lines, s);
+            }
+        },
+        &Expr::SetIndex { ref object, ref index, ref value, .. } => {
+            add_expression_lines(lines, object);
+            add_expression_lines(lines, index);
+            add_expression_lines(lines, value);
+        },
+        &Expr::Literal { .. } | &Expr::Variable { .. } => (),
+    }
+}
+
+/// Evaluates an expression typed by the user in the given scope.
+pub fn evaluate_source(environment: Rc<RefCell<Environment>>, source: &str) -> Result<Rc<Value>, Box<Error>> {
+    let tokens = scan(&String::from(source))?;
+    let expr = parse_expr(tokens)?;
+    evaluate(environment, &expr)
+}
+
+/// Lists each scope visible from the environment, innermost first, along
+/// with the variables defined in it.
+pub fn scopes(environment: &Rc<RefCell<Environment>>) -> Vec<(String, Vec<(String, Rc<Value>)>)> {
+    let mut scopes = Vec::new();
+    let mut current = Some(Rc::clone(environment));
+    while let Some(env) = current {
+        let enclosing = env.borrow().enclosing();
+        let name = match enclosing {
+            Some(_) => format!("Block {}", scopes.len()),
+            None => String::from("Globals"),
+        };
+        scopes.push((name, env.borrow().values()));
+        current = enclosing;
+    }
+    scopes
+}
+
+const HELP: &'static str = "\
+Commands:
+  break LINE (b)       set a breakpoint
+  delete [LINE] (d)    remove a breakpoint, or all of them
+  breakpoints          list breakpoints
+  continue (c)         run until the next breakpoint
+  step (s)             step to the next line, into calls
+  next (n)             step to the next line, over calls
+  finish               run until the current function returns
+  backtrace (bt)       show the call stack
+  frame N (f)          select a frame for 'vars' and 'print'
+  vars                 show the variables in each scope of the frame
+  print EXPR (p)       evaluate an expression in the frame
+  list (l)             show the source around the current line
+  quit (q)             stop the program";
+
+/// An interactive, command-line driven debugger.
+pub struct ConsoleDebugger<R, W> {
+    controller: Controller,
+    source_lines: Vec<String>,
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> ConsoleDebugger<R, W> {
+    pub fn new(source: &String, input: R, output: W) -> ConsoleDebugger<R, W> {
+        ConsoleDebugger {
+            controller: Controller::new(),
+            source_lines: source.lines().map(|line| String::from(line)).collect(),
+            input,
+            output,
+        }
+    }
+
+    fn source_line(&self, line: u32) -> String {
+        let index = (line as usize).saturating_sub(1);
+        self.source_lines.get(index).map(|s| String::from(s.trim())).unwrap_or(String::new())
+    }
+
+    fn command_loop(&mut self, frames: &[CallFrame]) -> io::Result<Option<StepMode>> {
+        let mut selected = frames.len() - 1;
+        loop {
+            write!(self.output, "(rlox) ")?;
+            self.output.flush()?;
+            let mut command = String::new();
+            if self.input.read_line(&mut command)? == 0 {
+                return Ok(None);
+            }
+            let mut parts = command.trim().splitn(2, char::is_whitespace);
+            let name = parts.next().unwrap_or("");
+            let argument = parts.next().unwrap_or("").trim();
+            match name {
+                "" => (),
+                "c" | "continue" => return Ok(Some(StepMode::Continue)),
+                "s" | "step" => return Ok(Some(StepMode::StepIn)),
+                "n" | "next" => return Ok(Some(StepMode::StepOver)),
+                "finish" => return Ok(Some(StepMode::StepOut)),
+                "q" | "quit" => return Ok(None),
+                "b" | "break" => match argument.parse::<u32>() {
+                    Ok(line) => {
+                        self.controller.set_breakpoint(line);
+                        writeln!(self.output, "Breakpoint set at line {}", line)?;
+                    },
+                    Err(_) => writeln!(self.output, "Usage: break LINE")?,
+                },
+                "d" | "delete" => match argument.parse::<u32>() {
+                    Ok(line) if self.controller.clear_breakpoint(line) => writeln!(self.output, "Breakpoint at line {} deleted", line)?,
+                    Ok(line) => writeln!(self.output, "No breakpoint at line {}", line)?,
+                    Err(_) if argument.is_empty() => {
+                        self.controller.clear_breakpoints();
+                        writeln!(self.output, "All breakpoints deleted")?;
+                    },
+                    Err(_) => writeln!(self.output, "Usage: delete [LINE]")?,
+                },
+                "breakpoints" => {
+                    let breakpoints = self.controller.breakpoints();
+                    if breakpoints.is_empty() {
+                        writeln!(self.output, "No breakpoints")?;
+                    }
+                    for line in breakpoints.iter() {
+                        writeln!(self.output, "line {}: {}", line, self.source_line(*line))?;
+                    }
+                },
+                "bt" | "backtrace" => for (i, frame) in frames.iter().rev().enumerate() {
+                    writeln!(self.output, "#{} [line {}] in {}", i, frame.line, frame.function)?;
+                },
+                "f" | "frame" => match argument.parse::<usize>() {
+                    Ok(n) if n < frames.len() => {
+                        selected = frames.len() - 1 - n;
+                        let frame = &frames[selected];
+                        writeln!(self.output, "#{} [line {}] in {}", n, frame.line, frame.function)?;
+                    },
+                    _ => writeln!(self.output, "Usage: frame N, where N is from 0 to {}", frames.len() - 1)?,
+                },
+                "vars" => for (name, values) in scopes(&frames[selected].environment).into_iter() {
+                    writeln!(self.output, "{}:", name)?;
+                    for (variable, value) in values.iter() {
+                        writeln!(self.output, "  {} = {}", variable, value)?;
+                    }
+                },
+                "p" | "print" => match evaluate_source(Rc::clone(&frames[selected].environment), argument) {
+                    Ok(value) => writeln!(self.output, "{}", value)?,
+                    Err(error) => writeln!(self.output, "{}", error)?,
+                },
+                "l" | "list" => {
+                    let current = frames[selected].line as usize;
+                    let first = current.saturating_sub(5).max(1);
+                    let last = (current + 5).min(self.source_lines.len());
+                    for n in first..(last + 1) {
+                        let marker = if n == current { "=>" } else if self.controller.breakpoints.contains(&(n as u32)) { " *" } else { "  " };
+                        writeln!(self.output, "{} {:4} {}", marker, n, self.source_lines[n - 1])?;
+                    }
+                },
+                "h" | "help" => writeln!(self.output, "{}", HELP)?,
+                _ => writeln!(self.output, "Unknown command '{}'; try 'help'", name)?,
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Debugger for ConsoleDebugger<R, W> {
+    fn on_statement(&mut self, line: u32, frames: &[CallFrame]) -> Result<(), Box<Error>> {
+        let reason = match self.controller.should_stop(line, frames.len()) {
+            Some(reason) => reason,
+            None => return Ok(()),
+        };
+        if reason == StopReason::Breakpoint {
+            write!(self.output, "Breakpoint, ")?;
+        }
+        let function = frames.last().map(|f| f.function.as_str()).unwrap_or("");
+        writeln!(self.output, "[line {}] in {}: {}", line, function, self.source_line(line))?;
+
+        match self.command_loop(frames)? {
+            Some(mode) => {
+                self.controller.resume(mode);
+                Ok(())
+            },
+            None => Err(From::from("Debugging session ended")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interpreter::interpret_with_debugger;
+    use module::Loader;
+    use parser::parse;
+    use std::io::Cursor;
+
+    fn debug(source: &str, commands: &str) -> String {
+        let source = String::from(source);
+        let statements = scan(&source).map_err(|e| vec![e]).and_then(parse).unwrap();
+        let mut output = Vec::new();
+        {
+            let mut debugger = ConsoleDebugger::new(&source, Cursor::new(commands), &mut output);
+            let environment = Rc::new(RefCell::new(Environment::new()));
+            let loader = Rc::new(RefCell::new(Loader::new(Vec::new())));
+            let _ = interpret_with_debugger(environment, statements, None, loader, &mut debugger);
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn stops_at_breakpoints_and_inspects_scopes() {
+        let output = debug(
+            "var a = 1;\n{\n  var b = a + 1;\n  b = b * 2;\n}\nprint a;\n",
+            "break 4\ncontinue\nvars\nprint b + 10\nbt\ncontinue\n"
+        );
+        assert_eq!(
+            "[line 1] in script: var a = 1;\n\
+             (rlox) Breakpoint set at line 4\n\
+             (rlox) Breakpoint, [line 4] in script: b = b * 2;\n\
+             (rlox) Block 0:\n  b = 2\nGlobals:\n  a = 1\n\
+             (rlox) 12\n\
+             (rlox) #0 [line 4] in script\n\
+             (rlox) ",
+            &output
+        )
+    }
+
+    #[test]
+    fn steps_line_by_line() {
+        let output = debug("var a = 1;\nif (a > 0)\n  a = 2;\nprint a;\n", "next\nnext\nstep\nquit\n");
+        let stops: Vec<&str> = output.lines().filter(|l| l.contains("[line")).collect();
+        assert_eq!(4, stops.len());
+        assert!(stops[3].ends_with("[line 4] in script: print a;"));
+    }
+
+    #[test]
+    fn controller_steps_over_and_out_of_calls() {
+        let mut controller = Controller::new();
+        assert_eq!(Some(StopReason::Step), controller.should_stop(1, 1));
+        controller.resume(StepMode::StepOver);
+        assert_eq!(None, controller.should_stop(5, 2));
+        assert_eq!(Some(StopReason::Step), controller.should_stop(2, 1));
+        controller.resume(StepMode::StepIn);
+        assert_eq!(Some(StopReason::Step), controller.should_stop(5, 2));
+        controller.resume(StepMode::StepOut);
+        assert_eq!(None, controller.should_stop(6, 2));
+        assert_eq!(Some(StopReason::Step), controller.should_stop(3, 1));
+    }
+
+    #[test]
+    fn lists_statement_lines() {
+        let source = String::from("var a = 1;\n\nfor (var i = 0; i < 2; i = i + 1)\n{\n  print i;\n}\n");
+        let statements = scan(&source).map_err(|e| vec![e]).and_then(parse).unwrap();
+        assert_eq!(vec![1, 3, 5], statement_lines(&statements).into_iter().collect::<Vec<u32>>());
+        let source = String::from("fun f(g) {\n  return g(1);\n}\nprint f((x) => {\n  return x;\n});\n");
+        let statements = scan(&source).map_err(|e| vec![e]).and_then(parse).unwrap();
+        assert_eq!(vec![1, 2, 4, 5], statement_lines(&statements).into_iter().collect::<Vec<u32>>());
+    }
+}
@@ -38,6 +38,20 @@ impl Environment {
         }
     }
 
+    pub fn enclosing(&self) -> Option<Rc<RefCell<Environment>>> {
+        self.enclosing.as_ref().map(|enc| Rc::clone(enc))
+    }
+
+    /// Returns the variables defined directly in this scope (not in
+    /// enclosing ones), sorted by name.
+    pub fn values(&self) -> Vec<(String, Rc<Value>)> {
+        let mut values: Vec<(String, Rc<Value>)> = self.values.iter()
+            .map(|(name, value)| (name.clone(), Rc::clone(value)))
+            .collect();
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        values
+    }
+
     pub fn get(&self, name: &Token) -> Option<Rc<Value>> {
         match self.values.get(&name.lexeme) {
             Some(v) => Some(v.clone()),
@@ -1,6 +1,7 @@
 use std::fmt;
 use std::rc::Rc;
 
+use statement::{Parameter, Stmt};
 use token::Token;
 use value::Value;
 
@@ -8,9 +9,29 @@ use value::Value;
 pub enum Expr {
     Assign { name: Token, value: Box<Expr> },
     Binary { left: Box<Expr>, operator: Token, right: Box<Expr> },
+    /// Named arguments, written `name: value`, come after the positional
+    /// ones.
+    Call { callee: Box<Expr>, paren: Token, arguments: Vec<Expr>, named: Vec<(Token, Expr)> },
+    /// `cond ? a : b`
+    Conditional { condition: Box<Expr>, question: Token, then_branch: Box<Expr>, else_branch: Box<Expr> },
+    /// A property access; an optional one, written `a?.b`, skips the rest
+    /// of the chain it's in if `a` is nil.
+    Get { object: Box<Expr>, name: Token, optional: bool },
     Grouping { expression: Box<Expr> },
+    /// A string with embedded expressions; the parts are concatenated.
+    Interpolation { parts: Vec<Expr> },
+    Index { object: Box<Expr>, bracket: Token, index: Box<Expr> },
+    /// An anonymous function, either `fun (a) { ... }` or `(a) => ...`;
+    /// the keyword is the `fun` or the `=>`.
+    Lambda { keyword: Token, params: Vec<Parameter>, body: Rc<Vec<Stmt>> },
+    List { elements: Vec<Expr> },
     Literal { value: Rc<Value> },
     Logical { left: Box<Expr>, operator: Token, right: Box<Expr> },
+    Range { start: Box<Expr>, operator: Token, end: Box<Expr>, step: Option<Box<Expr>> },
+    Map { brace: Token, entries: Vec<(Expr, Expr)> },
+    /// `operator` is the binary operator of a compound assignment such as
+    /// `xs[i] += 1`, which is applied to the element that's there.
+    SetIndex { object: Box<Expr>, bracket: Token, index: Box<Expr>, operator: Option<Token>, value: Box<Expr> },
     Unary { operator: Token, right: Box<Expr> },
     Variable { name: Token },
 }
@@ -31,12 +52,66 @@ impl Expr {
         }
     }
 
+    pub fn call(callee: Expr, paren: Token, arguments: Vec<Expr>, named: Vec<(Token, Expr)>) -> Expr {
+        Expr::Call {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+            named,
+        }
+    }
+
+    pub fn conditional(condition: Expr, question: Token, then_branch: Expr, else_branch: Expr) -> Expr {
+        Expr::Conditional {
+            condition: Box::new(condition),
+            question,
+            then_branch: Box::new(then_branch),
+            else_branch: Box::new(else_branch),
+        }
+    }
+
+    pub fn get(object: Expr, name: Token, optional: bool) -> Expr {
+        Expr::Get {
+            object: Box::new(object),
+            name,
+            optional,
+        }
+    }
+
     pub fn grouping(expression: Expr) -> Expr {
         Expr::Grouping {
             expression: Box::new(expression),
         }
     }
 
+    pub fn index(object: Expr, bracket: Token, index: Expr) -> Expr {
+        Expr::Index {
+            object: Box::new(object),
+            bracket,
+            index: Box::new(index),
+        }
+    }
+
+    pub fn interpolation(parts: Vec<Expr>) -> Expr {
+        Expr::Interpolation {
+            parts,
+        }
+    }
+
+    pub fn lambda(keyword: Token, params: Vec<Parameter>, body: Vec<Stmt>) -> Expr {
+        Expr::Lambda {
+            keyword,
+            params,
+            body: Rc::new(body),
+        }
+    }
+
+    pub fn list(elements: Vec<Expr>) -> Expr {
+        Expr::List {
+            elements,
+        }
+    }
+
     pub fn literal(value: Value) -> Expr {
         Expr::Literal {
             value: Rc::new(value),
@@ -51,6 +126,32 @@ impl Expr {
         }
     }
 
+    pub fn map(brace: Token, entries: Vec<(Expr, Expr)>) -> Expr {
+        Expr::Map {
+            brace,
+            entries,
+        }
+    }
+
+    pub fn range(start: Expr, operator: Token, end: Expr, step: Option<Expr>) -> Expr {
+        Expr::Range {
+            start: Box::new(start),
+            operator,
+            end: Box::new(end),
+            step: step.map(|s| Box::new(s)),
+        }
+    }
+
+    pub fn set_index(object: Expr, bracket: Token, index: Expr, operator: Option<Token>, value: Expr) -> Expr {
+        Expr::SetIndex {
+            object: Box::new(object),
+            bracket,
+            index: Box::new(index),
+            operator,
+            value: Box::new(value),
+        }
+    }
+
     pub fn unary(operator: Token, right: Expr) -> Expr {
         Expr::Unary {
             operator,
@@ -70,9 +171,51 @@ impl fmt::Display for Expr {
         match self {
             &Expr::Assign { ref name, ref value } => write!(f, "{} = {}", name.lexeme, value),
             &Expr::Binary { ref left, ref operator, ref right } => write!(f, "{} {} {}", left, operator, right),
+            &Expr::Call { ref callee, ref arguments, ref named, .. } => {
+                let arguments: Vec<String> = arguments.iter().map(|a| a.to_string())
+                    .chain(named.iter().map(|&(ref name, ref value)| format!("{}: {}", name.lexeme, value)))
+                    .collect();
+                write!(f, "{}({})", callee, arguments.join(", "))
+            },
+            &Expr::Conditional { ref condition, ref then_branch, ref else_branch, .. } => {
+                write!(f, "{} ? {} : {}", condition, then_branch, else_branch)
+            },
+            &Expr::Get { ref object, ref name, optional } => write!(f, "{}{}{}", object, if optional { "?." } else { "." }, name.lexeme),
             &Expr::Grouping { ref expression } => write!(f, "({})", expression),
+            &Expr::Interpolation { ref parts } => {
+                let parts: Vec<String> = parts.iter().map(|p| p.to_string()).collect();
+                write!(f, "`{}`", parts.join(" "))
+            },
+            &Expr::Index { ref object, ref index, .. } => write!(f, "{}[{}]", object, index),
+            &Expr::Lambda { ref params, .. } => {
+                let params: Vec<String> = params.iter().map(|p| match p.default {
+                    _ if p.rest => format!("...{}", p.name.lexeme),
+                    Some(ref default) => format!("{} = {}", p.name.lexeme, default),
+                    None => p.name.lexeme.clone(),
+                }).collect();
+                write!(f, "fun ({}) {{ ... }}", params.join(", "))
+            },
+            &Expr::List { ref elements } => {
+                let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+                write!(f, "[{}]", elements.join(", "))
+            },
             &Expr::Literal { ref value } => write!(f, "{}", value),
             &Expr::Logical { ref left, ref operator, ref right } => write!(f, "{} {} {}", left, operator, right),
+            &Expr::Map { ref entries, .. } => {
+                let entries: Vec<String> = entries.iter().map(|&(ref k, ref v)| format!("{}: {}", k, v)).collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            },
+            &Expr::Range { ref start, ref operator, ref end, ref step } => {
+                write!(f, "{}{}{}", start, operator.lexeme, end)?;
+                match step {
+                    &Some(ref s) => write!(f, " step {}", s),
+                    &None => Ok(()),
+                }
+            },
+            &Expr::SetIndex { ref object, ref index, operator: Some(ref operator), ref value, .. } => {
+                write!(f, "{}[{}] {}= {}", object, index, operator.lexeme, value)
+            },
+            &Expr::SetIndex { ref object, ref index, ref value, .. } => write!(f, "{}[{}] = {}", object, index, value),
             &Expr::Unary { ref operator, ref right } => write!(f, "{} {}", operator, right),
             &Expr::Variable { ref name } => write!(f, "{}", name),
         }
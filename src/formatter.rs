@@ -0,0 +1,320 @@
+use std::error::Error;
+
+use parser::parse;
+use scanner::scan;
+use token::{TokenType, Token};
+use token::TokenType::*;
+
+const INDENT: &'static str = "    ";
+
+struct State {
+    output: String,
+    indent: usize,
+    paren_depth: usize,
+    /// The paren depth outside each open block, since a function body can
+    /// be inside a call's parentheses and its statements still end lines.
+    block_paren_depths: Vec<usize>,
+    pending_newline: bool,
+    unary_minus: bool,
+    /// Whether a range operator has been seen in the current expression,
+    /// and whether the previous token was the `step` that can follow a
+    /// range (which, unlike other identifiers, doesn't end an operand).
+    in_range: bool,
+    range_step: bool,
+    /// For each open brace, whether it starts a map literal rather than a
+    /// block.
+    braces: Vec<bool>,
+    /// Whether the previous token was the brace of a map literal.
+    map_brace: bool,
+    /// For each open brace (and the top level), how many `?`s are waiting
+    /// for the `:` of their conditional, which unlike a map's `:` is spaced.
+    questions: Vec<u32>,
+    prev: Option<Token>,
+    prev_end_line: u32,
+}
+
+impl State {
+    fn new() -> State {
+        State {
+            output: String::new(),
+            indent: 0,
+            paren_depth: 0,
+            block_paren_depths: Vec::new(),
+            pending_newline: false,
+            unary_minus: false,
+            in_range: false,
+            range_step: false,
+            braces: Vec::new(),
+            map_brace: false,
+            questions: vec![0],
+            prev: None,
+            prev_end_line: 1,
+        }
+    }
+
+    fn prev_type(&self) -> Option<&TokenType> {
+        self.prev.as_ref().map(|p| &p.token_type)
+    }
+
+    fn newline(&mut self, blank_line: bool, continuation: bool) {
+        if !self.output.is_empty() {
+            self.output.push('\n');
+            if blank_line {
+                self.output.push('\n');
+            }
+        }
+        let indent = if continuation { self.indent + 1 } else { self.indent };
+        for _ in 0..indent {
+            self.output.push_str(INDENT);
+        }
+    }
+}
+
+/// Formats Lox source code, returning the formatted source.  Comments are
+/// preserved, and at most one blank line is kept between statements.  Source
+/// that doesn't parse is rejected rather than formatted.
+pub fn format(source: &String) -> Result<String, Vec<Box<Error>>> {
+    let tokens = scan(source).map_err(|error| vec![error])?;
+    parse(tokens.clone())?;
+
+    let mut state = State::new();
+    for token in tokens.into_iter() {
+        if token.token_type == Eof {
+            break;
+        }
+        emit_token(&mut state, token);
+    }
+    state.output.push('\n');
+    Ok(state.output)
+}
+
+fn emit_token(state: &mut State, token: Token) {
+    let map_brace = match token.token_type {
+        LeftBrace => {
+            let map = starts_map(state.prev_type());
+            state.braces.push(map);
+            map
+        },
+        RightBrace => state.braces.pop().unwrap_or(false),
+        _ => false,
+    };
+    match token.token_type {
+        RightBrace if !map_brace => {
+            state.indent = state.indent.saturating_sub(1);
+            state.paren_depth = state.block_paren_depths.pop().unwrap_or(0);
+        },
+        RightParen => state.paren_depth = state.paren_depth.saturating_sub(1),
+        _ => (),
+    }
+
+    let same_source_line = token.line <= state.prev_end_line;
+    let starts_line = match token.token_type {
+        _ if state.prev.is_none() => true,
+        Comment => !same_source_line,
+        Else | Catch | Finally if state.prev_type() == Some(&RightBrace) => false,
+        Semicolon | RightParen | Comma if state.prev_type() == Some(&RightBrace) => false,
+        LeftBrace | RightBrace if map_brace => false,
+        RightBrace => true,
+        _ => state.pending_newline,
+    };
+
+    if starts_line {
+        let prev_type = state.prev_type().cloned();
+        let blank_line = token.line > state.prev_end_line + 1
+            && prev_type != Some(LeftBrace)
+            && token.token_type != RightBrace;
+        let continuation = match prev_type {
+            None | Some(Semicolon) | Some(LeftBrace) | Some(RightBrace) => false,
+            Some(Comment) => !state.pending_newline,
+            _ => true,
+        };
+        state.newline(blank_line, continuation);
+    } else if needs_space(state, &token, map_brace) {
+        state.output.push(' ');
+    }
+
+    state.output.push_str(&token.lexeme);
+    state.pending_newline = match token.token_type {
+        LeftBrace | RightBrace => !map_brace,
+        Semicolon => state.paren_depth == 0,
+        Comment => token.lexeme.starts_with("//") || state.pending_newline,
+        _ => false,
+    };
+
+    match token.token_type {
+        LeftBrace if !map_brace => {
+            state.indent += 1;
+            state.block_paren_depths.push(state.paren_depth);
+            state.paren_depth = 0;
+        },
+        LeftParen => state.paren_depth += 1,
+        _ => (),
+    }
+    match token.token_type {
+        LeftBrace => state.questions.push(0),
+        RightBrace if state.questions.len() > 1 => { state.questions.pop(); },
+        Question => *state.questions.last_mut().unwrap() += 1,
+        Colon => {
+            let questions = state.questions.last_mut().unwrap();
+            *questions = questions.saturating_sub(1);
+        },
+        _ => (),
+    }
+
+    state.map_brace = map_brace;
+    state.unary_minus = token.token_type == Minus && (state.range_step || !state.prev_type().map_or(false, ends_operand));
+    state.range_step = state.in_range && token.token_type == Identifier && token.lexeme == "step"
+        && state.prev_type().map_or(false, ends_operand);
+    state.in_range = match token.token_type {
+        DotDot | DotDotEqual => true,
+        Semicolon | Comma | LeftBrace | RightBrace => false,
+        _ => state.in_range,
+    };
+    state.prev_end_line = token.line + token.lexeme.matches('\n').count() as u32;
+    state.prev = Some(token);
+}
+
+// a brace starts a block when it follows the end of a statement or the
+// header of a compound statement; anywhere else it's a map literal
+fn starts_map(prev: Option<&TokenType>) -> bool {
+    match prev {
+        None | Some(&RightParen) | Some(&Arrow) | Some(&Else) | Some(&Try) | Some(&Finally) |
+        Some(&Semicolon) | Some(&LeftBrace) | Some(&RightBrace) | Some(&Comment) => false,
+        _ => true,
+    }
+}
+
+fn needs_space(state: &State, token: &Token, map_brace: bool) -> bool {
+    let prev = match state.prev_type() {
+        Some(prev) => prev,
+        None => return false,
+    };
+
+    match (prev, &token.token_type) {
+        (_, &Comment) => true,
+        (&LeftBrace, _) if state.map_brace => false,
+        (_, &RightBrace) if map_brace => false,
+        (_, &Colon) => state.questions.last().map_or(false, |&n| n > 0),
+        (&RightBrace, &LeftBracket) if state.map_brace => false,
+        // the `}` that ends an interpolated expression is part of the
+        // string token that follows it
+        (&Interpolation, _) | (_, &InterpolationEnd) => false,
+        (_, &Interpolation) => !token.lexeme.starts_with('}'),
+        (_, &DotDot) | (&DotDot, _) | (_, &DotDotEqual) | (&DotDotEqual, _) => false,
+        (&LeftParen, _) | (&LeftBracket, _) | (&Dot, _) | (&QuestionDot, _) => false,
+        (_, &RightParen) | (_, &RightBracket) | (_, &Semicolon) | (_, &Comma) | (_, &Dot) | (_, &QuestionDot) => false,
+        (&Identifier, &LeftParen) | (&RightParen, &LeftParen) | (&RightBracket, &LeftParen) => false,
+        (&Identifier, &LeftBracket) | (&RightParen, &LeftBracket) | (&RightBracket, &LeftBracket) => false,
+        (&Bang, _) | (&Tilde, _) | (&Ellipsis, _) => false,
+        (&Minus, _) => !state.unary_minus,
+        _ => true,
+    }
+}
+
+fn ends_operand(token_type: &TokenType) -> bool {
+    match *token_type {
+        Identifier | Number | Str | InterpolationEnd | True | False | Nil | This | RightParen | RightBracket => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(source: &str) -> String {
+        format(&String::from(source)).unwrap()
+    }
+
+    #[test]
+    fn normalises_spacing_and_indentation() {
+        assert_eq!(
+            "var a = 1 + 2 * -3;\nif (a > 2) {\n    print a;\n} else {\n    print !a;\n}\n",
+            &fmt("var a=1+2*  -3;  if(a>2){print a;}else{\n\n\nprint !a;}")
+        )
+    }
+
+    #[test]
+    fn preserves_comments_and_blank_lines() {
+        let source = "// leading\nvar a = 1; // trailing\n\n\n\n/* block */\nprint a;\n";
+        assert_eq!(
+            "// leading\nvar a = 1; // trailing\n\n/* block */\nprint a;\n",
+            &fmt(source)
+        )
+    }
+
+    #[test]
+    fn formats_for_loop_header_on_one_line() {
+        assert_eq!(
+            "for (var i = 0; i < 3; i = i + 1) {\n    print i;\n}\n",
+            &fmt("for(var i=0;i<3;i=i+1)\n{\n  print i;\n}")
+        )
+    }
+
+    #[test]
+    fn keeps_collection_literals_inline() {
+        assert_eq!(
+            "{\n    var m = {\"a\": [1, 2], 3: {}};\n    print m[\"a\"][0];\n}\n",
+            &fmt("{ var m={ \"a\" : [ 1,2 ] ,3:{ } } ; print m [\"a\"][ 0 ] ; }")
+        )
+    }
+
+    #[test]
+    fn formats_ranges_and_their_steps() {
+        assert_eq!(
+            "for (var i in 10..=-step - 1 step -2) print i;\n",
+            &fmt("for (var i in 10 ..= - step-1 step - 2) print i;")
+        )
+    }
+
+    #[test]
+    fn spaces_binary_operators_but_not_unary_ones() {
+        assert_eq!(
+            "a += ~b ** -2 % 3 << 1;\n",
+            &fmt("a+=~ b**-2%3<<1;")
+        )
+    }
+
+    #[test]
+    fn spaces_conditional_colons_but_not_map_ones() {
+        assert_eq!(
+            "var a = b ? {\"k\": c ?? 1} : d?.e;\n",
+            &fmt("var a=b?{\"k\" : c??1}:d ?. e;")
+        )
+    }
+
+    #[test]
+    fn formats_function_bodies_as_blocks_even_inside_calls() {
+        assert_eq!(
+            "f(fun (x) {\n    var y = x;\n    return y;\n}, (a) => {\n    return a;\n});\nvar g = (x) => x * 2;\n",
+            &fmt("f(fun(x){var y=x;return y;},(a)=>{return a;});var g=(x)=>x*2;")
+        )
+    }
+
+    #[test]
+    fn spaces_defaults_and_named_arguments_but_not_rest_parameters() {
+        assert_eq!(
+            "fun f(a, b = 2, ...rest) {\n    return f(a, b: 3);\n}\n",
+            &fmt("fun f(a,b=2,...rest){return f(a,b:3);}")
+        )
+    }
+
+    #[test]
+    fn keeps_interpolated_expressions_inside_their_string() {
+        assert_eq!(
+            "print \"a ${x + 1} b ${{\"k\": 1}[\"k\"]}\";\n",
+            &fmt("print  \"a ${ x+1 } b ${{\"k\":1} [\"k\"]}\";")
+        )
+    }
+
+    #[test]
+    fn is_idempotent() {
+        let once = fmt("var a=1;{var b=a-1;\n\n{print b;}}// done");
+        assert_eq!(once, fmt(&once))
+    }
+
+    #[test]
+    fn rejects_invalid_source() {
+        assert!(format(&String::from("var = ;")).is_err())
+    }
+}
@@ -1,10 +1,89 @@
-use callable::Callable;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use callable::{arguments, Callable};
+use environment::Environment;
+use list;
+use statement::{Parameter, Stmt};
 use value::Value;
 
-#[derive(PartialEq, Debug, Clone)]
+/// A function written in Lox, along with the scope it was created in, which
+/// it can go on using after that scope has ended.
+#[derive(Clone)]
 pub struct LoxFunction {
     name: String,
-    arity: u32,
+    params: Vec<Parameter>,
+    body: Rc<Vec<Stmt>>,
+    closure: Rc<RefCell<Environment>>,
+    /// The file the function was defined in, if it came from one.
+    file: Option<String>,
+}
+
+impl LoxFunction {
+    pub fn new(name: &str, params: &Vec<Parameter>, body: &Rc<Vec<Stmt>>, closure: Rc<RefCell<Environment>>, file: Option<String>) -> LoxFunction {
+        LoxFunction {
+            name: String::from(name),
+            params: params.clone(),
+            body: Rc::clone(body),
+            closure,
+            file,
+        }
+    }
+
+    pub fn params(&self) -> &Vec<Parameter> {
+        &self.params
+    }
+
+    pub fn body(&self) -> &Vec<Stmt> {
+        &self.body
+    }
+
+    pub fn closure(&self) -> Rc<RefCell<Environment>> {
+        Rc::clone(&self.closure)
+    }
+
+    pub fn file(&self) -> Option<String> {
+        self.file.clone()
+    }
+
+    /// Matches a call's arguments to the parameters, giving a value for each
+    /// parameter in order, or `None` where its default should be used.
+    pub fn bind(&self, positional: Vec<Rc<Value>>, named: Vec<(String, Rc<Value>)>) -> Result<Vec<Option<Rc<Value>>>, String> {
+        let rest = self.params.last().map_or(false, |p| p.rest);
+        let fixed = if rest { self.params.len() - 1 } else { self.params.len() };
+        if positional.len() > fixed && !rest {
+            let at_most = if self.params.iter().any(|p| p.default.is_some()) { "at most " } else { "" };
+            return Err(format!("{}() takes {}{} but got {}", self.name, at_most, arguments(fixed), positional.len()));
+        }
+
+        let mut arguments = vec![None; self.params.len()];
+        let mut positional = positional.into_iter();
+        for argument in arguments.iter_mut().take(fixed) {
+            *argument = positional.next();
+        }
+        if rest {
+            arguments[fixed] = Some(Rc::new(list::new(positional.collect())));
+        }
+
+        for (name, value) in named.into_iter() {
+            match self.params[..fixed].iter().position(|p| p.name.lexeme == name) {
+                Some(i) if arguments[i].is_some() => return Err(format!("{}() got more than one value for '{}'", self.name, name)),
+                Some(i) => arguments[i] = Some(value),
+                None => return Err(format!("{}() has no parameter named '{}'", self.name, name)),
+            }
+        }
+
+        let missing: Vec<String> = self.params.iter().zip(arguments.iter())
+            .filter(|&(p, a)| a.is_none() && p.default.is_none())
+            .map(|(p, _)| format!("'{}'", p.name.lexeme))
+            .collect();
+        match missing.len() {
+            0 => Ok(arguments),
+            1 => Err(format!("{}() is missing an argument for {}", self.name, missing[0])),
+            _ => Err(format!("{}() is missing arguments for {}", self.name, missing.join(", "))),
+        }
+    }
 }
 
 impl Callable for LoxFunction {
@@ -12,7 +91,22 @@ impl Callable for LoxFunction {
         &self.name
     }
 
-    fn call(arguments: &Vec<&Value>) -> Value {
-        unimplemented!()
+    // the arguments that have to be given; there can be more
+    fn arity(&self) -> usize {
+        self.params.iter().filter(|p| p.default.is_none() && !p.rest).count()
+    }
+}
+
+// two functions are the same if they were made by the same declaration in
+// the same scope
+impl PartialEq for LoxFunction {
+    fn eq(&self, other: &LoxFunction) -> bool {
+        Rc::ptr_eq(&self.body, &other.body) && Rc::ptr_eq(&self.closure, &other.closure)
+    }
+}
+
+impl fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LoxFunction({})", self.name)
     }
 }
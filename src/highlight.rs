@@ -0,0 +1,155 @@
+use std::error::Error;
+
+use scanner::scan;
+use token::TokenType;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Class {
+    Keyword,
+    Identifier,
+    Str,
+    Number,
+    Comment,
+    Operator,
+    /// Whitespace, and anything the scanner didn't recognise.
+    Plain,
+}
+
+impl Class {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Class::Keyword => "keyword",
+            Class::Identifier => "identifier",
+            Class::Str => "string",
+            Class::Number => "number",
+            Class::Comment => "comment",
+            Class::Operator => "operator",
+            Class::Plain => "plain",
+        }
+    }
+
+    fn ansi_color(&self) -> Option<&'static str> {
+        match *self {
+            Class::Keyword => Some("1;35"),
+            Class::Str => Some("32"),
+            Class::Number => Some("36"),
+            Class::Comment => Some("90"),
+            Class::Operator => Some("33"),
+            Class::Identifier | Class::Plain => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub class: Class,
+}
+
+fn classify_token(token_type: &TokenType) -> Class {
+    match *token_type {
+        TokenType::Identifier => Class::Identifier,
+        TokenType::Str | TokenType::Interpolation | TokenType::InterpolationEnd => Class::Str,
+        TokenType::Number => Class::Number,
+        TokenType::Comment => Class::Comment,
+        TokenType::Invalid | TokenType::Eof => Class::Plain,
+        _ => match token_type.const_lexeme() {
+            Some(lexeme) if lexeme.chars().all(|c| c.is_alphabetic()) => Class::Keyword,
+            _ => Class::Operator,
+        },
+    }
+}
+
+/// Splits the source into consecutive byte ranges, each tagged with its
+/// lexical class.  Together the spans cover the whole source, so the gaps
+/// between tokens come back as `Class::Plain`.
+pub fn classify(source: &String) -> Result<Vec<Span>, Box<Error>> {
+    let tokens = scan(source)?;
+    let mut spans = Vec::new();
+    let mut position = 0;
+    for token in tokens.iter().filter(|t| t.token_type != TokenType::Eof) {
+        if token.offset > position {
+            spans.push(Span { start: position, end: token.offset, class: Class::Plain });
+        }
+        spans.push(Span { start: token.offset, end: token.end_offset(), class: classify_token(&token.token_type) });
+        position = token.end_offset();
+    }
+    if position < source.len() {
+        spans.push(Span { start: position, end: source.len(), class: Class::Plain });
+    }
+    Ok(spans)
+}
+
+/// Renders the source with ANSI colour escapes, for display in a terminal.
+pub fn to_ansi(source: &String) -> Result<String, Box<Error>> {
+    let mut output = String::new();
+    for span in classify(source)?.iter() {
+        let text = &source[span.start..span.end];
+        match span.class.ansi_color() {
+            Some(color) => output.push_str(&format!("\x1b[{}m{}\x1b[0m", color, text)),
+            None => output.push_str(text),
+        }
+    }
+    Ok(output)
+}
+
+/// Renders the source as an HTML `<pre>` block, with each token wrapped in
+/// a `<span>` whose class is `lox-` followed by the class name, so it can
+/// be styled with CSS.
+pub fn to_html(source: &String) -> Result<String, Box<Error>> {
+    let mut output = String::from("<pre class=\"lox\">");
+    for span in classify(source)?.iter() {
+        let text = escape_html(&source[span.start..span.end]);
+        match span.class {
+            Class::Plain => output.push_str(&text),
+            class => output.push_str(&format!("<span class=\"lox-{}\">{}</span>", class.name(), text)),
+        }
+    }
+    output.push_str("</pre>");
+    Ok(output)
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_every_byte() {
+        let source = String::from("var s = \"hé\"; // hi\nprint s<1;");
+        let spans = classify(&source).unwrap();
+        let classes: Vec<Class> = spans.iter().filter(|s| s.class != Class::Plain).map(|s| s.class).collect();
+        assert_eq!(
+            vec![Class::Keyword, Class::Identifier, Class::Operator, Class::Str, Class::Operator, Class::Comment,
+                 Class::Keyword, Class::Identifier, Class::Operator, Class::Number, Class::Operator],
+            classes
+        );
+        for pair in spans.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+        assert_eq!(0, spans[0].start);
+        assert_eq!(source.len(), spans.last().unwrap().end);
+    }
+
+    #[test]
+    fn renders_html() {
+        assert_eq!(
+            "<pre class=\"lox\"><span class=\"lox-keyword\">print</span> <span class=\"lox-number\">1</span> <span class=\"lox-operator\">&lt;</span> <span class=\"lox-identifier\">a</span><span class=\"lox-operator\">;</span></pre>",
+            &to_html(&String::from("print 1 < a;")).unwrap()
+        )
+    }
+}
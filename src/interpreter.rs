@@ -1,40 +1,228 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
 use std::error::Error;
 use std::fmt;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::thread;
 
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+use callable::{Callable, LoxCallable};
 use environment::Environment;
 use expression::Expr;
-use statement::Stmt;
+use function::LoxFunction;
+use iterator::{iterate, Iteration};
+use list;
+use map;
+use map::{Key, LoxMap};
+use module::{Loader, Module};
+use number;
+use number::Kind;
+use parser::{parse, ParseError};
+use range;
+use range::Range;
+use scanner::{scan, ScanError};
+use string;
+use statement::{Parameter, Stmt};
 use token::{TokenType, Token};
 use value::Value;
 
-struct State {
+/// How deep calls can nest before the program is stopped with a stack
+/// overflow, rather than overflowing the interpreter's own stack.
+const MAX_FRAMES: usize = 10000;
+
+/// The native stack the interpreter should be run with, which leaves room
+/// for `MAX_FRAMES` calls even in a debug build, where each takes about
+/// 10KB.
+pub const STACK_SIZE: usize = 512 * 1024 * 1024;
+
+// the stack assumed for threads not started by `spawn_with_stack()`, which
+// is the smallest Rust gives a thread by default
+const DEFAULT_STACK_SIZE: usize = 2 * 1024 * 1024;
+
+// how much stack a call has to leave free, for whatever runs between one
+// call and the next
+const STACK_RESERVE: usize = 256 * 1024;
+
+thread_local! {
+    // the address the current thread's stack starts near, and its size, or
+    // zeros until the interpreter first runs on the thread
+    static STACK: Cell<(usize, usize)> = Cell::new((0, 0));
+}
+
+/// Runs `f` on a new thread with a `STACK_SIZE` stack, where programs can
+/// nest calls `MAX_FRAMES` deep.
+pub fn spawn_with_stack<F, T>(f: F) -> io::Result<thread::JoinHandle<T>>
+    where F: FnOnce() -> T + Send + 'static, T: Send + 'static
+{
+    thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+        STACK.with(|stack| stack.set((stack_address(), STACK_SIZE)));
+        f()
+    })
+}
+
+fn stack_address() -> usize {
+    let marker = 0u8;
+    &marker as *const u8 as usize
+}
+
+// on a thread the interpreter hasn't run on before, the stack is taken to
+// start here, which is as shallow as the interpreter gets
+fn note_stack_start() {
+    STACK.with(|stack| if stack.get().0 == 0 {
+        stack.set((stack_address(), DEFAULT_STACK_SIZE));
+    });
+}
+
+fn stack_remaining() -> usize {
+    let (start, size) = STACK.with(|stack| stack.get());
+    // stacks grow down on every platform Rust supports
+    size.saturating_sub(start.saturating_sub(stack_address()))
+}
+
+/// One entry in the interpreter's call stack.
+#[derive(Clone)]
+pub struct CallFrame {
+    pub function: String,
+    /// The file the function was defined in, if it came from one.
+    pub file: Option<String>,
+    /// The line currently executing in this frame (for callers, the line
+    /// of the call).
+    pub line: u32,
+    /// The innermost scope active in this frame.
+    pub environment: Rc<RefCell<Environment>>,
+}
+
+impl CallFrame {
+    fn new(function: &str, file: Option<String>, environment: Rc<RefCell<Environment>>) -> CallFrame {
+        CallFrame {
+            function: String::from(function),
+            file,
+            line: 0,
+            environment,
+        }
+    }
+}
+
+/// Receives control before every statement (other than blocks) runs, with
+/// the current call stack, innermost frame last.  Returning an error aborts
+/// the program with that error.
+pub trait Debugger {
+    fn on_statement(&mut self, line: u32, frames: &[CallFrame]) -> Result<(), Box<Error>>;
+
+    /// Receives the output of `print` statements while debugging, so that
+    /// front ends that use stdout themselves can redirect it.
+    fn on_output(&mut self, text: &str) -> Result<(), Box<Error>> {
+        print!("{}", text);
+        Ok(())
+    }
+}
+
+struct State<'a> {
     environment: Rc<RefCell<Environment>>,
+    frames: Vec<CallFrame>,
+    debugger: Option<&'a mut Debugger>,
+    loader: Rc<RefCell<Loader>>,
 }
 
-impl State {
-    fn new(environment: Rc<RefCell<Environment>>) -> State {
+impl<'a> State<'a> {
+    fn new(environment: Rc<RefCell<Environment>>, file: Option<String>, debugger: Option<&'a mut Debugger>) -> State<'a> {
+        State::with_loader(environment, file, debugger, Rc::new(RefCell::new(Loader::new(Vec::new()))))
+    }
+
+    fn with_loader(environment: Rc<RefCell<Environment>>, file: Option<String>, debugger: Option<&'a mut Debugger>, loader: Rc<RefCell<Loader>>) -> State<'a> {
+        note_stack_start();
         State {
+            frames: vec![CallFrame::new("script", file, Rc::clone(&environment))],
             environment,
+            debugger,
+            loader,
+        }
+    }
+}
+
+/// One line of a runtime error's stack trace, standing for `repeated`
+/// more identical frames below it too, as deep recursion would otherwise
+/// print thousands of the same line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceFrame {
+    pub function: String,
+    pub file: Option<String>,
+    pub line: u32,
+    pub repeated: usize,
+}
+
+impl fmt::Display for TraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[line {}] in ", self.line)?;
+        match self.function.as_str() {
+            "script" => write!(f, "script")?,
+            name => write!(f, "{}()", name)?,
+        }
+        if let Some(ref file) = self.file {
+            write!(f, " ({})", file)?;
+        }
+        match self.repeated {
+            0 => Ok(()),
+            repeated => write!(f, " [repeated {} more times]", repeated),
         }
     }
 }
 
+/// An exception, either raised by the interpreter or thrown by the program.
+/// Unless it's caught by a `try` statement, it aborts the program.
 #[derive(Debug)]
-struct RuntimeError {
-    location: Token,
+pub struct RuntimeError {
+    line: u32,
+    value: Rc<Value>,
     description: String,
+    trace: Vec<TraceFrame>,
 }
 
 impl RuntimeError {
     pub fn new(location: &Token, message: String) -> Box<RuntimeError> {
         let description = format!("ERR:{}:{}", location.line, message);
         Box::new(RuntimeError {
-            location: location.clone(),
+            line: location.line,
+            value: Rc::new(Value::Error { message, line: location.line }),
+            description,
+            trace: Vec::new(),
+        })
+    }
+
+    /// An exception raised by a `throw` statement.
+    pub fn thrown(line: u32, value: Rc<Value>) -> Box<RuntimeError> {
+        let description = format!("ERR:{}:Uncaught exception: {}", line, value);
+        Box::new(RuntimeError {
+            line,
+            value,
             description,
+            trace: Vec::new(),
         })
     }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// The value a `catch` block receives: whatever was thrown, or an error
+    /// value for errors raised by the interpreter itself.
+    pub fn value(&self) -> Rc<Value> {
+        Rc::clone(&self.value)
+    }
+
+    /// The call stack at the point the error was raised, innermost frame
+    /// first.
+    pub fn trace(&self) -> &[TraceFrame] {
+        &self.trace
+    }
 }
 
 impl fmt::Display for RuntimeError {
@@ -49,39 +237,162 @@ impl Error for RuntimeError {
     }
 }
 
+/// Carries the value of a `return` statement out to the function call.  It
+/// isn't an exception, so `catch` blocks let it through.
+#[derive(Debug)]
+struct Return {
+    keyword: Token,
+    value: Rc<Value>,
+}
+
+impl fmt::Display for Return {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl Error for Return {
+    fn description(&self) -> &str {
+        "return"
+    }
+}
+
+/// Runs a program.  Each call it makes nests on the native stack, so run
+/// this (and the other `interpret` functions) on a thread made by
+/// `spawn_with_stack()` to let calls nest `MAX_FRAMES` deep; on other
+/// threads, which are assumed to have Rust's default stack, deep calls are
+/// stopped sooner with a stack overflow error.
 pub fn interpret(environment: Rc<RefCell<Environment>>, statements: Vec<Stmt>) -> Result<(), Box<Error>> {
-    let mut state = State::new(Rc::clone(&environment));
-    let mut iter = statements.into_iter();
-    loop {
-        match iter.next() {
-            Some(ref stmt) => execute_stmt(&mut state, stmt)?,
-            None => break,
-        }
+    let mut state = State::new(environment, None, None);
+    execute_program(&mut state, &statements)
+}
+
+/// Like `interpret()`, but names the file the statements came from in the
+/// stack traces of runtime errors.
+pub fn interpret_file(environment: Rc<RefCell<Environment>>, statements: Vec<Stmt>, filename: &str) -> Result<(), Box<Error>> {
+    let mut state = State::new(environment, Some(String::from(filename)), None);
+    execute_program(&mut state, &statements)
+}
+
+/// Like `interpret_with_loader()`, but hands control to the debugger before
+/// each statement.  As with `interpret()`, deep calls need a thread made by
+/// `spawn_with_stack()`.
+pub fn interpret_with_debugger(environment: Rc<RefCell<Environment>>, statements: Vec<Stmt>, filename: Option<&str>, loader: Rc<RefCell<Loader>>, debugger: &mut Debugger) -> Result<(), Box<Error>> {
+    execute_module(environment, &statements, filename, loader, Some(debugger))
+}
+
+/// Like `interpret_file()`, but imports modules with the given loader, which
+/// can be shared between runs so that modules are only loaded once.  The
+/// file is loaded as a module itself, so a module that imports it back is
+/// reported as a cycle rather than running it a second time.
+pub fn interpret_with_loader(environment: Rc<RefCell<Environment>>, statements: Vec<Stmt>, filename: Option<&str>, loader: Rc<RefCell<Loader>>) -> Result<(), Box<Error>> {
+    execute_module(environment, &statements, filename, loader, None)
+}
+
+fn execute_module(environment: Rc<RefCell<Environment>>, statements: &Vec<Stmt>, filename: Option<&str>, loader: Rc<RefCell<Loader>>, debugger: Option<&mut Debugger>) -> Result<(), Box<Error>> {
+    let path = filename.and_then(|file| Path::new(file).canonicalize().ok());
+    if let Some(ref path) = path {
+        loader.borrow_mut().begin(path)?;
+    }
+    let result = {
+        let mut state = State::with_loader(Rc::clone(&environment), filename.map(String::from), debugger, Rc::clone(&loader));
+        execute_program(&mut state, statements)
+    };
+    if let Some(ref path) = path {
+        let module = result.as_ref().ok().map(|_| Rc::new(Module::new(path.display().to_string(), environment, statements)));
+        loader.borrow_mut().finish(path, module);
+    }
+    result
+}
+
+/// Evaluates a single expression in the given environment.
+pub fn evaluate(environment: Rc<RefCell<Environment>>, expr: &Expr) -> Result<Rc<Value>, Box<Error>> {
+    let mut state = State::new(environment, None, None);
+    evaluate_expression(&mut state, expr)
+}
+
+// a `return` outside of any function has nowhere to go
+fn execute_program(state: &mut State, statements: &Vec<Stmt>) -> Result<(), Box<Error>> {
+    execute_stmts(state, statements).map_err(|error| match error.downcast::<Return>() {
+        Ok(ret) => RuntimeError::new(&ret.keyword, String::from("Can't return from outside a function")) as Box<Error>,
+        Err(error) => error,
+    })
+}
+
+fn execute_stmts(state: &mut State, statements: &Vec<Stmt>) -> Result<(), Box<Error>> {
+    for statement in statements.iter() {
+        execute_stmt(state, statement)?;
     }
     Ok(())
 }
 
 fn execute_stmt(state: &mut State, stmt: &Stmt) -> Result<(), Box<Error>> {
     match stmt {
-        &Stmt::Block { ref statements } => execute_block(state, statements),
-        &Stmt::Expression { ref expression } => execute_expression_stmt(state, expression),
-        &Stmt::For { ref initializer, ref condition, ref increment, ref body } => execute_for_stmt(state, initializer, condition, increment, body),
-        &Stmt::If { ref expression, ref then_branch, ref else_branch } => execute_if_stmt(state, expression, then_branch, else_branch),
-        &Stmt::Print { ref expression } => execute_print_stmt(state, expression),
+        &Stmt::Block { .. } => (),
+        _ => enter_line(state, stmt.line())?,
+    }
+    let result = match stmt {
+        &Stmt::Block { ref statements, .. } => execute_block(state, statements),
+        &Stmt::Expression { ref expression, .. } => execute_expression_stmt(state, expression),
+        &Stmt::For { ref initializer, ref condition, ref increment, ref body, .. } => execute_for_stmt(state, initializer, condition, increment, body),
+        &Stmt::ForIn { ref name, ref iterable, ref body, .. } => execute_for_in_stmt(state, name, iterable, body),
+        &Stmt::Function { ref name, ref params, ref body } => execute_function_stmt(state, name, params, body),
+        &Stmt::If { ref expression, ref then_branch, ref else_branch, .. } => execute_if_stmt(state, expression, then_branch, else_branch),
+        &Stmt::Import { ref keyword, ref path, ref alias, ref names } => execute_import_stmt(state, keyword, path, alias, names),
+        &Stmt::Export { ref declaration, .. } => execute_stmt(state, declaration),
+        &Stmt::Print { ref expression, .. } => execute_print_stmt(state, expression),
+        &Stmt::Return { ref keyword, ref value } => execute_return_stmt(state, keyword, value),
+        &Stmt::Throw { ref expression, line } => execute_throw_stmt(state, expression, line),
+        &Stmt::Try { ref body, ref catch_branch, ref finally_branch, .. } => execute_try_stmt(state, body, catch_branch, finally_branch),
         &Stmt::Var { ref name, ref initializer } => execute_var_stmt(state, name, initializer),
+    };
+    result.map_err(|error| attach_trace(state, error))
+}
+
+/// Records the current call stack on a runtime error the first time it
+/// passes through a statement, which is while the frame that raised it is
+/// still on the stack.
+fn attach_trace(state: &State, mut error: Box<Error>) -> Box<Error> {
+    if let Some(runtime_error) = error.downcast_mut::<RuntimeError>() {
+        if runtime_error.trace.is_empty() {
+            let line = runtime_error.line;
+            for (i, frame) in state.frames.iter().rev().enumerate() {
+                let line = if i == 0 { line } else { frame.line };
+                if let Some(last) = runtime_error.trace.last_mut() {
+                    if last.line == line && last.function == frame.function && last.file == frame.file {
+                        last.repeated += 1;
+                        continue;
+                    }
+                }
+                runtime_error.trace.push(TraceFrame {
+                    function: frame.function.clone(),
+                    file: frame.file.clone(),
+                    line,
+                    repeated: 0,
+                });
+            }
+        }
+    }
+    error
+}
+
+fn enter_line(state: &mut State, line: u32) -> Result<(), Box<Error>> {
+    if let Some(frame) = state.frames.last_mut() {
+        frame.line = line;
+        frame.environment = Rc::clone(&state.environment);
+    }
+    match state.debugger {
+        Some(ref mut debugger) => debugger.on_statement(line, &state.frames),
+        None => Ok(()),
     }
 }
 
 fn execute_block(state: &mut State, statements: &Vec<Stmt>) -> Result<(), Box<Error>> {
     let block_environment = Environment::new_enclosing(Some(Rc::clone(&state.environment)));
-    let mut block_state = State::new(Rc::new(RefCell::new(block_environment)));
-    for statement in statements.iter() {
-        match execute_stmt(&mut block_state, statement) {
-            Ok(_) => (),
-            Err(error) => return Err(error),
-        }
-    }
-    Ok(())
+    let enclosing = mem::replace(&mut state.environment, Rc::new(RefCell::new(block_environment)));
+    let result = execute_stmts(state, statements);
+    state.environment = enclosing;
+    result
 }
 
 fn execute_expression_stmt(state: &mut State, expr: &Expr) -> Result<(), Box<Error>> {
@@ -108,6 +419,53 @@ fn execute_for_stmt(state: &mut State, initializer: &Option<Box<Stmt>>, conditio
     Ok(())
 }
 
+fn execute_for_in_stmt(state: &mut State, name: &Token, iterable: &Expr, body: &Stmt) -> Result<(), Box<Error>> {
+    let iterable_value = evaluate_expression(state, iterable)?;
+    let mut iteration = iterate(&iterable_value).map_err(|message| RuntimeError::new(name, message) as Box<Error>)?;
+    if let Iteration::Iterator(iterator) = iteration {
+        let iterator_value = call_value(state, iterator, name, Vec::new(), Vec::new())?;
+        iteration = match iterate(&iterator_value).map_err(|message| RuntimeError::new(name, message) as Box<Error>)? {
+            Iteration::Iterator(_) => return Err(RuntimeError::new(name, format!("iterator() gave {}, which has an iterator() of its own", iterator_value))),
+            iteration => iteration,
+        };
+    }
+    loop {
+        let element = match iteration {
+            Iteration::Values(ref mut values) => match values.next() {
+                Some(element) => element,
+                None => break,
+            },
+            Iteration::Calls(ref next) => match call_value(state, Rc::clone(next), name, Vec::new(), Vec::new())? {
+                ref element if **element == Value::Nil => break,
+                element => element,
+            },
+            Iteration::Iterator(_) => unreachable!(),
+        };
+        // each time round the loop gets a fresh variable
+        let mut loop_environment = Environment::new_enclosing(Some(Rc::clone(&state.environment)));
+        loop_environment.define(name.lexeme.clone(), element);
+        let enclosing = mem::replace(&mut state.environment, Rc::new(RefCell::new(loop_environment)));
+        let result = execute_stmt(state, body);
+        state.environment = enclosing;
+        result?;
+    }
+    Ok(())
+}
+
+fn execute_function_stmt(state: &mut State, name: &Token, params: &Vec<Parameter>, body: &Rc<Vec<Stmt>>) -> Result<(), Box<Error>> {
+    let function = make_function(state, &name.lexeme, params, body);
+    state.environment.borrow_mut().define(name.lexeme.clone(), function);
+    Ok(())
+}
+
+// closes over the current scope, so a function declared in a block or
+// another function can still see that scope's variables after it ends
+fn make_function(state: &State, name: &str, params: &Vec<Parameter>, body: &Rc<Vec<Stmt>>) -> Rc<Value> {
+    let file = state.frames.last().and_then(|frame| frame.file.clone());
+    let function = LoxFunction::new(name, params, body, Rc::clone(&state.environment), file);
+    Rc::new(Value::Callable(LoxCallable::Function(function)))
+}
+
 fn execute_if_stmt(state: &mut State, expr: &Expr, then_branch: &Box<Stmt>, else_branch: &Option<Box<Stmt>>) -> Result<(), Box<Error>> {
     match evaluate_expression(state, expr) {
         Ok(value) => 
@@ -123,11 +481,112 @@ fn execute_if_stmt(state: &mut State, expr: &Expr, then_branch: &Box<Stmt>, else
     }
 }
 
+fn execute_import_stmt(state: &mut State, keyword: &Token, path: &str, alias: &Option<Token>, names: &Vec<(Token, Token)>) -> Result<(), Box<Error>> {
+    let module = import_module(state, keyword, path)?;
+    if let &Some(ref alias) = alias {
+        state.environment.borrow_mut().define(alias.lexeme.clone(), Rc::new(Value::Module(Rc::clone(&module))));
+    }
+    for &(ref name, ref local) in names.iter() {
+        match module.get(&name.lexeme) {
+            Some(value) => state.environment.borrow_mut().define(local.lexeme.clone(), value),
+            None => return Err(RuntimeError::new(name, format!("Module '{}' doesn't export '{}'", module.path(), name.lexeme))),
+        }
+    }
+    Ok(())
+}
+
+// modules are found relative to the file of the code doing the importing
+fn import_module(state: &mut State, keyword: &Token, path: &str) -> Result<Rc<Module>, Box<Error>> {
+    if let Some(module) = state.loader.borrow_mut().native(path) {
+        return Ok(module);
+    }
+    let importer = state.frames.last().and_then(|frame| frame.file.clone());
+    let resolved = state.loader.borrow().resolve(path, importer.as_ref().map(|f| f.as_str()))
+        .map_err(|message| RuntimeError::new(keyword, message) as Box<Error>)?;
+    if let Some(module) = state.loader.borrow().get(&resolved) {
+        return Ok(module);
+    }
+    state.loader.borrow_mut().begin(&resolved).map_err(|message| RuntimeError::new(keyword, message) as Box<Error>)?;
+    let module = run_module(state, keyword, &resolved);
+    state.loader.borrow_mut().finish(&resolved, module.as_ref().ok().cloned());
+    module
+}
+
+// a module runs in a fresh environment of its own, in a frame of its own
+// so that errors in it are traced to its file
+fn run_module(state: &mut State, keyword: &Token, path: &PathBuf) -> Result<Rc<Module>, Box<Error>> {
+    let filename = path.display().to_string();
+    let source = fs::read_to_string(path)
+        .map_err(|error| RuntimeError::new(keyword, format!("Can't read module '{}': {}", filename, error)) as Box<Error>)?;
+    let statements = scan(&source).map_err(|error| vec![error]).and_then(parse).map_err(|errors| {
+        let error = &errors[0];
+        let (line, message) = match (error.downcast_ref::<ScanError>(), error.downcast_ref::<ParseError>()) {
+            (Some(e), _) => (e.line(), String::from(e.message())),
+            (_, Some(e)) => (e.line(), String::from(e.message())),
+            _ => (0, error.to_string()),
+        };
+        RuntimeError::new(keyword, format!("Syntax error in module '{}' on line {}: {}", filename, line, message)) as Box<Error>
+    })?;
+
+    let environment = Rc::new(RefCell::new(Environment::new()));
+    state.frames.push(CallFrame::new("script", Some(filename.clone()), Rc::clone(&environment)));
+    let enclosing = mem::replace(&mut state.environment, Rc::clone(&environment));
+    let result = execute_program(state, &statements);
+    state.environment = enclosing;
+    state.frames.pop();
+    result.map(|_| Rc::new(Module::new(filename, environment, &statements)))
+}
+
 fn execute_print_stmt(state: &mut State, expr: &Expr) -> Result<(), Box<Error>> {
-    evaluate_expression(state, expr).map(|ref value| {
-        println!("{}", value.to_string());
-        ()
-    })
+    let value = evaluate_expression(state, expr)?;
+    match state.debugger {
+        Some(ref mut debugger) => debugger.on_output(&format!("{}\n", value)),
+        None => {
+            println!("{}", value.to_string());
+            Ok(())
+        },
+    }
+}
+
+fn execute_return_stmt(state: &mut State, keyword: &Token, value: &Option<Expr>) -> Result<(), Box<Error>> {
+    let value = match value {
+        &Some(ref v) => evaluate_expression(state, v)?,
+        &None => Rc::new(Value::Nil),
+    };
+    Err(Box::new(Return { keyword: keyword.clone(), value }))
+}
+
+fn execute_throw_stmt(state: &mut State, expr: &Expr, line: u32) -> Result<(), Box<Error>> {
+    let value = evaluate_expression(state, expr)?;
+    Err(RuntimeError::thrown(line, value))
+}
+
+fn execute_try_stmt(state: &mut State, body: &Stmt, catch_branch: &Option<(Token, Box<Stmt>)>, finally_branch: &Option<Box<Stmt>>) -> Result<(), Box<Error>> {
+    let mut result = execute_stmt(state, body);
+    if let &Some((ref name, ref catch_body)) = catch_branch {
+        // only exceptions can be caught; other errors (such as the debugger
+        // ending the session) always abort the program
+        let caught = match result {
+            Err(ref error) => error.downcast_ref::<RuntimeError>().map(|e| e.value()),
+            Ok(_) => None,
+        };
+        if let Some(value) = caught {
+            result = execute_catch(state, name, value, catch_body);
+        }
+    }
+    if let &Some(ref fb) = finally_branch {
+        execute_stmt(state, fb)?;
+    }
+    result
+}
+
+fn execute_catch(state: &mut State, name: &Token, value: Rc<Value>, body: &Stmt) -> Result<(), Box<Error>> {
+    let mut catch_environment = Environment::new_enclosing(Some(Rc::clone(&state.environment)));
+    catch_environment.define(name.lexeme.clone(), value);
+    let enclosing = mem::replace(&mut state.environment, Rc::new(RefCell::new(catch_environment)));
+    let result = execute_stmt(state, body);
+    state.environment = enclosing;
+    result
 }
 
 fn execute_var_stmt(state: &mut State, name: &Token, initializer: &Option<Expr>) -> Result<(), Box<Error>> {
@@ -144,9 +603,23 @@ fn evaluate_expression(state: &mut State, expr: &Expr) -> Result<Rc<Value>, Box<
     match expr {
         &Expr::Assign { ref name, ref value } => evaluate_assign(state, name, &**value),
         &Expr::Binary { ref left, ref operator, ref right } => evaluate_binary(state, &**left, operator, &**right),
+        &Expr::Call { .. } | &Expr::Get { .. } | &Expr::Index { .. } => {
+            evaluate_chain(state, expr).map(|value| value.unwrap_or_else(|| Rc::new(Value::Nil)))
+        },
+        &Expr::Conditional { ref condition, ref then_branch, ref else_branch, .. } => {
+            evaluate_conditional(state, &**condition, &**then_branch, &**else_branch)
+        },
         &Expr::Grouping { ref expression } => evaluate_grouping(state, &**expression),
+        &Expr::Interpolation { ref parts } => evaluate_interpolation(state, parts),
+        &Expr::Lambda { ref params, ref body, .. } => Ok(make_function(state, "lambda", params, body)),
+        &Expr::List { ref elements } => evaluate_list(state, elements),
         &Expr::Literal { ref value } => evaluate_literal(state, Rc::clone(value)),
         &Expr::Logical { ref left, ref operator, ref right } => evaluate_logical(state, &**left, operator, &**right),
+        &Expr::Map { ref brace, ref entries } => evaluate_map(state, brace, entries),
+        &Expr::Range { ref start, ref operator, ref end, ref step } => evaluate_range(state, &**start, operator, &**end, step),
+        &Expr::SetIndex { ref object, ref bracket, ref index, ref operator, ref value } => {
+            evaluate_set_index(state, &**object, bracket, &**index, operator, &**value)
+        },
         &Expr::Unary { ref operator, ref right } => evaluate_unary(state, operator, &**right),
         &Expr::Variable { ref name } => match state.environment.borrow().get(name) {
             Some(ref value) => Ok(Rc::clone(value)),
@@ -173,7 +646,12 @@ fn evaluate_binary(state: &mut State, left: &Expr, operator: &Token, right: &Exp
     let left_value = evaluate_expression(state, left)?;
     let right_value = evaluate_expression(state, right)?;
     match operator.token_type {
-        TokenType::Minus | TokenType::Plus | TokenType::Slash | TokenType::Star => arithmetic(&left_value, operator, &right_value).map(|v| Rc::new(v)),
+        TokenType::Minus | TokenType::Plus | TokenType::Slash | TokenType::Star | TokenType::Percent | TokenType::StarStar => {
+            arithmetic(&left_value, operator, &right_value).map(|v| Rc::new(v))
+        },
+        TokenType::Ampersand | TokenType::Pipe | TokenType::Caret | TokenType::LessLess | TokenType::GreaterGreater => {
+            bitwise(&left_value, operator, &right_value).map(|v| Rc::new(v))
+        },
         TokenType::Less | TokenType::LessEqual | TokenType::Greater | TokenType::GreaterEqual => compare(&left_value, operator, &right_value).map(|v| Rc::new(v)),
         TokenType::EqualEqual => Ok(Rc::new(Value::Boolean(is_equal(left_value, right_value)))),
         TokenType::BangEqual => Ok(Rc::new(Value::Boolean(!is_equal(left_value, right_value)))),
@@ -186,14 +664,16 @@ fn evaluate_binary(state: &mut State, left: &Expr, operator: &Token, right: &Exp
 
 fn arithmetic(left: &Value, operator: &Token, right: &Value) -> Result<Value, Box<Error>> {
     match (left, right) {
-        (&Value::Number(l), &Value::Number(r)) => match operator.token_type {
-            TokenType::Minus => Ok(Value::Number(l - r)),
-            TokenType::Plus => Ok(Value::Number(l + r)),
-            TokenType::Slash if r == 0.0 => Err(RuntimeError::new(operator, String::from("Can't divide by zero"))),
-            TokenType::Slash => Ok(Value::Number(l / r)),
-            TokenType::Star => Ok(Value::Number(l * r)),
+        (&Value::Integer(l), &Value::Integer(r)) => integer_arithmetic(l, operator, r),
+        _ if number::kind(left).is_some() && number::kind(right).is_some() => number_arithmetic(left, operator, right),
+        (&Value::List(ref l), &Value::List(ref r)) => match operator.token_type {
+            TokenType::Plus => {
+                let mut elements = l.borrow().clone();
+                elements.extend(r.borrow().iter().cloned());
+                Ok(list::new(elements))
+            },
             _ => {
-                let description = format!("Operator '{}' is not valid for arithmetic", operator.token_type);
+                let description = format!("Operator '{}' is not valid for lists", operator.token_type);
                 Err(RuntimeError::new(operator, description))
             },
         },
@@ -215,22 +695,359 @@ fn arithmetic(left: &Value, operator: &Token, right: &Value) -> Result<Value, Bo
     }
 }
 
+// the result is in the wider of the two kinds of number, except that a
+// decimal and a float can't be mixed, since that would lose exactness
+fn number_arithmetic(left: &Value, operator: &Token, right: &Value) -> Result<Value, Box<Error>> {
+    match (number::kind(left), number::kind(right)) {
+        (Some(Kind::Decimal), Some(Kind::Float)) | (Some(Kind::Float), Some(Kind::Decimal)) => {
+            Err(RuntimeError::new(operator, format!("Can't mix a decimal and a float in {} {} {}", number::literal(left), operator.lexeme, number::literal(right))))
+        },
+        (Some(l), Some(r)) if l.max(r) == Kind::Float => float_arithmetic(left.as_float().unwrap(), operator, right.as_float().unwrap()),
+        (Some(l), Some(r)) if l.max(r) == Kind::Decimal => {
+            decimal_arithmetic(number::to_decimal(left).unwrap(), operator, number::to_decimal(right).unwrap())
+        },
+        _ => bigint_arithmetic(number::to_bigint(left).unwrap(), operator, number::to_bigint(right).unwrap()),
+    }
+}
+
+// integer division rounds down, towards negative infinity, rather than
+// towards zero, and so the result of `%` has the same sign as the divisor;
+// overflowing carries on with big integers
+fn integer_arithmetic(l: i64, operator: &Token, r: i64) -> Result<Value, Box<Error>> {
+    let result = match operator.token_type {
+        TokenType::Minus => l.checked_sub(r),
+        TokenType::Plus => l.checked_add(r),
+        TokenType::Slash | TokenType::Percent if r == 0 => return Err(RuntimeError::new(operator, String::from("Can't divide by zero"))),
+        TokenType::Slash => l.checked_div(r).map(|q| if l % r != 0 && (l < 0) != (r < 0) { q - 1 } else { q }),
+        TokenType::Percent => l.checked_rem(r).map(|m| if m != 0 && (m < 0) != (r < 0) { m + r } else { m }),
+        TokenType::Star => l.checked_mul(r),
+        TokenType::StarStar if l == 0 && r < 0 => return Err(zero_to_negative_power(operator)),
+        // a negative power isn't a whole number, so it's a float
+        TokenType::StarStar if r < 0 => return Ok(Value::Number((l as f64).powf(r as f64))),
+        TokenType::StarStar if r > u32::max_value() as i64 => None,
+        TokenType::StarStar => l.checked_pow(r as u32),
+        _ => return Err(invalid_arithmetic(operator)),
+    };
+    match result {
+        Some(n) => Ok(Value::Integer(n)),
+        None => bigint_arithmetic(BigInt::from(l), operator, BigInt::from(r)),
+    }
+}
+
+fn bigint_arithmetic(l: BigInt, operator: &Token, r: BigInt) -> Result<Value, Box<Error>> {
+    match operator.token_type {
+        TokenType::Minus => Ok(number::integer(l - r)),
+        TokenType::Plus => Ok(number::integer(l + r)),
+        TokenType::Slash | TokenType::Percent if r.is_zero() => Err(RuntimeError::new(operator, String::from("Can't divide by zero"))),
+        TokenType::Slash => Ok(number::integer(l.div_floor(&r))),
+        TokenType::Percent => Ok(number::integer(l.mod_floor(&r))),
+        TokenType::Star => Ok(number::integer(l * r)),
+        TokenType::StarStar if l.is_zero() && r.is_negative() => Err(zero_to_negative_power(operator)),
+        TokenType::StarStar if r.is_negative() => Ok(Value::Number(l.to_f64().unwrap().powf(r.to_f64().unwrap()))),
+        TokenType::StarStar => match r.to_u32() {
+            Some(exponent) => number::pow(&l, exponent).map(number::integer).map_err(|message| RuntimeError::new(operator, message) as Box<Error>),
+            None => Err(RuntimeError::new(operator, format!("Exponent {} is too large", r))),
+        },
+        _ => Err(invalid_arithmetic(operator)),
+    }
+}
+
+fn decimal_arithmetic(l: BigDecimal, operator: &Token, r: BigDecimal) -> Result<Value, Box<Error>> {
+    match operator.token_type {
+        TokenType::Minus => Ok(Value::Decimal(l - r)),
+        TokenType::Plus => Ok(Value::Decimal(l + r)),
+        TokenType::Slash | TokenType::Percent if r.is_zero() => Err(RuntimeError::new(operator, String::from("Can't divide by zero"))),
+        TokenType::Slash => Ok(Value::Decimal(l / r)),
+        TokenType::Percent => {
+            let m = &l % &r;
+            Ok(Value::Decimal(if !m.is_zero() && m.is_negative() != r.is_negative() { m + r } else { m }))
+        },
+        TokenType::Star => Ok(Value::Decimal(l * r)),
+        TokenType::StarStar if !r.is_integer() => Err(RuntimeError::new(operator, format!("Can't raise a decimal to the power {}d", r))),
+        TokenType::StarStar if l.is_zero() && r.is_negative() => Err(zero_to_negative_power(operator)),
+        TokenType::StarStar => match r.to_i64() {
+            Some(exponent) => number::decimal_pow(&l, exponent).map(Value::Decimal).map_err(|message| RuntimeError::new(operator, message) as Box<Error>),
+            None => Err(RuntimeError::new(operator, format!("Exponent {}d is too large", r))),
+        },
+        _ => Err(invalid_arithmetic(operator)),
+    }
+}
+
+// which, like dividing by zero, has no answer
+fn zero_to_negative_power(operator: &Token) -> Box<Error> {
+    RuntimeError::new(operator, String::from("Can't raise zero to a negative power"))
+}
+
+fn float_arithmetic(l: f64, operator: &Token, r: f64) -> Result<Value, Box<Error>> {
+    match operator.token_type {
+        TokenType::Minus => Ok(Value::Number(l - r)),
+        TokenType::Plus => Ok(Value::Number(l + r)),
+        TokenType::Slash | TokenType::Percent if r == 0.0 => Err(RuntimeError::new(operator, String::from("Can't divide by zero"))),
+        TokenType::Slash => Ok(Value::Number(l / r)),
+        TokenType::Percent => {
+            let m = l % r;
+            Ok(Value::Number(if m != 0.0 && (m < 0.0) != (r < 0.0) { m + r } else { m }))
+        },
+        TokenType::Star => Ok(Value::Number(l * r)),
+        TokenType::StarStar if l == 0.0 && r < 0.0 => Err(zero_to_negative_power(operator)),
+        TokenType::StarStar => Ok(Value::Number(l.powf(r))),
+        _ => Err(invalid_arithmetic(operator)),
+    }
+}
+
+// bitwise operators work on integers as if they were in two's complement,
+// however big they are
+fn bitwise(left: &Value, operator: &Token, right: &Value) -> Result<Value, Box<Error>> {
+    let (l, r) = match (number::to_bigint(left), number::to_bigint(right)) {
+        (Some(l), Some(r)) => (l, r),
+        _ => return Err(RuntimeError::new(operator, format!("Operator '{}' can only be applied to integers", operator.lexeme))),
+    };
+    match operator.token_type {
+        TokenType::Ampersand => Ok(number::integer(l & r)),
+        TokenType::Pipe => Ok(number::integer(l | r)),
+        TokenType::Caret => Ok(number::integer(l ^ r)),
+        TokenType::LessLess | TokenType::GreaterGreater if r.is_negative() => Err(RuntimeError::new(operator, format!("Can't shift by {} bits", r))),
+        TokenType::LessLess => number::shift_left(&l, &r).map(number::integer).map_err(|message| RuntimeError::new(operator, message) as Box<Error>),
+        // shifting right by more bits than there are leaves only the sign
+        TokenType::GreaterGreater => match r.to_u64() {
+            Some(bits) if bits < l.bits() => Ok(number::integer(l >> bits as usize)),
+            _ => Ok(Value::Integer(if l.is_negative() { -1 } else { 0 })),
+        },
+        _ => Err(invalid_arithmetic(operator)),
+    }
+}
+
+fn invalid_arithmetic(operator: &Token) -> Box<Error> {
+    RuntimeError::new(operator, format!("Operator '{}' is not valid for arithmetic", operator.token_type))
+}
+
 fn compare(left: &Value, operator: &Token, right: &Value) -> Result<Value, Box<Error>> {
-    match (left, right) {
-        (&Value::Number(l), &Value::Number(r)) => match operator.token_type {
-            TokenType::Less => Ok(Value::Boolean(l < r)),
-            TokenType::LessEqual => Ok(Value::Boolean(l <= r)),
-            TokenType::Greater => Ok(Value::Boolean(l > r)),
-            TokenType::GreaterEqual => Ok(Value::Boolean(l >= r)),
-            _ => {
-                let description = format!("Operator '{}' is not valid for comparison", operator.token_type);
-                Err(RuntimeError::new(operator, description))
-            },
+    let ordering = match number::compare(left, right) {
+        Some(ordering) => ordering,
+        None => return Err(RuntimeError::new(operator, String::from("Cannot perform comparison on non-numeric values"))),
+    };
+    match operator.token_type {
+        TokenType::Less => Ok(Value::Boolean(ordering == Some(Ordering::Less))),
+        TokenType::LessEqual => Ok(Value::Boolean(ordering.map_or(false, |o| o != Ordering::Greater))),
+        TokenType::Greater => Ok(Value::Boolean(ordering == Some(Ordering::Greater))),
+        TokenType::GreaterEqual => Ok(Value::Boolean(ordering.map_or(false, |o| o != Ordering::Less))),
+        _ => {
+            let description = format!("Operator '{}' is not valid for comparison", operator.token_type);
+            Err(RuntimeError::new(operator, description))
+        },
+    }
+}
+
+// evaluates a chain of property accesses, calls and indexing, such as
+// `a?.b(c)[d]`; `None` means an optional access found nil, and so the rest
+// of the chain was skipped
+fn evaluate_chain(state: &mut State, expr: &Expr) -> Result<Option<Rc<Value>>, Box<Error>> {
+    match expr {
+        &Expr::Call { ref callee, ref paren, ref arguments, ref named } => match evaluate_chain(state, callee)? {
+            Some(callee_value) => evaluate_call(state, callee_value, paren, arguments, named).map(Some),
+            None => Ok(None),
+        },
+        &Expr::Get { ref object, ref name, optional } => match evaluate_chain(state, object)? {
+            Some(ref object_value) if optional && **object_value == Value::Nil => Ok(None),
+            Some(object_value) => evaluate_get(object_value, name).map(Some),
+            None => Ok(None),
+        },
+        &Expr::Index { ref object, ref bracket, ref index } => match evaluate_chain(state, object)? {
+            Some(object_value) => evaluate_index(state, object_value, bracket, index).map(Some),
+            None => Ok(None),
         },
-        _ => Err(RuntimeError::new(operator, String::from("Cannot perform comparison on non-numeric values"))),
+        _ => evaluate_expression(state, expr).map(Some),
     }
 }
 
+fn evaluate_get(object_value: Rc<Value>, name: &Token) -> Result<Rc<Value>, Box<Error>> {
+    if let Value::Module(ref module) = *object_value {
+        return module.get(&name.lexeme)
+            .ok_or(RuntimeError::new(name, format!("Module '{}' doesn't export '{}'", module.path(), name.lexeme)) as Box<Error>);
+    }
+    let property = match (&*object_value, name.lexeme.as_str()) {
+        (&Value::Error { ref message, .. }, "message") => Some(Value::Str(message.clone())),
+        (&Value::Error { line, .. }, "line") => Some(Value::Integer(line as i64)),
+        (&Value::List(_), method) => list::method(method).map(|m| Value::Callable(LoxCallable::Native(m.bind(Rc::clone(&object_value))))),
+        (&Value::Str(_), method) => string::method(method).map(|m| Value::Callable(LoxCallable::Native(m.bind(Rc::clone(&object_value))))),
+        (&Value::Range(_), method) => range::method(method).map(|m| Value::Callable(LoxCallable::Native(m.bind(Rc::clone(&object_value))))),
+        (&Value::Map(_), method) => map::method(method).map(|m| Value::Callable(LoxCallable::Native(m.bind(Rc::clone(&object_value))))),
+        _ => None,
+    };
+    match property {
+        Some(value) => Ok(Rc::new(value)),
+        None => Err(RuntimeError::new(name, format!("Undefined property '{}' on {}", name.lexeme, object_value))),
+    }
+}
+
+fn evaluate_call(state: &mut State, callee_value: Rc<Value>, paren: &Token, arguments: &Vec<Expr>, named: &Vec<(Token, Expr)>) -> Result<Rc<Value>, Box<Error>> {
+    let mut argument_values = Vec::new();
+    for argument in arguments.iter() {
+        argument_values.push(evaluate_expression(state, argument)?);
+    }
+    let mut named_values = Vec::new();
+    for &(ref name, ref argument) in named.iter() {
+        named_values.push((name.lexeme.clone(), evaluate_expression(state, argument)?));
+    }
+    call_value(state, callee_value, paren, argument_values, named_values)
+}
+
+fn call_value(state: &mut State, callee_value: Rc<Value>, paren: &Token, argument_values: Vec<Rc<Value>>, named_values: Vec<(String, Rc<Value>)>) -> Result<Rc<Value>, Box<Error>> {
+    match *callee_value {
+        Value::Callable(ref callable) => {
+            let arguments = callable.bind(argument_values, named_values)
+                .map_err(|message| RuntimeError::new(paren, message) as Box<Error>)?;
+            match *callable {
+                LoxCallable::Native(ref native) => {
+                    native.call(arguments.into_iter().filter_map(|a| a).collect())
+                        .map_err(|message| RuntimeError::new(paren, message) as Box<Error>)
+                },
+                LoxCallable::Function(ref function) => call_function(state, function, paren, arguments),
+            }
+        },
+        _ => Err(RuntimeError::new(paren, format!("Can only call functions, not {}", callee_value))),
+    }
+}
+
+fn call_function(state: &mut State, function: &LoxFunction, paren: &Token, arguments: Vec<Option<Rc<Value>>>) -> Result<Rc<Value>, Box<Error>> {
+    if state.frames.len() >= MAX_FRAMES || stack_remaining() < STACK_RESERVE {
+        return Err(RuntimeError::new(paren, String::from("Stack overflow")));
+    }
+    let call_environment = Rc::new(RefCell::new(Environment::new_enclosing(Some(function.closure()))));
+    state.frames.push(CallFrame::new(function.name(), function.file(), Rc::clone(&call_environment)));
+    let enclosing = mem::replace(&mut state.environment, call_environment);
+    // an error in a default isn't in any of the function's statements, so
+    // it has to be traced here, while the function's frame is still there
+    let result = match define_parameters(state, function, arguments) {
+        Ok(_) => execute_stmts(state, function.body()),
+        Err(error) => Err(attach_trace(state, error)),
+    };
+    state.environment = enclosing;
+    state.frames.pop();
+    match result {
+        Ok(_) => Ok(Rc::new(Value::Nil)),
+        Err(error) => error.downcast::<Return>().map(|ret| ret.value),
+    }
+}
+
+// defaults are evaluated in the function's scope as each parameter is
+// defined, so they can refer to the parameters before them
+fn define_parameters(state: &mut State, function: &LoxFunction, arguments: Vec<Option<Rc<Value>>>) -> Result<(), Box<Error>> {
+    for (param, argument) in function.params().iter().zip(arguments.into_iter()) {
+        let value = match (argument, &param.default) {
+            (Some(value), _) => value,
+            (None, &Some(ref default)) => evaluate_expression(state, default)?,
+            (None, &None) => Rc::new(Value::Nil),
+        };
+        state.environment.borrow_mut().define(param.name.lexeme.clone(), value);
+    }
+    Ok(())
+}
+
+fn evaluate_index(state: &mut State, object_value: Rc<Value>, bracket: &Token, index: &Expr) -> Result<Rc<Value>, Box<Error>> {
+    let index_value = evaluate_expression(state, index)?;
+    index_into(object_value, bracket, index_value)
+}
+
+fn index_into(object_value: Rc<Value>, bracket: &Token, index_value: Rc<Value>) -> Result<Rc<Value>, Box<Error>> {
+    match (&*object_value, &*index_value) {
+        (&Value::List(ref elements), &Value::Range(ref range)) => {
+            let elements = elements.borrow();
+            let indices = range.slice_indices(elements.len()).map_err(|message| RuntimeError::new(bracket, message) as Box<Error>)?;
+            Ok(Rc::new(list::new(indices.into_iter().map(|i| Rc::clone(&elements[i])).collect())))
+        },
+        (&Value::List(ref elements), _) => {
+            let elements = elements.borrow();
+            list::index(&index_value, elements.len())
+                .map(|i| Rc::clone(&elements[i]))
+                .map_err(|message| RuntimeError::new(bracket, message) as Box<Error>)
+        },
+        (&Value::Str(ref s), _) => {
+            let chars: Vec<char> = s.chars().collect();
+            let indices = match *index_value {
+                Value::Range(ref range) => range.slice_indices(chars.len()),
+                _ => list::index(&index_value, chars.len()).map(|i| vec![i]),
+            }.map_err(|message| RuntimeError::new(bracket, message) as Box<Error>)?;
+            Ok(Rc::new(Value::Str(indices.into_iter().map(|i| chars[i]).collect())))
+        },
+        (&Value::Map(ref map), _) => {
+            let key = Key::from_value(&index_value).map_err(|message| RuntimeError::new(bracket, message) as Box<Error>)?;
+            match map.borrow().get(&key) {
+                Some(value) => Ok(value),
+                None => Err(RuntimeError::new(bracket, format!("Key {} not found in map", key))),
+            }
+        },
+        _ => Err(RuntimeError::new(bracket, format!("Can't index into {}", object_value))),
+    }
+}
+
+// a compound assignment reads the element before evaluating the value, as
+// `xs[i] = xs[i] + v` would, but evaluates the object and index only once
+fn evaluate_set_index(state: &mut State, object: &Expr, bracket: &Token, index: &Expr, operator: &Option<Token>, value: &Expr) -> Result<Rc<Value>, Box<Error>> {
+    let object_value = evaluate_expression(state, object)?;
+    let index_value = evaluate_expression(state, index)?;
+    let value = match *operator {
+        Some(ref operator) => {
+            let current = index_into(Rc::clone(&object_value), bracket, Rc::clone(&index_value))?;
+            let value = evaluate_expression(state, value)?;
+            Rc::new(arithmetic(&current, operator, &value)?)
+        },
+        None => evaluate_expression(state, value)?,
+    };
+    match *object_value {
+        Value::List(ref elements) => {
+            let mut elements = elements.borrow_mut();
+            let i = list::index(&index_value, elements.len()).map_err(|message| RuntimeError::new(bracket, message) as Box<Error>)?;
+            elements[i] = Rc::clone(&value);
+            Ok(value)
+        },
+        Value::Map(ref map) => {
+            let key = Key::from_value(&index_value).map_err(|message| RuntimeError::new(bracket, message) as Box<Error>)?;
+            map.borrow_mut().insert(key, Rc::clone(&value));
+            Ok(value)
+        },
+        _ => Err(RuntimeError::new(bracket, format!("Can't index into {}", object_value))),
+    }
+}
+
+fn evaluate_map(state: &mut State, brace: &Token, entries: &Vec<(Expr, Expr)>) -> Result<Rc<Value>, Box<Error>> {
+    let mut map = LoxMap::new();
+    for &(ref key, ref value) in entries.iter() {
+        let key_value = evaluate_expression(state, key)?;
+        let key = Key::from_value(&key_value).map_err(|message| RuntimeError::new(brace, message) as Box<Error>)?;
+        map.insert(key, evaluate_expression(state, value)?);
+    }
+    Ok(Rc::new(map::new(map)))
+}
+
+fn evaluate_range(state: &mut State, start: &Expr, operator: &Token, end: &Expr, step: &Option<Box<Expr>>) -> Result<Rc<Value>, Box<Error>> {
+    let start_value = evaluate_expression(state, start)?;
+    let end_value = evaluate_expression(state, end)?;
+    let step_value = match step {
+        &Some(ref s) => evaluate_expression(state, s)?,
+        &None => Rc::new(Value::Integer(1)),
+    };
+    Range::from_values(&start_value, &end_value, &step_value, operator.token_type == TokenType::DotDotEqual)
+        .map(|range| Rc::new(Value::Range(range)))
+        .map_err(|message| RuntimeError::new(operator, message) as Box<Error>)
+}
+
+fn evaluate_interpolation(state: &mut State, parts: &Vec<Expr>) -> Result<Rc<Value>, Box<Error>> {
+    let mut s = String::new();
+    for part in parts.iter() {
+        s.push_str(&evaluate_expression(state, part)?.to_string());
+    }
+    Ok(Rc::new(Value::Str(s)))
+}
+
+fn evaluate_list(state: &mut State, elements: &Vec<Expr>) -> Result<Rc<Value>, Box<Error>> {
+    let mut values = Vec::new();
+    for element in elements.iter() {
+        values.push(evaluate_expression(state, element)?);
+    }
+    Ok(Rc::new(list::new(values)))
+}
+
 fn evaluate_grouping(state: &mut State, expression: &Expr) -> Result<Rc<Value>, Box<Error>> {
     evaluate_expression(state, expression)
 }
@@ -239,6 +1056,14 @@ fn evaluate_literal(_state: &mut State, value: Rc<Value>) -> Result<Rc<Value>, B
     Ok(value)
 }
 
+fn evaluate_conditional(state: &mut State, condition: &Expr, then_branch: &Expr, else_branch: &Expr) -> Result<Rc<Value>, Box<Error>> {
+    if is_truthy(evaluate_expression(state, condition)?) {
+        evaluate_expression(state, then_branch)
+    } else {
+        evaluate_expression(state, else_branch)
+    }
+}
+
 fn evaluate_logical(state: &mut State, left: &Expr, operator: &Token, right: &Expr) -> Result<Rc<Value>, Box<Error>> {
     match evaluate_expression(state, left) {
         Ok(ref left_value) => {
@@ -246,6 +1071,7 @@ fn evaluate_logical(state: &mut State, left: &Expr, operator: &Token, right: &Ex
             match operator.token_type {
                 TokenType::Or if is_left_truthy => Ok(Rc::clone(left_value)),
                 TokenType::And if !is_left_truthy => Ok(Rc::clone(left_value)),
+                TokenType::QuestionQuestion if **left_value != Value::Nil => Ok(Rc::clone(left_value)),
                 _ => evaluate_expression(state, right),
             }
         },
@@ -258,8 +1084,16 @@ fn evaluate_unary(state: &mut State, operator: &Token, right: &Expr) -> Result<R
     match operator.token_type {
         TokenType::Minus => match *right_value {
             Value::Number(n) => Ok(Rc::new(Value::Number(-n))),
+            Value::Integer(n) => Ok(Rc::new(number::integer(-BigInt::from(n)))),
+            Value::BigInt(ref n) => Ok(Rc::new(number::integer(-n))),
+            Value::Decimal(ref d) => Ok(Rc::new(Value::Decimal(-d))),
             _ => Err(RuntimeError::new(operator, format!("Operator '-' cannot be applied to non-number value {}", right_value))),
         },
+        TokenType::Tilde => match *right_value {
+            Value::Integer(n) => Ok(Rc::new(Value::Integer(!n))),
+            Value::BigInt(ref n) => Ok(Rc::new(number::integer(!n))),
+            _ => Err(RuntimeError::new(operator, format!("Operator '~' can only be applied to integers, not {}", right_value))),
+        },
         TokenType::Bang => Ok(Rc::new(Value::Boolean(!is_truthy(right_value)))),
         _ => {
             let description = format!("Operator '{}' is not valid in a unary expression", operator.token_type);
@@ -277,11 +1111,200 @@ fn is_truthy(value: Rc<Value>) -> bool {
 }
 
 fn is_equal(left: Rc<Value>, right: Rc<Value>) -> bool {
-    match *left {
-        Value::Nil => match *right {
-            Value::Nil => true,
-            _ => false,
-        },
+    match (&*left, &*right) {
+        (&Value::Nil, &Value::Nil) => true,
+        (&Value::Nil, _) => false,
+        // `1 == 1.0`, even though they're different kinds of number
+        (l, r) if number::kind(l).is_some() && number::kind(r).is_some() => number::compare(l, r) == Some(Some(Ordering::Equal)),
         _ => *left == *right,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parse;
+    use scanner::scan;
+
+    // runs a script named test.lox, returning the final value of its `r`
+    // variable, and the error it stopped with, if any, with that error's
+    // stack trace
+    fn run_source(source: &str) -> (Option<Rc<Value>>, Option<String>, Vec<String>) {
+        let statements = parse(scan(&String::from(source)).unwrap()).unwrap();
+        let environment = Rc::new(RefCell::new(Environment::new()));
+        let result = interpret_file(Rc::clone(&environment), statements, "test.lox");
+        let name = Token::with_lexeme(TokenType::Identifier, String::from("r"), 1);
+        let r = environment.borrow().get(&name);
+        match result {
+            Ok(_) => (r, None, vec![]),
+            Err(error) => {
+                let trace = error.downcast_ref::<RuntimeError>()
+                    .map(|e| e.trace().iter().map(|f| f.to_string()).collect())
+                    .unwrap_or(vec![]);
+                (r, Some(error.to_string()), trace)
+            },
+        }
+    }
+
+    // the final value of `r` in a script that runs without errors
+    fn result_of(source: &str) -> String {
+        match run_source(source) {
+            (Some(r), None, _) => r.to_string(),
+            (_, error, _) => panic!("no result from {:?}: {:?}", source, error),
+        }
+    }
+
+    fn error_from(source: &str) -> String {
+        run_source(source).1.unwrap()
+    }
+
+    #[test]
+    fn runtime_errors_carry_a_stack_trace() {
+        let (_, error, trace) = run_source("var a = 1;\n{\n  print a / 0;\n}");
+        assert_eq!(Some(String::from("ERR:3:Can't divide by zero")), error);
+        assert_eq!(vec![String::from("[line 3] in script (test.lox)")], trace);
+    }
+
+    #[test]
+    fn exceptions_can_be_caught() {
+        let source = "var r = \"\";\ntry {\n  r = 1 / 0;\n} catch (e) {\n  r = e.message;\n} finally {\n  r = r + \"!\";\n}\ntry { throw 42; } catch (e) { r = r + e; }";
+        assert_eq!("Can't divide by zero!42", result_of(source));
+    }
+
+    #[test]
+    fn integers_stay_exact_until_mixed_with_floats() {
+        assert_eq!("[3, -4, 3.5, 6, 6.0, true, true]", result_of("var r = [7 / 2, -7 / 2, 7 / 2.0, 2 * 3, 2 * 3.0, 1 == 1.0, 1 < 1.5];"));
+    }
+
+    #[test]
+    fn overflowing_integers_become_big_integers() {
+        let r = run_source("var r = [9223372036854775807 + 1, 9223372036854775807 + 1 - 1, 1.10d * 3, 0.1d + 0.2d == 0.3d];").0.unwrap();
+        assert_eq!("[9223372036854775808, 9223372036854775807, 3.30, true]", r.to_string());
+        if let Value::List(ref elements) = *r {
+            assert_eq!(Value::Integer(::std::i64::MAX), *elements.borrow()[1]);
+        }
+
+        assert_eq!("ERR:1:Can't mix a decimal and a float in 1.5d + 1.5", error_from("print 1.5d + 1.5;"));
+        assert_eq!("ERR:1:Can't mix a decimal and a float in 2.0 - 0.5d", error_from("print 2.0 - 0.5d;"));
+        assert_eq!("ERR:1:Can't raise a decimal to the power 0.5d", error_from("print 2d ** 0.5d;"));
+    }
+
+    #[test]
+    fn evaluates_extra_operators_with_their_precedence() {
+        let source = "var a = 10;\na += 5;\nvar r = [-7 % 3, -2 ** 2, 2 ** 3 ** 2, 2 ** -1, 1 + 2 & 3 == 3, ~5, 1 << 64, a];";
+        assert_eq!("[2, -4, 512, 0.5, true, -6, 18446744073709551616, 15]", result_of(source));
+    }
+
+    #[test]
+    fn raises_to_negative_powers() {
+        assert_eq!("[0.5, 0.25, 1.0, 0.5, 0.5]", result_of("var r = [2 ** -1, 2 ** -2, 1 ** -5, 2.0 ** -1, 2d ** -1];"));
+        for source in ["0 ** -1", "0 ** -(2 ** 70)", "0.0 ** -0.5", "0d ** -1"].iter() {
+            assert_eq!("ERR:1:Can't raise zero to a negative power", error_from(&format!("print {};", source)));
+        }
+    }
+
+    #[test]
+    fn compound_assignment_evaluates_an_index_once() {
+        let source = "var xs = [1, 2];\nvar i = 0;\nfun f() { i = i + 1; return i - 1; }\nxs[f()] += 10;\nvar m = {\"k\": 1};\nm[\"k\"] *= 3;\nvar r = [xs, i, m];";
+        assert_eq!("[[11, 2], 1, {\"k\": 3}]", result_of(source));
+    }
+
+    #[test]
+    fn for_in_follows_the_iterator_protocol() {
+        let source = "fun counter(n) { var i = 0; return fun () { if (i >= n) return nil; i += 1; return i; }; }\nvar r = [];\nfor (var x in counter(2)) r.push(x);\nfor (var x in {\"next\": counter(1)}) r.push(x);\nfor (var x in {\"iterator\": fun () { return \"ab\"; }}) r.push(x);\nfor (var x in {\"next\": 1}) r.push(x);";
+        assert_eq!("[1, 2, 1, \"a\", \"b\", \"next\"]", result_of(source));
+    }
+
+    #[test]
+    fn conditionals_and_nil_checks_short_circuit() {
+        let source = "var a;\nvar n = 0;\nvar r = [a ?? 1, 0 ?? (n = 1), a?.b.c(), a?.keys()[0], (a?.b) ?? 2, false ? 3 : nil ? 4 : 5, n];";
+        assert_eq!("[1, 0, nil, nil, 2, 5, 0]", result_of(source));
+    }
+
+    #[test]
+    fn functions_close_over_their_scope() {
+        let source = "fun counter() {\n  var n = 0;\n  return fun () { n += 1; return n; };\n}\nvar c = counter();\nc();\nfun apply(f, x) { return f(x); }\nfun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }\nvar r = [c(), apply((x) => x * 2, 21), apply(fun (x) { return x + 1; }, 1), fib(10)];";
+        assert_eq!("[2, 42, 2, 55]", result_of(source));
+    }
+
+    #[test]
+    fn functions_see_the_variables_in_scope_where_they_are_declared() {
+        let source = "var a = 1;\nvar r = [];\n{\n  fun show() { r.push(a); }\n  show();\n  { var a = 3; show(); }\n}\nfun later() { return b; }\nvar b = 2;\nr.push(later());";
+        assert_eq!("[1, 1, 2]", result_of(source));
+
+        let errors = [
+            ("var a = 1;\n{\n  fun show() { print a; }\n  var a = 2;\n}", "ERR:4:can't declare 'a' after a function in the same scope has used an outer 'a'"),
+            ("fun f() {\n  var g = () => x;\n  var x = 1;\n}", "ERR:3:can't declare 'x' after a function in the same scope has used an outer 'x'"),
+            ("fun f(a) {\n  { var g = () => a; var a = 1; }\n}", "ERR:2:can't declare 'a' after a function in the same scope has used an outer 'a'"),
+        ];
+        for &(source, message) in errors.iter() {
+            let error = parse(scan(&String::from(source)).unwrap()).err().unwrap();
+            assert_eq!(message, error[0].to_string());
+        }
+    }
+
+    #[test]
+    fn deep_recursion_stops_with_a_collapsed_trace() {
+        let (r, error, trace) = spawn_with_stack(|| {
+            let (r, error, trace) = run_source("fun f(n) { if (n == 0) return 0; return 1 + f(n - 1); }\nvar r = f(5000);\nfun g() {\n  return g();\n}\ng();");
+            (r.map(|r| r.to_string()), error, trace)
+        }).unwrap().join().unwrap();
+        assert_eq!(Some(String::from("5000")), r);
+        assert_eq!(Some(String::from("ERR:4:Stack overflow")), error);
+        assert_eq!(
+            vec![String::from("[line 4] in g() (test.lox) [repeated 9998 more times]"), String::from("[line 6] in script (test.lox)")],
+            trace
+        );
+    }
+
+    #[test]
+    fn deep_recursion_on_a_small_stack_stops_before_overflowing_it() {
+        let error = thread::Builder::new().stack_size(DEFAULT_STACK_SIZE).spawn(|| {
+            error_from("fun f(n) { if (n == 0) return 0; return 1 + f(n - 1); }\nprint f(100000);")
+        }).unwrap().join().unwrap();
+        assert_eq!("ERR:1:Stack overflow", error);
+    }
+
+    #[test]
+    fn errors_in_functions_trace_the_calls() {
+        let (_, _, trace) = run_source("fun f(x) {\n  return x / 0;\n}\nprint f(1);");
+        assert_eq!(vec![String::from("[line 2] in f() (test.lox)"), String::from("[line 4] in script (test.lox)")], trace);
+
+        let (_, _, trace) = run_source("fun f(a,\n      b = a / 0) {\n  return b;\n}\nprint f(1);");
+        assert_eq!(vec![String::from("[line 2] in f() (test.lox)"), String::from("[line 5] in script (test.lox)")], trace);
+
+        assert_eq!("ERR:1:Can't return from outside a function", error_from("try { return 1; } catch (e) {}"));
+    }
+
+    #[test]
+    fn binds_defaults_rest_parameters_and_named_arguments() {
+        let source = "fun f(a, b = a + 1, ...rest) { return [a, b, rest]; }\nvar g = (x, y = 0) => x - y;\nvar r = [f(1), f(1, 5, 6, 7), f(b: 2, a: 3), g(y: 1, x: 4)];";
+        assert_eq!("[[1, 2, []], [1, 5, [6, 7]], [3, 2, []], 3]", result_of(source));
+
+        let errors = [
+            ("f();", "ERR:2:f() is missing an argument for 'a'"),
+            ("f(1, 2, 3);", "ERR:2:f() takes at most 2 arguments but got 3"),
+            ("f(1, c: 2);", "ERR:2:f() has no parameter named 'c'"),
+            ("f(1, a: 2);", "ERR:2:f() got more than one value for 'a'"),
+            ("\"\".length(n: 1);", "ERR:2:length doesn't take named arguments"),
+            ("\"\".contains();", "ERR:2:contains expects 1 argument but got 0"),
+            ("(fun () {})(1);", "ERR:2:lambda() takes 0 arguments but got 1"),
+            ("fun g(a) {}\ng(1, 2);", "ERR:3:g() takes 1 argument but got 2"),
+        ];
+        for &(call, message) in errors.iter() {
+            assert_eq!(message, error_from(&format!("fun f(a, b = 1) {{}}\n{}", call)));
+        }
+
+        let errors = [
+            ("fun f(a = 1, b) {}", "ERR:1:parameters without defaults can't follow ones with defaults"),
+            ("var g = (a = 1, b) => a;", "ERR:1:parameters without defaults can't follow ones with defaults"),
+            ("var a = 0;\nvar g = (a += 1) => a;", "ERR:2:unexpected token Arrow; expected [identifier]"),
+            ("fun f(a, b, a) {}", "ERR:1:duplicate parameter 'a'"),
+            ("var g = (a, ...a) => a;", "ERR:1:duplicate parameter 'a'"),
+        ];
+        for &(source, message) in errors.iter() {
+            let error = parse(scan(&String::from(source)).unwrap()).err().unwrap();
+            assert_eq!(message, error[0].to_string());
+        }
+    }
+}
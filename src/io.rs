@@ -0,0 +1,166 @@
+use std::env;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::process;
+use std::rc::Rc;
+
+use callable::{LoxCallable, NativeFunction, NativeFn};
+use list;
+use module::Module;
+use value::Value;
+
+const FUNCTIONS: &'static [(&'static str, usize, NativeFn)] = &[
+    ("read_file", 1, read_file),
+    ("write_file", 2, write_file),
+    ("append_file", 2, append_file),
+    ("exists", 1, exists),
+    ("list_dir", 1, list_dir),
+    ("mkdir", 1, mkdir),
+    ("remove", 1, remove),
+    ("read_line", 0, read_line),
+    ("env", 1, env_var),
+    ("exit", 1, exit),
+];
+
+/// The `io` module, imported with `import "io" as io;`, which as well as
+/// its functions has the script's command-line arguments as `args`.
+pub fn module(args: &Vec<String>) -> Module {
+    let mut values: Vec<(&str, Value)> = FUNCTIONS.iter()
+        .map(|&(name, arity, function)| (name, Value::Callable(LoxCallable::Native(NativeFunction::new(name, arity, function)))))
+        .collect();
+    values.push(("args", list::new(args.iter().map(|arg| Rc::new(Value::Str(arg.clone()))).collect())));
+    Module::native("io", values)
+}
+
+fn string(value: &Value) -> Result<&str, String> {
+    match *value {
+        Value::Str(ref s) => Ok(s),
+        _ => Err(format!("Expected a string, not {}", value)),
+    }
+}
+
+fn nil() -> Result<Rc<Value>, String> {
+    Ok(Rc::new(Value::Nil))
+}
+
+fn read_file(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let path = string(&arguments[0])?;
+    let contents = fs::read_to_string(path).map_err(|error| format!("Can't read file '{}': {}", path, error))?;
+    Ok(Rc::new(Value::Str(contents)))
+}
+
+fn write_file(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let path = string(&arguments[0])?;
+    fs::write(path, string(&arguments[1])?).map_err(|error| format!("Can't write file '{}': {}", path, error))?;
+    nil()
+}
+
+fn append_file(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let path = string(&arguments[0])?;
+    let contents = string(&arguments[1])?;
+    OpenOptions::new().append(true).create(true).open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|error| format!("Can't append to file '{}': {}", path, error))?;
+    nil()
+}
+
+fn exists(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    Ok(Rc::new(Value::Boolean(Path::new(string(&arguments[0])?).exists())))
+}
+
+// the names of the directory's entries, sorted
+fn list_dir(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let path = string(&arguments[0])?;
+    let mut names: Vec<String> = fs::read_dir(path)
+        .and_then(|entries| entries.map(|entry| entry.map(|e| e.file_name().to_string_lossy().into_owned())).collect())
+        .map_err(|error| format!("Can't list directory '{}': {}", path, error))?;
+    names.sort();
+    Ok(Rc::new(list::new(names.into_iter().map(|name| Rc::new(Value::Str(name))).collect())))
+}
+
+// makes any missing parent directories too
+fn mkdir(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let path = string(&arguments[0])?;
+    fs::create_dir_all(path).map_err(|error| format!("Can't make directory '{}': {}", path, error))?;
+    nil()
+}
+
+// removes a file, or a directory along with everything in it
+fn remove(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let path = string(&arguments[0])?;
+    let result = if Path::new(path).is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+    result.map_err(|error| format!("Can't remove '{}': {}", path, error))?;
+    nil()
+}
+
+// a line from stdin without its line ending, or nil at the end of the input
+fn read_line(_: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) => nil(),
+        Ok(_) => {
+            let length = line.trim_end_matches(&['\r', '\n'][..]).len();
+            line.truncate(length);
+            Ok(Rc::new(Value::Str(line)))
+        },
+        Err(error) => Err(format!("Can't read from stdin: {}", error)),
+    }
+}
+
+// nil if the variable isn't set
+fn env_var(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    match env::var(string(&arguments[0])?) {
+        Ok(value) => Ok(Rc::new(Value::Str(value))),
+        Err(_) => nil(),
+    }
+}
+
+fn exit(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let code = match *arguments[0] {
+        Value::Integer(code) if code >= i32::min_value() as i64 && code <= i32::max_value() as i64 => code as i32,
+        ref other => return Err(format!("Exit code must be an integer, not {}", other)),
+    };
+    let _ = io::stdout().flush();
+    process::exit(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, arguments: Vec<&str>) -> Result<Value, String> {
+        let module = module(&Vec::new());
+        match *module.get(name).unwrap() {
+            Value::Callable(LoxCallable::Native(ref f)) => {
+                let arguments = arguments.into_iter().map(|a| Rc::new(Value::Str(String::from(a)))).collect();
+                f.call(arguments).map(|v| (*v).clone())
+            },
+            ref other => panic!("{} isn't a function: {}", name, other),
+        }
+    }
+
+    #[test]
+    fn reads_writes_and_removes_files() {
+        let dir = env::temp_dir().join(format!("rlox-io-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let dir = dir.to_str().unwrap();
+        let file = format!("{}/sub/a.txt", dir);
+        call("mkdir", vec![&format!("{}/sub", dir)]).unwrap();
+        call("write_file", vec![&file, "one\n"]).unwrap();
+        call("append_file", vec![&file, "two\n"]).unwrap();
+        assert_eq!(Ok(Value::Str(String::from("one\ntwo\n"))), call("read_file", vec![&file]));
+        assert_eq!(Ok(list::new(vec![Rc::new(Value::Str(String::from("a.txt")))])), call("list_dir", vec![&format!("{}/sub", dir)]));
+        call("remove", vec![dir]).unwrap();
+        assert_eq!(Ok(Value::Boolean(false)), call("exists", vec![&file]));
+    }
+
+    #[test]
+    fn reports_failures_as_errors() {
+        let error = call("read_file", vec!["/no/such/file"]).unwrap_err();
+        assert!(error.starts_with("Can't read file '/no/such/file': "), "{}", error);
+        assert_eq!(Err(String::from("Exit code must be an integer, not x")), call("exit", vec!["x"]));
+    }
+}
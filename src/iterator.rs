@@ -0,0 +1,115 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use map::Key;
+use range::Range;
+use value::Value;
+
+/// How a `for-in` loop gets its elements.  Values other than the built-in
+/// collections can be looped over by following a protocol: a function is
+/// called for each element until it returns `nil`, as is the `next`
+/// function of a map, while a map with an `iterator` function has it
+/// called once to get what to loop over.
+pub enum Iteration {
+    Values(LoxIterator),
+    Calls(Rc<Value>),
+    Iterator(Rc<Value>),
+}
+
+/// Steps through the elements of a value in a `for-in` loop.
+pub enum LoxIterator {
+    /// Lists are read as the loop goes, so elements pushed inside the loop
+    /// are visited too.
+    List { elements: Rc<RefCell<Vec<Rc<Value>>>>, position: usize },
+    /// The keys of a map, or the characters of a string, as they were
+    /// when the loop started.
+    Snapshot { values: Vec<Rc<Value>>, position: usize },
+    Range { range: Range, position: usize },
+}
+
+impl Iterator for LoxIterator {
+    type Item = Rc<Value>;
+
+    fn next(&mut self) -> Option<Rc<Value>> {
+        match *self {
+            LoxIterator::List { ref elements, ref mut position } => {
+                let next = elements.borrow().get(*position).cloned();
+                *position += 1;
+                next
+            },
+            LoxIterator::Snapshot { ref values, ref mut position } => {
+                let next = values.get(*position).cloned();
+                *position += 1;
+                next
+            },
+            LoxIterator::Range { ref range, ref mut position } => {
+                let next = range.get(*position).map(Rc::new);
+                *position += 1;
+                next
+            },
+        }
+    }
+}
+
+/// Starts iterating over a value, if it's a kind of value that can be
+/// iterated over.
+pub fn iterate(value: &Value) -> Result<Iteration, String> {
+    match *value {
+        Value::Callable(_) => return Ok(Iteration::Calls(Rc::new(value.clone()))),
+        Value::Map(ref map) => {
+            let function = |name: &str| map.borrow().get(&Key::Str(String::from(name))).and_then(|f| match *f {
+                Value::Callable(_) => Some(Rc::clone(&f)),
+                _ => None,
+            });
+            if let Some(next) = function("next") {
+                return Ok(Iteration::Calls(next));
+            }
+            if let Some(iterator) = function("iterator") {
+                return Ok(Iteration::Iterator(iterator));
+            }
+        },
+        _ => (),
+    }
+    iterate_values(value).map(Iteration::Values)
+}
+
+fn iterate_values(value: &Value) -> Result<LoxIterator, String> {
+    match *value {
+        Value::List(ref elements) => Ok(LoxIterator::List { elements: Rc::clone(elements), position: 0 }),
+        Value::Map(ref map) => {
+            let keys = map.borrow().entries().iter().map(|&(ref key, _)| Rc::new(key.to_value())).collect();
+            Ok(LoxIterator::Snapshot { values: keys, position: 0 })
+        },
+        Value::Str(ref s) => {
+            let chars = s.chars().map(|c| Rc::new(Value::Str(c.to_string()))).collect();
+            Ok(LoxIterator::Snapshot { values: chars, position: 0 })
+        },
+        Value::Range(ref range) => Ok(LoxIterator::Range { range: range.clone(), position: 0 }),
+        _ => Err(format!("Can't iterate over {}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use list;
+
+    #[test]
+    fn sees_elements_added_while_iterating() {
+        let xs = list::new(vec![Rc::new(Value::Integer(1))]);
+        let mut iter = iterate_values(&xs).unwrap();
+        assert_eq!(Some(Rc::new(Value::Integer(1))), iter.next());
+        if let Value::List(ref elements) = xs {
+            elements.borrow_mut().push(Rc::new(Value::Integer(2)));
+        }
+        assert_eq!(Some(Rc::new(Value::Integer(2))), iter.next());
+        assert_eq!(None, iter.next());
+    }
+
+    #[test]
+    fn iterates_over_characters() {
+        let chars: Vec<String> = iterate_values(&Value::Str(String::from("hé"))).unwrap().map(|c| c.to_string()).collect();
+        assert_eq!(vec!["h", "é"], chars);
+        assert!(iterate(&Value::Integer(1)).is_err());
+    }
+}
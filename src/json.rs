@@ -0,0 +1,432 @@
+use std::rc::Rc;
+
+use num_bigint::BigInt;
+
+use callable::{LoxCallable, NativeFunction};
+use list;
+use map;
+use map::{Key, LoxMap};
+use module::Module;
+use value::Value;
+
+// deeper nesting than this is almost certainly a mistake, and would
+// otherwise overflow the stack
+const MAX_DEPTH: usize = 500;
+
+/// The `json` module, imported with `import "json" as json;`.
+pub fn module() -> Module {
+    Module::native("json", vec![
+        ("parse", Value::Callable(LoxCallable::Native(NativeFunction::new("parse", 1, parse)))),
+        ("stringify", Value::Callable(LoxCallable::Native(NativeFunction::with_optional("stringify", 1, 1, stringify)))),
+    ])
+}
+
+fn parse(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    match *arguments[0] {
+        Value::Str(ref s) => from_json(s).map(Rc::new),
+        ref other => Err(format!("Expected a string, not {}", other)),
+    }
+}
+
+// the indent is a number of spaces or a string to indent with; without
+// one, the JSON is all on one line
+fn stringify(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let indent = match arguments.get(1).map(|a| &**a) {
+        None | Some(&Value::Nil) => None,
+        Some(&Value::Integer(n)) if n >= 0 && n <= 10 => Some(" ".repeat(n as usize)),
+        Some(&Value::Str(ref s)) => Some(s.clone()),
+        Some(other) => return Err(format!("Indent must be a number of spaces from 0 to 10 or a string, not {}", other)),
+    };
+    to_json(&arguments[0], indent.as_ref().map(|s| s.as_str())).map(|json| Rc::new(Value::Str(json)))
+}
+
+/// Parses JSON into a Lox value.  Objects become maps, keeping the order of
+/// their keys, and arrays become lists.  Numbers without a fraction or an
+/// exponent become integers, however big they are, and the rest floats.
+pub fn from_json(source: &str) -> Result<Value, String> {
+    let mut parser = Parser { chars: source.chars().collect(), current: 0 };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        None => Ok(value),
+        Some(_) => Err(parser.error("expected the end of the input")),
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    current: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.current).cloned()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.current += 1;
+        c
+    }
+
+    fn error(&self, message: &str) -> String {
+        let before = &self.chars[..self.current.min(self.chars.len())];
+        let line = before.iter().filter(|&&c| c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+        format!("Invalid JSON at line {}, column {}: {}", line, column, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.peek() {
+            self.current += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char, message: &str) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.current += 1;
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, String> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(depth),
+            Some('[') => self.array(depth),
+            Some('"') => self.string().map(Value::Str),
+            Some(c) if c == '-' || c.is_digit(10) => self.number(),
+            Some(_) if self.literal("true") => Ok(Value::Boolean(true)),
+            Some(_) if self.literal("false") => Ok(Value::Boolean(false)),
+            Some(_) if self.literal("null") => Ok(Value::Nil),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn literal(&mut self, word: &str) -> bool {
+        let end = self.current + word.len();
+        if end <= self.chars.len() && self.chars[self.current..end].iter().cloned().eq(word.chars()) {
+            self.current = end;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, String> {
+        self.current += 1;
+        let mut map = LoxMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.current += 1;
+            return Ok(map::new(map));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a string key"));
+            }
+            let key = self.string()?;
+            self.expect(':', "expected ':' after a key")?;
+            let value = self.value(depth + 1)?;
+            map.insert(Key::Str(key), Rc::new(value));
+            self.skip_whitespace();
+            match self.advance() {
+                Some(',') => (),
+                Some('}') => return Ok(map::new(map)),
+                _ => {
+                    self.current -= 1;
+                    return Err(self.error("expected ',' or '}'"));
+                },
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, String> {
+        self.current += 1;
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.current += 1;
+            return Ok(list::new(elements));
+        }
+        loop {
+            elements.push(Rc::new(self.value(depth + 1)?));
+            self.skip_whitespace();
+            match self.advance() {
+                Some(',') => (),
+                Some(']') => return Ok(list::new(elements)),
+                _ => {
+                    self.current -= 1;
+                    return Err(self.error("expected ',' or ']'"));
+                },
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.current += 1;
+        let mut s = String::new();
+        loop {
+            match self.advance() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.advance() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/') => s.push('/'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('u') => s.push(self.unicode_escape()?),
+                    _ => {
+                        self.current -= 1;
+                        return Err(self.error("invalid escape in string"));
+                    },
+                },
+                Some(c) if c < ' ' => {
+                    self.current -= 1;
+                    return Err(self.error("control characters in strings must be escaped"));
+                },
+                Some(c) => s.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let end = self.current + 4;
+        let digits: String = self.chars[self.current..end.min(self.chars.len())].iter().collect();
+        match u32::from_str_radix(&digits, 16) {
+            Ok(n) if digits.len() == 4 && digits.chars().all(|c| c.is_digit(16)) => {
+                self.current = end;
+                Ok(n)
+            },
+            _ => Err(self.error("expected four hex digits after '\\u'")),
+        }
+    }
+
+    // characters outside the basic multilingual plane are written as a
+    // surrogate pair of escapes
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if high >= 0xD800 && high < 0xDC00 && self.literal("\\u") {
+            let low = self.hex4()?;
+            if low < 0xDC00 || low >= 0xE000 {
+                return Err(self.error("invalid surrogate pair"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        ::std::char::from_u32(code).ok_or(self.error("invalid unicode escape"))
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.current;
+        if self.peek() == Some('-') {
+            self.current += 1;
+        }
+        match self.peek() {
+            Some('0') => self.current += 1,
+            Some(c) if c.is_digit(10) => self.digits(),
+            _ => return Err(self.error("expected a digit")),
+        }
+        let mut float = false;
+        if self.peek() == Some('.') {
+            float = true;
+            self.current += 1;
+            if !self.peek().map_or(false, |c| c.is_digit(10)) {
+                return Err(self.error("expected a digit after '.'"));
+            }
+            self.digits();
+        }
+        if let Some('e') | Some('E') = self.peek() {
+            float = true;
+            self.current += 1;
+            if let Some('+') | Some('-') = self.peek() {
+                self.current += 1;
+            }
+            if !self.peek().map_or(false, |c| c.is_digit(10)) {
+                return Err(self.error("expected a digit in the exponent"));
+            }
+            self.digits();
+        }
+
+        let text: String = self.chars[start..self.current].iter().collect();
+        // there's no integer -0, so it has to be a float to keep its sign
+        if float || text == "-0" {
+            match text.parse::<f64>() {
+                Ok(n) if n.is_finite() => Ok(Value::Number(n)),
+                _ => {
+                    self.current = start;
+                    Err(self.error(&format!("{} is too large to be a float", text)))
+                },
+            }
+        } else {
+            match text.parse() {
+                Ok(i) => Ok(Value::Integer(i)),
+                Err(_) => Ok(Value::BigInt(text.parse::<BigInt>().unwrap())),
+            }
+        }
+    }
+
+    fn digits(&mut self) {
+        while self.peek().map_or(false, |c| c.is_digit(10)) {
+            self.current += 1;
+        }
+    }
+}
+
+/// Converts a Lox value to JSON, indenting nested values with the given
+/// string, if any.  Only nil, booleans, finite numbers, strings, lists and
+/// maps with string keys can be converted.
+pub fn to_json(value: &Value, indent: Option<&str>) -> Result<String, String> {
+    let mut output = String::new();
+    write_json(&mut output, value, indent, &mut Vec::new())?;
+    Ok(output)
+}
+
+// `containers` holds the lists and maps being written, so that one which
+// contains itself can be reported rather than written forever
+fn write_json(output: &mut String, value: &Value, indent: Option<&str>, containers: &mut Vec<*const ()>) -> Result<(), String> {
+    match *value {
+        Value::Nil => output.push_str("null"),
+        Value::Boolean(b) => output.push_str(&b.to_string()),
+        Value::Integer(_) | Value::BigInt(_) | Value::Decimal(_) => output.push_str(&value.to_string()),
+        Value::Number(n) if n.is_finite() => output.push_str(&value.to_string()),
+        Value::Number(n) => return Err(format!("Can't convert {} to JSON", n)),
+        Value::Str(ref s) => write_string(output, s),
+        Value::List(ref elements) => {
+            let pointer = &**elements as *const _ as *const ();
+            if containers.contains(&pointer) {
+                return Err(String::from("Can't convert a list that contains itself to JSON"));
+            }
+            containers.push(pointer);
+            let elements = elements.borrow();
+            output.push('[');
+            for (i, element) in elements.iter().enumerate() {
+                if i > 0 {
+                    output.push(',');
+                }
+                write_newline(output, indent, containers.len());
+                write_json(output, element, indent, containers)?;
+            }
+            containers.pop();
+            if !elements.is_empty() {
+                write_newline(output, indent, containers.len());
+            }
+            output.push(']');
+        },
+        Value::Map(ref map) => {
+            let pointer = &**map as *const _ as *const ();
+            if containers.contains(&pointer) {
+                return Err(String::from("Can't convert a map that contains itself to JSON"));
+            }
+            containers.push(pointer);
+            let map = map.borrow();
+            output.push('{');
+            for (i, &(ref key, ref value)) in map.entries().iter().enumerate() {
+                if i > 0 {
+                    output.push(',');
+                }
+                write_newline(output, indent, containers.len());
+                match *key {
+                    Key::Str(ref s) => write_string(output, s),
+                    _ => return Err(format!("Only maps with string keys can be converted to JSON, not one with the key {}", key)),
+                }
+                output.push_str(if indent.is_some() { ": " } else { ":" });
+                write_json(output, value, indent, containers)?;
+            }
+            containers.pop();
+            if map.len() > 0 {
+                write_newline(output, indent, containers.len());
+            }
+            output.push('}');
+        },
+        Value::Callable(_) => return Err(format!("Can't convert the function {} to JSON", value)),
+        _ => return Err(format!("Can't convert {} to JSON", value)),
+    }
+    Ok(())
+}
+
+fn write_newline(output: &mut String, indent: Option<&str>, depth: usize) {
+    if let Some(indent) = indent {
+        output.push('\n');
+        for _ in 0..depth {
+            output.push_str(indent);
+        }
+    }
+}
+
+fn write_string(output: &mut String, s: &str) {
+    output.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if c < ' ' => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_json_text() {
+        let compact = r#"{"name":"rlox","tags":["a\"b","\u0001\n"],"n":-12,"big":123456789012345678901234567890,"x":1.5,"e":1.0,"ok":true,"none":null,"empty":{},"list":[]}"#;
+        assert_eq!(compact, to_json(&from_json(compact).unwrap(), None).unwrap());
+
+        let pretty = "{\n  \"b\": [\n    1,\n    {\n      \"a\": null\n    }\n  ],\n  \"a\": []\n}";
+        assert_eq!(pretty, to_json(&from_json(pretty).unwrap(), Some("  ")).unwrap());
+    }
+
+    #[test]
+    fn round_trips_values() {
+        let value = from_json(r#"[{"k": [1, 2.5e3, "😀 \/"]}, -0, 1E2]"#).unwrap();
+        assert_eq!("[{\"k\": [1, 2500.0, \"\u{1F600} /\"]}, -0.0, 100.0]", value.to_string());
+        assert_eq!(value, from_json(&to_json(&value, Some("\t")).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn reports_where_json_is_invalid() {
+        assert_eq!(Err(String::from("Invalid JSON at line 2, column 5: expected ',' or ']'")), from_json("[1,\n  2 3]"));
+        assert_eq!(Err(String::from("Invalid JSON at line 1, column 2: expected a string key")), from_json("{a: 1}"));
+        assert_eq!(Err(String::from("Invalid JSON at line 1, column 5: -1e400 is too large to be a float")), from_json("[1, -1e400]"));
+        assert!(from_json("01").is_err());
+        assert!(from_json("[1,]").is_err());
+        assert!(from_json("\"abc").is_err());
+        assert!(from_json(&"[".repeat(1000)).is_err());
+    }
+
+    #[test]
+    fn rejects_values_json_cant_represent() {
+        let function = Value::Callable(LoxCallable::Native(NativeFunction::new("parse", 1, parse)));
+        assert_eq!(Err(String::from("Can't convert the function parse to JSON")), to_json(&list::new(vec![Rc::new(function)]), None));
+        assert_eq!(Err(String::from("Can't convert NaN to JSON")), to_json(&Value::Number(::std::f64::NAN), None));
+
+        let mut map = LoxMap::new();
+        map.insert(Key::Integer(1), Rc::new(Value::Nil));
+        assert!(to_json(&map::new(map), None).is_err());
+
+        let xs = list::new(Vec::new());
+        if let Value::List(ref elements) = xs {
+            elements.borrow_mut().push(Rc::new(xs.clone()));
+        }
+        assert_eq!(Err(String::from("Can't convert a list that contains itself to JSON")), to_json(&xs, None));
+    }
+}
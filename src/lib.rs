@@ -1,32 +1,66 @@
+extern crate bigdecimal;
 #[macro_use]
 extern crate lazy_static;
+extern crate num_bigint;
+extern crate num_integer;
+extern crate num_traits;
+#[macro_use]
+extern crate serde_json;
 
 use std::cell::RefCell;
 use std::error::Error;
 use std::rc::Rc;
 
 pub mod callable;
+pub mod dap;
+pub mod debugger;
 pub mod environment;
 pub mod expression;
 pub mod interpreter;
+pub mod io;
+pub mod iterator;
+pub mod json;
+pub mod linter;
+pub mod list;
+pub mod formatter;
 pub mod function;
+pub mod highlight;
+pub mod lsp;
+pub mod map;
+pub mod math;
+pub mod module;
+pub mod number;
 pub mod parser;
+pub mod range;
+pub mod resolver;
 pub mod scanner;
 pub mod statement;
+pub mod string;
 pub mod token;
+pub mod transport;
 pub mod value;
 
 use environment::Environment;
-use interpreter::interpret;
+use interpreter::interpret_with_loader;
+use module::Loader;
 use parser::parse;
 use scanner::scan;
 
-pub fn run(environment: Rc<RefCell<Environment>>, source: &String) -> Result<(), Vec<Box<Error>>> {
+/// Runs source that isn't from a file, such as a line typed into the REPL;
+/// modules it imports are found relative to the current directory.
+pub fn run(environment: Rc<RefCell<Environment>>, source: &String, loader: &Rc<RefCell<Loader>>) -> Result<(), Vec<Box<Error>>> {
     scan(source).map_err(|error| vec![error]).and_then(|tokens| {
         //println!("tokens: {:?}", tokens);
         parse(tokens)
     }).and_then(|expr| {
         //println!("expr: {}", expr);
-        interpret(environment, expr).map_err(|error| vec![error])
+        interpret_with_loader(environment, expr, None, Rc::clone(loader)).map_err(|error| vec![error])
     })
 }
+
+/// Like `run()`, for source read from the named file.
+pub fn run_file(environment: Rc<RefCell<Environment>>, source: &String, filename: &str, loader: &Rc<RefCell<Loader>>) -> Result<(), Vec<Box<Error>>> {
+    scan(source).map_err(|error| vec![error])
+        .and_then(parse)
+        .and_then(|statements| interpret_with_loader(environment, statements, Some(filename), Rc::clone(loader)).map_err(|error| vec![error]))
+}
@@ -0,0 +1,507 @@
+use std::fmt;
+
+use expression::Expr;
+use statement::{Parameter, Stmt};
+use token::{TokenType, Token};
+use value::Value;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Rule {
+    UnusedVariable,
+    ShadowedVariable,
+    AssignmentInCondition,
+    MismatchedLiteralComparison,
+    EmptyBlock,
+    UnreachableCode,
+}
+
+const ALL_RULES: [Rule; 6] = [
+    Rule::UnusedVariable,
+    Rule::ShadowedVariable,
+    Rule::AssignmentInCondition,
+    Rule::MismatchedLiteralComparison,
+    Rule::EmptyBlock,
+    Rule::UnreachableCode,
+];
+
+impl Rule {
+    pub fn all() -> &'static [Rule] {
+        &ALL_RULES
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::ShadowedVariable => "shadowed-variable",
+            Rule::AssignmentInCondition => "assignment-in-condition",
+            Rule::MismatchedLiteralComparison => "mismatched-literal-comparison",
+            Rule::EmptyBlock => "empty-block",
+            Rule::UnreachableCode => "unreachable-code",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        ALL_RULES.iter().find(|rule| rule.name() == name).cloned()
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+pub struct Config {
+    enabled: Vec<Rule>,
+}
+
+impl Config {
+    /// Creates a config with every rule enabled.
+    pub fn new() -> Config {
+        Config {
+            enabled: ALL_RULES.to_vec(),
+        }
+    }
+
+    pub fn enable(&mut self, rule: Rule) {
+        if !self.is_enabled(rule) {
+            self.enabled.push(rule);
+        }
+    }
+
+    pub fn disable(&mut self, rule: Rule) {
+        self.enabled.retain(|r| *r != rule);
+    }
+
+    pub fn is_enabled(&self, rule: Rule) -> bool {
+        self.enabled.contains(&rule)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Warning {
+    pub rule: Rule,
+    pub line: u32,
+    pub message: String,
+}
+
+impl Warning {
+    /// Renders the warning as a single-line JSON object, for tools that
+    /// consume lint output.
+    pub fn to_json(&self, filename: &str) -> String {
+        format!("{{\"file\":\"{}\",\"rule\":\"{}\",\"line\":{},\"message\":\"{}\"}}",
+                escape_json(filename), self.rule, self.line, escape_json(&self.message))
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WARN:{}:{} [{}]", self.line, self.message, self.rule)
+    }
+}
+
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+struct Variable {
+    name: Token,
+    used: bool,
+}
+
+struct State<'a> {
+    config: &'a Config,
+    scopes: Vec<Vec<Variable>>,
+    warnings: Vec<Warning>,
+}
+
+impl<'a> State<'a> {
+    fn warn(&mut self, rule: Rule, line: u32, message: String) {
+        if self.config.is_enabled(rule) {
+            self.warnings.push(Warning { rule, line, message });
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn end_scope(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            for variable in scope.iter().filter(|v| !v.used) {
+                let message = format!("Variable '{}' is never used", variable.name.lexeme);
+                self.warn(Rule::UnusedVariable, variable.name.line, message);
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &Token) {
+        // globals can be redefined freely, and are never reported as unused
+        if self.scopes.is_empty() {
+            return;
+        }
+
+        let shadowed = self.scopes[..self.scopes.len() - 1].iter().rev()
+            .filter_map(|scope| scope.iter().find(|v| v.name.lexeme == name.lexeme))
+            .map(|v| v.name.line)
+            .next();
+        if let Some(line) = shadowed {
+            let message = format!("Variable '{}' shadows the declaration on line {}", name.lexeme, line);
+            self.warn(Rule::ShadowedVariable, name.line, message);
+        }
+
+        let scope = self.scopes.last_mut().unwrap();
+        scope.retain(|v| v.name.lexeme != name.lexeme);
+        scope.push(Variable { name: name.clone(), used: false });
+    }
+
+    fn mark_used(&mut self, name: &Token) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(variable) = scope.iter_mut().find(|v| v.name.lexeme == name.lexeme) {
+                variable.used = true;
+                return;
+            }
+        }
+    }
+}
+
+/// Runs the enabled lint rules over a parsed program, returning the
+/// warnings in the order they were found.
+pub fn lint(statements: &Vec<Stmt>, config: &Config) -> Vec<Warning> {
+    let mut state = State {
+        config,
+        scopes: Vec::new(),
+        warnings: Vec::new(),
+    };
+    check_stmts(&mut state, statements);
+    state.warnings
+}
+
+/// Checks a sequence of statements, reporting the first one that can never
+/// run because control never gets past an earlier one.
+fn check_stmts(state: &mut State, statements: &[Stmt]) {
+    let mut reported = false;
+    for (i, statement) in statements.iter().enumerate() {
+        if !reported && i > 0 && always_exits(&statements[i - 1]) {
+            state.warn(Rule::UnreachableCode, statement.line(), String::from("Unreachable code"));
+            reported = true;
+        }
+        check_stmt(state, statement);
+    }
+}
+
+// whether control can never get past the statement, because every path
+// through it returns, throws or loops forever
+fn always_exits(stmt: &Stmt) -> bool {
+    match stmt {
+        &Stmt::Return { .. } | &Stmt::Throw { .. } => true,
+        &Stmt::Block { ref statements, .. } => statements.iter().any(always_exits),
+        &Stmt::For { ref condition, .. } => match literal_value(condition) {
+            Some(&Value::Boolean(true)) => true,
+            _ => false,
+        },
+        &Stmt::If { ref then_branch, else_branch: Some(ref eb), .. } => always_exits(then_branch) && always_exits(eb),
+        _ => false,
+    }
+}
+
+fn check_stmt(state: &mut State, stmt: &Stmt) {
+    match stmt {
+        &Stmt::Block { ref statements, line } => {
+            if statements.is_empty() {
+                state.warn(Rule::EmptyBlock, line, String::from("Empty block"));
+            }
+            state.begin_scope();
+            check_stmts(state, statements);
+            state.end_scope();
+        },
+        &Stmt::Expression { ref expression, .. } => check_expr(state, expression),
+        &Stmt::For { ref initializer, ref condition, ref increment, ref body, .. } => {
+            if let &Some(ref i) = initializer {
+                check_stmt(state, i);
+            }
+            check_condition(state, condition);
+            check_stmt(state, body);
+            if let &Some(ref i) = increment {
+                check_stmt(state, i);
+            }
+        },
+        &Stmt::ForIn { ref name, ref iterable, ref body, .. } => {
+            check_expr(state, iterable);
+            state.begin_scope();
+            state.declare(name);
+            check_stmt(state, body);
+            state.end_scope();
+        },
+        &Stmt::Function { ref name, ref params, ref body } => {
+            // declared first, so the body can call the function
+            state.declare(name);
+            check_function(state, params, body);
+        },
+        &Stmt::Import { ref alias, ref names, .. } => {
+            for name in alias.iter().chain(names.iter().map(|&(_, ref local)| local)) {
+                state.declare(name);
+            }
+        },
+        &Stmt::Export { ref declaration, .. } => check_stmt(state, declaration),
+        &Stmt::If { ref expression, ref then_branch, ref else_branch, .. } => {
+            check_condition(state, expression);
+            check_stmt(state, then_branch);
+            if let &Some(ref eb) = else_branch {
+                check_stmt(state, eb);
+            }
+        },
+        &Stmt::Print { ref expression, .. } => check_expr(state, expression),
+        &Stmt::Return { ref value, .. } => if let &Some(ref v) = value {
+            check_expr(state, v);
+        },
+        &Stmt::Throw { ref expression, .. } => check_expr(state, expression),
+        &Stmt::Try { ref body, ref catch_branch, ref finally_branch, .. } => {
+            check_stmt(state, body);
+            if let &Some((ref name, ref cb)) = catch_branch {
+                // it's common not to need the exception, so the name
+                // counts as used
+                state.begin_scope();
+                state.declare(name);
+                state.mark_used(name);
+                check_stmt(state, cb);
+                state.end_scope();
+            }
+            if let &Some(ref fb) = finally_branch {
+                check_stmt(state, fb);
+            }
+        },
+        &Stmt::Var { ref name, ref initializer } => {
+            if let &Some(ref init) = initializer {
+                check_expr(state, init);
+            }
+            state.declare(name);
+        },
+    }
+}
+
+fn check_function(state: &mut State, params: &Vec<Parameter>, body: &Vec<Stmt>) {
+    // callbacks often ignore some of their arguments, so parameters count
+    // as used
+    state.begin_scope();
+    for param in params.iter() {
+        if let Some(ref default) = param.default {
+            check_expr(state, default);
+        }
+        state.declare(&param.name);
+        state.mark_used(&param.name);
+    }
+    check_stmts(state, body);
+    state.end_scope();
+}
+
+fn check_condition(state: &mut State, condition: &Expr) {
+    let mut expr = condition;
+    while let &Expr::Grouping { ref expression } = expr {
+        expr = expression;
+    }
+    if let &Expr::Assign { ref name, .. } = expr {
+        let message = format!("Assignment to '{}' used as a condition; did you mean '=='?", name.lexeme);
+        state.warn(Rule::AssignmentInCondition, name.line, message);
+    }
+    check_expr(state, condition);
+}
+
+fn check_expr(state: &mut State, expr: &Expr) {
+    match expr {
+        &Expr::Assign { ref value, .. } => check_expr(state, value),
+        &Expr::Binary { ref left, ref operator, ref right } => {
+            check_comparison(state, left, operator, right);
+            check_expr(state, left);
+            check_expr(state, right);
+        },
+        &Expr::Call { ref callee, ref arguments, ref named, .. } => {
+            check_expr(state, callee);
+            for argument in arguments.iter().chain(named.iter().map(|&(_, ref value)| value)) {
+                check_expr(state, argument);
+            }
+        },
+        &Expr::Conditional { ref condition, ref then_branch, ref else_branch, .. } => {
+            check_condition(state, condition);
+            check_expr(state, then_branch);
+            check_expr(state, else_branch);
+        },
+        &Expr::Get { ref object, .. } => check_expr(state, object),
+        &Expr::Grouping { ref expression } => check_expr(state, expression),
+        &Expr::Index { ref object, ref index, .. } => {
+            check_expr(state, object);
+            check_expr(state, index);
+        },
+        &Expr::Interpolation { ref parts } => for part in parts.iter() {
+            check_expr(state, part);
+        },
+        &Expr::Lambda { ref params, ref body, .. } => check_function(state, params, body),
+        &Expr::List { ref elements } => for element in elements.iter() {
+            check_expr(state, element);
+        },
+        &Expr::Literal { .. } => (),
+        &Expr::Logical { ref left, ref right, .. } => {
+            check_expr(state, left);
+            check_expr(state, right);
+        },
+        &Expr::Map { ref entries, .. } => for &(ref key, ref value) in entries.iter() {
+            check_expr(state, key);
+            check_expr(state, value);
+        },
+        &Expr::Range { ref start, ref end, ref step, .. } => {
+            check_expr(state, start);
+            check_expr(state, end);
+            if let &Some(ref s) = step {
+                check_expr(state, s);
+            }
+        },
+        &Expr::SetIndex { ref object, ref index, ref value, .. } => {
+            check_expr(state, object);
+            check_expr(state, index);
+            check_expr(state, value);
+        },
+        &Expr::Unary { ref right, .. } => check_expr(state, right),
+        &Expr::Variable { ref name } => state.mark_used(name),
+    }
+}
+
+fn check_comparison(state: &mut State, left: &Expr, operator: &Token, right: &Expr) {
+    match operator.token_type {
+        TokenType::Less | TokenType::LessEqual | TokenType::Greater | TokenType::GreaterEqual => {
+            for operand in [left, right].iter() {
+                match literal_value(operand) {
+                    Some(value) if type_name(value) == "number" => (),
+                    None => (),
+                    Some(value) => {
+                        let message = format!("Comparison '{}' against non-number literal {} always fails", operator.lexeme, describe(value));
+                        state.warn(Rule::MismatchedLiteralComparison, operator.line, message);
+                    },
+                }
+            }
+        },
+        TokenType::EqualEqual | TokenType::BangEqual => {
+            if let (Some(l), Some(r)) = (literal_value(left), literal_value(right)) {
+                if type_name(l) != type_name(r) {
+                    let message = format!("Comparison '{}' between {} and {} is always {}",
+                                          operator.lexeme, describe(l), describe(r),
+                                          operator.token_type == TokenType::BangEqual);
+                    state.warn(Rule::MismatchedLiteralComparison, operator.line, message);
+                }
+            }
+        },
+        _ => (),
+    }
+}
+
+fn literal_value(expr: &Expr) -> Option<&Value> {
+    match expr {
+        &Expr::Literal { ref value } => Some(&**value),
+        &Expr::Grouping { ref expression } => literal_value(expression),
+        _ => None,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match *value {
+        Value::Nil => "nil",
+        Value::Str(_) => "string",
+        Value::Number(_) | Value::Integer(_) | Value::BigInt(_) | Value::Decimal(_) => "number",
+        Value::Boolean(_) => "boolean",
+        Value::List(_) => "list",
+        Value::Map(_) => "map",
+        Value::Range(_) => "range",
+        _ => "value",
+    }
+}
+
+fn describe(value: &Value) -> String {
+    match *value {
+        Value::Str(ref s) => format!("string \"{}\"", s),
+        Value::Nil => String::from("nil"),
+        _ => format!("{} {}", type_name(value), value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parse;
+    use scanner::scan;
+
+    fn lint_source(source: &str, config: &Config) -> Vec<(Rule, u32)> {
+        let stmts = scan(&String::from(source)).map_err(|e| vec![e]).and_then(parse).unwrap();
+        lint(&stmts, config).into_iter().map(|w| (w.rule, w.line)).collect()
+    }
+
+    #[test]
+    fn reports_unused_and_shadowed_locals() {
+        let source = "var a = 1;\n{\n  var a = 2;\n  var b = 3;\n  print a;\n}\n";
+        assert_eq!(
+            vec![(Rule::UnusedVariable, 4)],
+            lint_source(source, &Config::new())
+        );
+        let source = "{\n  var a = 1;\n  {\n    var a = 2;\n    print a;\n  }\n}\n";
+        assert_eq!(
+            vec![(Rule::ShadowedVariable, 4), (Rule::UnusedVariable, 2)],
+            lint_source(source, &Config::new())
+        );
+        // unused parameters are fine, but unused locals in a body aren't
+        let source = "fun f(a, b) {\n  var c = a;\n  return (x) => a;\n}\n";
+        assert_eq!(
+            vec![(Rule::UnusedVariable, 2)],
+            lint_source(source, &Config::new())
+        )
+    }
+
+    #[test]
+    fn reports_conditions_comparisons_and_empty_blocks() {
+        let source = "var a = 1;\nif ((a = 2)) {}\nprint a < \"x\";\nprint 1 == \"1\";\n";
+        assert_eq!(
+            vec![(Rule::AssignmentInCondition, 2), (Rule::EmptyBlock, 2), (Rule::MismatchedLiteralComparison, 3), (Rule::MismatchedLiteralComparison, 4)],
+            lint_source(source, &Config::new())
+        )
+    }
+
+    #[test]
+    fn reports_code_after_a_loop_that_never_ends() {
+        let source = "while (true) print 1;\nprint 2;\nprint 3;\n{\n  for (;;) print 4;\n  print 5;\n}\n{\n  while (false) print 6;\n  print 7;\n}\n";
+        assert_eq!(
+            vec![(Rule::UnreachableCode, 2), (Rule::UnreachableCode, 6)],
+            lint_source(source, &Config::new())
+        );
+        let mut config = Config::new();
+        config.disable(Rule::UnreachableCode);
+        assert!(lint_source(source, &config).is_empty())
+    }
+
+    #[test]
+    fn reports_code_after_a_return_or_throw() {
+        let source = "fun k() { return; print \"x\"; }\nfun f(a) {\n  if (a) { return 1; } else { throw \"no\"; }\n  print a;\n  print a;\n}\nfun g(a) {\n  if (a) return 1;\n  return 2;\n}\n";
+        assert_eq!(
+            vec![(Rule::UnreachableCode, 1), (Rule::UnreachableCode, 4)],
+            lint_source(source, &Config::new())
+        );
+        let mut config = Config::new();
+        config.disable(Rule::UnreachableCode);
+        assert!(lint_source(source, &config).is_empty())
+    }
+
+    #[test]
+    fn disabled_rules_are_not_reported() {
+        let mut config = Config::new();
+        config.disable(Rule::EmptyBlock);
+        assert!(lint_source("{}", &config).is_empty())
+    }
+}
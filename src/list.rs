@@ -0,0 +1,129 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use callable::NativeFunction;
+use value::Value;
+
+pub fn new(elements: Vec<Rc<Value>>) -> Value {
+    Value::List(Rc::new(RefCell::new(elements)))
+}
+
+/// Looks up one of the methods every list has, such as `push`.
+pub fn method(name: &str) -> Option<NativeFunction> {
+    match name {
+        "length" | "len" => Some(NativeFunction::new(name, 0, length)),
+        "push" => Some(NativeFunction::new(name, 1, push)),
+        "pop" => Some(NativeFunction::new(name, 0, pop)),
+        "insert" => Some(NativeFunction::new(name, 2, insert)),
+        "remove" => Some(NativeFunction::new(name, 1, remove)),
+        "slice" => Some(NativeFunction::new(name, 2, slice)),
+        _ => None,
+    }
+}
+
+/// Turns a Lox index into a position in a list (or string) of the given
+/// length.  Negative indices count back from the end.
+pub fn index(value: &Value, length: usize) -> Result<usize, String> {
+    let i = integer(value)?;
+    let position = if i < 0 { i + length as i64 } else { i };
+    if position < 0 || position >= length as i64 {
+        Err(format!("Index {} is out of bounds for length {}", i, length))
+    } else {
+        Ok(position as usize)
+    }
+}
+
+/// Like `index()`, but for the bounds of a slice, which are clamped to the
+/// list rather than being errors.
+pub fn bound(value: &Value, length: usize) -> Result<usize, String> {
+    let i = integer(value)?;
+    let position = if i < 0 { i + length as i64 } else { i };
+    Ok(position.max(0).min(length as i64) as usize)
+}
+
+fn integer(value: &Value) -> Result<i64, String> {
+    match *value {
+        Value::Integer(i) => Ok(i),
+        Value::Number(n) if n.fract() == 0.0 => Ok(n as i64),
+        Value::Number(n) => Err(format!("Index must be a whole number, not {}", n)),
+        Value::BigInt(ref n) => Err(format!("Index {} is out of bounds", n)),
+        _ => Err(format!("Index must be a number, not {}", value)),
+    }
+}
+
+fn elements(value: &Value) -> &Rc<RefCell<Vec<Rc<Value>>>> {
+    match *value {
+        Value::List(ref elements) => elements,
+        _ => panic!("BUG: list method called on {}", value),
+    }
+}
+
+fn length(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let length = elements(&arguments[0]).borrow().len();
+    Ok(Rc::new(Value::Integer(length as i64)))
+}
+
+fn push(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    elements(&arguments[0]).borrow_mut().push(Rc::clone(&arguments[1]));
+    Ok(Rc::new(Value::Nil))
+}
+
+fn pop(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    elements(&arguments[0]).borrow_mut().pop().ok_or(String::from("Can't pop from an empty list"))
+}
+
+fn insert(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let mut elements = elements(&arguments[0]).borrow_mut();
+    let length = elements.len();
+    // inserting at the end is the same as pushing
+    let position = match *arguments[1] {
+        Value::Integer(i) if i == length as i64 => length,
+        Value::Number(n) if n == length as f64 => length,
+        ref i => index(i, length)?,
+    };
+    elements.insert(position, Rc::clone(&arguments[2]));
+    Ok(Rc::new(Value::Nil))
+}
+
+fn remove(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let mut elements = elements(&arguments[0]).borrow_mut();
+    let position = index(&arguments[1], elements.len())?;
+    Ok(elements.remove(position))
+}
+
+fn slice(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let elements = elements(&arguments[0]).borrow();
+    let start = bound(&arguments[1], elements.len())?;
+    let end = bound(&arguments[2], elements.len())?;
+    let sliced = if start < end { elements[start..end].to_vec() } else { Vec::new() };
+    Ok(Rc::new(new(sliced)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(ns: &[i64]) -> Rc<Value> {
+        Rc::new(new(ns.iter().map(|n| Rc::new(Value::Integer(*n))).collect()))
+    }
+
+    #[test]
+    fn resolves_negative_and_out_of_bounds_indices() {
+        assert_eq!(Ok(2), index(&Value::Integer(-1), 3));
+        assert_eq!(Err(String::from("Index 3 is out of bounds for length 3")), index(&Value::Integer(3), 3));
+        assert_eq!(Err(String::from("Index -4 is out of bounds for length 3")), index(&Value::Integer(-4), 3));
+        assert!(index(&Value::Number(0.5), 3).is_err());
+    }
+
+    #[test]
+    fn methods_modify_the_list() {
+        let xs = numbers(&[1, 2, 3]);
+        let call = |name: &str, arguments: Vec<Rc<Value>>| method(name).unwrap().bind(Rc::clone(&xs)).call(arguments).unwrap();
+        call("push", vec![Rc::new(Value::Integer(4))]);
+        call("insert", vec![Rc::new(Value::Integer(0)), Rc::new(Value::Integer(0))]);
+        assert_eq!(Rc::new(Value::Integer(2)), call("remove", vec![Rc::new(Value::Integer(2))]));
+        assert_eq!(Rc::new(Value::Integer(4)), call("pop", vec![]));
+        assert_eq!(numbers(&[0, 1, 3]), xs);
+        assert_eq!(numbers(&[1, 3]), call("slice", vec![Rc::new(Value::Integer(1)), Rc::new(Value::Integer(10))]));
+    }
+}
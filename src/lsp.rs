@@ -0,0 +1,469 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::prelude::*;
+
+use serde_json;
+use serde_json::Value as Json;
+
+use linter;
+use parser::{parse, ParseError};
+use resolver::{resolve, DeclarationKind, Resolution};
+use scanner::{keywords, scan, ScanError};
+use token::{TokenType, Token};
+use transport::{read_body, write_message};
+
+const SEVERITY_ERROR: u32 = 1;
+const SEVERITY_WARNING: u32 = 2;
+const SYMBOL_KIND_VARIABLE: u32 = 13;
+const COMPLETION_KIND_VARIABLE: u32 = 6;
+const COMPLETION_KIND_KEYWORD: u32 = 14;
+const TEXT_DOCUMENT_SYNC_FULL: u32 = 1;
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INVALID_REQUEST: i64 = -32600;
+
+// LSP positions are zero-based lines and columns counted in UTF-16 code
+// units, whereas tokens know their byte offset in the source.
+struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> LineIndex {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        LineIndex { line_starts }
+    }
+
+    fn position(&self, text: &str, offset: usize) -> Json {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        let character = text[self.line_starts[line]..offset].encode_utf16().count();
+        json!({"line": line, "character": character})
+    }
+
+    fn offset(&self, text: &str, position: &Json) -> Option<usize> {
+        let line = position["line"].as_u64()? as usize;
+        let character = position["character"].as_u64()? as usize;
+        let start = *self.line_starts.get(line)?;
+        let mut units = 0;
+        for (i, c) in text[start..].char_indices() {
+            if units >= character || c == '\n' {
+                return Some(start + i);
+            }
+            units += c.len_utf16();
+        }
+        Some(text.len())
+    }
+
+    // a range covering a whole (one-based) source line, for messages that
+    // only know which line they refer to
+    fn line_range(&self, text: &str, line: u32) -> Json {
+        let index = (line.max(1) as usize - 1).min(self.line_starts.len() - 1);
+        let start = self.line_starts[index];
+        let end = text[start..].find('\n').map(|i| start + i).unwrap_or(text.len());
+        json!({"start": self.position(text, start), "end": self.position(text, end)})
+    }
+}
+
+struct Document {
+    text: String,
+    lines: LineIndex,
+    identifiers: Vec<String>,
+    resolution: Option<Resolution>,
+    diagnostics: Vec<Json>,
+}
+
+impl Document {
+    fn new(text: String) -> Document {
+        let mut document = Document {
+            lines: LineIndex::new(&text),
+            text,
+            identifiers: Vec::new(),
+            resolution: None,
+            diagnostics: Vec::new(),
+        };
+        document.analyze();
+        document
+    }
+
+    fn analyze(&mut self) {
+        let tokens = match scan(&self.text) {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                let diagnostic = match error.downcast_ref::<ScanError>() {
+                    Some(scan_error) => self.diagnostic(self.lines.line_range(&self.text, scan_error.line()), SEVERITY_ERROR, scan_error.message()),
+                    None => self.diagnostic(self.lines.line_range(&self.text, 1), SEVERITY_ERROR, &error.to_string()),
+                };
+                self.diagnostics.push(diagnostic);
+                return;
+            },
+        };
+
+        let mut identifiers: Vec<String> = tokens.iter()
+            .filter(|t| t.token_type == TokenType::Identifier)
+            .map(|t| t.lexeme.clone())
+            .collect();
+        identifiers.sort();
+        identifiers.dedup();
+        self.identifiers = identifiers;
+
+        match parse(tokens) {
+            Ok(statements) => {
+                for warning in linter::lint(&statements, &linter::Config::new()).iter() {
+                    let range = self.lines.line_range(&self.text, warning.line);
+                    let diagnostic = self.diagnostic(range, SEVERITY_WARNING, &warning.message);
+                    self.diagnostics.push(diagnostic);
+                }
+                self.resolution = Some(resolve(&statements));
+            },
+            Err(errors) => for error in errors.iter() {
+                let diagnostic = match error.downcast_ref::<ParseError>() {
+                    Some(parse_error) => {
+                        let range = self.range(parse_error.token());
+                        self.diagnostic(range, SEVERITY_ERROR, parse_error.message())
+                    },
+                    None => {
+                        let range = self.lines.line_range(&self.text, 1);
+                        self.diagnostic(range, SEVERITY_ERROR, &error.to_string())
+                    },
+                };
+                self.diagnostics.push(diagnostic);
+            },
+        }
+    }
+
+    fn diagnostic(&self, range: Json, severity: u32, message: &str) -> Json {
+        json!({"range": range, "severity": severity, "source": "rlox", "message": message})
+    }
+
+    fn range(&self, token: &Token) -> Json {
+        let end = token.end_offset().min(self.text.len());
+        let start = token.offset.min(end);
+        json!({"start": self.lines.position(&self.text, start), "end": self.lines.position(&self.text, end)})
+    }
+
+    fn declaration_at(&self, position: &Json) -> Option<usize> {
+        let offset = self.lines.offset(&self.text, position)?;
+        self.resolution.as_ref()?.declaration_at(offset)
+    }
+}
+
+struct Server {
+    documents: HashMap<String, Document>,
+}
+
+type RequestResult = Result<Json, (i64, String)>;
+
+/// Runs a language server, reading JSON-RPC messages from `input` and
+/// writing responses and notifications to `output`, until the client sends
+/// `exit` or closes the input.
+pub fn serve<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<()> {
+    let mut server = Server {
+        documents: HashMap::new(),
+    };
+
+    while let Some(body) = read_body(&mut input)? {
+        // the client can't be told which request it was, so the id is null
+        let message: Json = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(error) => {
+                let response = json!({"jsonrpc": "2.0", "id": null, "error": {"code": PARSE_ERROR, "message": error.to_string()}});
+                write_message(&mut output, &response)?;
+                continue;
+            },
+        };
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        match message.get("id") {
+            // a response to a request of ours, none of which need answers
+            Some(_) if message.get("method").is_none() && (message.get("result").is_some() || message.get("error").is_some()) => (),
+            Some(id) => {
+                let response = match server.handle_request(method, params) {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                    Err((code, error)) => json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": error}}),
+                };
+                write_message(&mut output, &response)?;
+            },
+            None if method == "exit" => break,
+            None => for notification in server.handle_notification(method, params).iter() {
+                write_message(&mut output, notification)?;
+            },
+        }
+    }
+    Ok(())
+}
+
+impl Server {
+    fn handle_request(&mut self, method: &str, params: &Json) -> RequestResult {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": TEXT_DOCUMENT_SYNC_FULL,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": {"name": "rlox"},
+            })),
+            "shutdown" => Ok(Json::Null),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/references" => self.references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            "textDocument/completion" => self.completion(params),
+            "" => Err((INVALID_REQUEST, String::from("Missing method"))),
+            _ => Err((METHOD_NOT_FOUND, format!("Unsupported method '{}'", method))),
+        }
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = match params["textDocument"]["uri"].as_str() {
+            Some(uri) => uri.to_string(),
+            None => return vec![],
+        };
+        let text = match method {
+            "textDocument/didOpen" => params["textDocument"]["text"].as_str(),
+            // we only advertise full-document sync, so the last change has
+            // the complete text
+            "textDocument/didChange" => params["contentChanges"].as_array()
+                .and_then(|changes| changes.last())
+                .and_then(|change| change["text"].as_str()),
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![publish_diagnostics(&uri, &vec![])];
+            },
+            _ => None,
+        };
+
+        match text {
+            Some(text) => {
+                let document = Document::new(text.to_string());
+                let notification = publish_diagnostics(&uri, &document.diagnostics);
+                self.documents.insert(uri, document);
+                vec![notification]
+            },
+            None => vec![],
+        }
+    }
+
+    fn document<'a>(&'a self, params: &'a Json) -> Result<(&'a str, &'a Document), (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str()
+            .ok_or((INVALID_PARAMS, String::from("Missing textDocument.uri")))?;
+        self.documents.get(uri)
+            .map(|document| (uri, document))
+            .ok_or((INVALID_PARAMS, format!("Unknown document '{}'", uri)))
+    }
+
+    fn definition(&self, params: &Json) -> RequestResult {
+        let (uri, document) = self.document(params)?;
+        Ok(match document.declaration_at(&params["position"]) {
+            Some(index) => {
+                let declaration = &document.resolution.as_ref().unwrap().declarations[index];
+                json!({"uri": uri, "range": document.range(&declaration.name)})
+            },
+            None => Json::Null,
+        })
+    }
+
+    fn references(&self, params: &Json) -> RequestResult {
+        let (uri, document) = self.document(params)?;
+        let index = match document.declaration_at(&params["position"]) {
+            Some(index) => index,
+            None => return Ok(Json::Null),
+        };
+        let resolution = document.resolution.as_ref().unwrap();
+        let mut names: Vec<&Token> = Vec::new();
+        if params["context"]["includeDeclaration"].as_bool().unwrap_or(true) {
+            names.push(&resolution.declarations[index].name);
+        }
+        names.extend(resolution.references_to(index).iter().map(|r| &r.name));
+        Ok(Json::Array(names.iter().map(|name| json!({"uri": uri, "range": document.range(name)})).collect()))
+    }
+
+    fn hover(&self, params: &Json) -> RequestResult {
+        let (_, document) = self.document(params)?;
+        Ok(match document.declaration_at(&params["position"]) {
+            Some(index) => {
+                let declaration = &document.resolution.as_ref().unwrap().declarations[index];
+                let scope = if declaration.depth == 0 { "global" } else { "local" };
+                let (signature, description) = match declaration.kind {
+                    DeclarationKind::Variable => (format!("var {}", declaration.name.lexeme), format!("{} variable", scope)),
+                    DeclarationKind::Function => (format!("fun {}", declaration.name.lexeme), format!("{} function", scope)),
+                    DeclarationKind::Parameter => (declaration.name.lexeme.clone(), String::from("parameter")),
+                    DeclarationKind::Import => (format!("import {}", declaration.name.lexeme), format!("{} import", scope)),
+                };
+                let value = format!("```lox\n{}\n```\n{} declared on line {}", signature, description, declaration.name.line);
+                json!({"contents": {"kind": "markdown", "value": value}})
+            },
+            None => Json::Null,
+        })
+    }
+
+    fn document_symbols(&self, params: &Json) -> RequestResult {
+        let (uri, document) = self.document(params)?;
+        let symbols = match document.resolution {
+            Some(ref resolution) => resolution.declarations.iter().map(|declaration| json!({
+                "name": declaration.name.lexeme,
+                "kind": SYMBOL_KIND_VARIABLE,
+                "location": {"uri": uri, "range": document.range(&declaration.name)},
+            })).collect(),
+            None => vec![],
+        };
+        Ok(Json::Array(symbols))
+    }
+
+    fn completion(&self, params: &Json) -> RequestResult {
+        let (_, document) = self.document(params)?;
+        let mut items: Vec<Json> = keywords().iter()
+            .map(|keyword| json!({"label": keyword, "kind": COMPLETION_KIND_KEYWORD}))
+            .collect();
+        items.extend(document.identifiers.iter().map(|identifier| json!({"label": identifier, "kind": COMPLETION_KIND_VARIABLE})));
+        Ok(Json::Array(items))
+    }
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: &Vec<Json>) -> Json {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics},
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use transport::read_message;
+
+    const URI: &'static str = "file:///test.lox";
+
+    // plays a scripted session against the server, returning everything it
+    // sent back
+    fn run_session(messages: Vec<Json>) -> Vec<Json> {
+        let mut input = Vec::new();
+        for message in messages.iter() {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut output = Vec::new();
+        serve(Cursor::new(input), &mut output).unwrap();
+
+        let mut reader = Cursor::new(output);
+        let mut replies = Vec::new();
+        while let Some(reply) = read_message(&mut reader).unwrap() {
+            replies.push(reply);
+        }
+        replies
+    }
+
+    fn open(text: &str) -> Json {
+        json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+            "textDocument": {"uri": URI, "languageId": "lox", "version": 1, "text": text},
+        }})
+    }
+
+    fn request(id: u32, method: &str, line: u32, character: u32) -> Json {
+        json!({"jsonrpc": "2.0", "id": id, "method": method, "params": {
+            "textDocument": {"uri": URI},
+            "position": {"line": line, "character": character},
+            "context": {"includeDeclaration": true},
+        }})
+    }
+
+    fn range(line: u32, start: u32, end: u32) -> Json {
+        json!({"start": {"line": line, "character": start}, "end": {"line": line, "character": end}})
+    }
+
+    #[test]
+    fn publishes_diagnostics() {
+        let replies = run_session(vec![open("var a = ;\n{ var b = 1; }\n")]);
+        let diagnostics = &replies[0]["params"]["diagnostics"];
+        assert_eq!(range(0, 8, 9), diagnostics[0]["range"]);
+        assert_eq!(SEVERITY_ERROR, diagnostics[0]["severity"]);
+
+        let replies = run_session(vec![open("{ var b = 1; }\n")]);
+        let diagnostics = &replies[0]["params"]["diagnostics"];
+        assert_eq!(SEVERITY_WARNING, diagnostics[0]["severity"]);
+
+        let replies = run_session(vec![open("var a = 1;\nvar b = 0x;\n")]);
+        let diagnostics = &replies[0]["params"]["diagnostics"];
+        assert_eq!(1, diagnostics[0]["range"]["start"]["line"]);
+        assert_eq!("Malformed number '0x'", diagnostics[0]["message"]);
+    }
+
+    #[test]
+    fn finds_definitions_and_references() {
+        let replies = run_session(vec![
+            open("var a = 1;\n{\n  var a = a + 1;\n  print a;\n}\nprint a;\n"),
+            request(1, "textDocument/definition", 3, 8),
+            request(2, "textDocument/references", 0, 4),
+            request(3, "textDocument/hover", 5, 6),
+            json!({"jsonrpc": "2.0", "id": 4, "method": "shutdown"}),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+        ]);
+
+        assert_eq!(range(2, 6, 7), replies[1]["result"]["range"]);
+        let references: Vec<&Json> = replies[2]["result"].as_array().unwrap().iter().map(|l| &l["range"]).collect();
+        assert_eq!(vec![&range(0, 4, 5), &range(2, 10, 11), &range(5, 6, 7)], references);
+        assert!(replies[3]["result"]["contents"]["value"].as_str().unwrap().contains("global variable declared on line 1"));
+        assert_eq!(Json::Null, replies[4]["result"]);
+    }
+
+    #[test]
+    fn lists_symbols_and_completions() {
+        let replies = run_session(vec![
+            open("var alpha = 1;\nvar beta = alpha;\n"),
+            request(1, "textDocument/documentSymbol", 0, 0),
+            request(2, "textDocument/completion", 1, 0),
+        ]);
+
+        let symbols: Vec<&Json> = replies[1]["result"].as_array().unwrap().iter().map(|s| &s["name"]).collect();
+        assert_eq!(vec!["alpha", "beta"], symbols);
+        let labels: Vec<&str> = replies[2]["result"].as_array().unwrap().iter().map(|c| c["label"].as_str().unwrap()).collect();
+        assert!(labels.contains(&"while"));
+        assert!(labels.contains(&"alpha"));
+    }
+
+    #[test]
+    fn describes_what_each_name_was_declared_as() {
+        let replies = run_session(vec![
+            open("fun add(a, b) {\n  return a + b;\n}\nprint add(1, 2);\n"),
+            request(1, "textDocument/hover", 3, 7),
+            request(2, "textDocument/hover", 1, 9),
+        ]);
+
+        assert_eq!("```lox\nfun add\n```\nglobal function declared on line 1", replies[1]["result"]["contents"]["value"]);
+        assert_eq!("```lox\na\n```\nparameter declared on line 1", replies[2]["result"]["contents"]["value"]);
+    }
+
+    #[test]
+    fn rejects_unknown_methods() {
+        let replies = run_session(vec![json!({"jsonrpc": "2.0", "id": 1, "method": "bogus"})]);
+        assert_eq!(METHOD_NOT_FOUND, replies[0]["error"]["code"]);
+    }
+
+    #[test]
+    fn reports_malformed_json_and_keeps_serving() {
+        let mut input = Vec::new();
+        input.extend_from_slice(b"Content-Length: 5\r\n\r\n{oops");
+        write_message(&mut input, &json!({"jsonrpc": "2.0", "id": 7, "result": {}})).unwrap();
+        write_message(&mut input, &json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"})).unwrap();
+        let mut output = Vec::new();
+        serve(Cursor::new(input), &mut output).unwrap();
+
+        let mut reader = Cursor::new(output);
+        let parse_error = read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(PARSE_ERROR, parse_error["error"]["code"]);
+        assert_eq!(Json::Null, parse_error["id"]);
+        // the client's response to us gets no reply of its own
+        assert_eq!(json!(1), read_message(&mut reader).unwrap().unwrap()["id"]);
+        assert_eq!(None, read_message(&mut reader).unwrap());
+    }
+}
@@ -2,23 +2,135 @@ extern crate rlox;
 
 use std::cell::RefCell;
 use std::env;
+use std::error::Error;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
+use std::io::IsTerminal;
+use std::path::PathBuf;
 use std::process;
 use std::rc::Rc;
 
+use rlox::debugger::ConsoleDebugger;
 use rlox::environment::Environment;
+use rlox::interpreter::{interpret_with_debugger, spawn_with_stack, RuntimeError};
+use rlox::linter;
+use rlox::module::Loader;
+
+enum Command {
+    Run { source_filename: Option<String>, module_path: Vec<PathBuf>, script_args: Vec<String> },
+    Format { check: bool, filenames: Vec<String> },
+    Lint { json: bool, config: linter::Config, filenames: Vec<String> },
+    LanguageServer,
+    DebugAdapter,
+    Highlight { html: bool, source_filename: String },
+    Debug { source_filename: String, module_path: Vec<PathBuf>, script_args: Vec<String> },
+}
 
 struct Arguments {
-    source_filename: Option<String>,
+    command: Command,
 }
 
 impl Arguments {
     fn new(mut args: env::Args) -> Result<Arguments, &'static str> {
         args.next();
-        let source_filename = args.next();
-        Ok(Arguments { source_filename })
+        let command = match args.next() {
+            Some(ref subcommand) if subcommand == "fmt" => {
+                let mut check = false;
+                let mut filenames = Vec::new();
+                for arg in args {
+                    match arg.as_str() {
+                        "--check" => check = true,
+                        s if s.starts_with("-") => return Err("unknown option for 'fmt'"),
+                        _ => filenames.push(arg),
+                    }
+                }
+                Command::Format { check, filenames }
+            },
+            Some(ref subcommand) if subcommand == "lint" => {
+                let mut json = false;
+                let mut config = linter::Config::new();
+                let mut filenames = Vec::new();
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "--json" => json = true,
+                        "--enable" | "--disable" => {
+                            let rule = args.next().and_then(|name| linter::Rule::from_name(&name))
+                                .ok_or("'--enable' and '--disable' require a rule name")?;
+                            if arg == "--enable" {
+                                config.enable(rule);
+                            } else {
+                                config.disable(rule);
+                            }
+                        },
+                        s if s.starts_with("-") => return Err("unknown option for 'lint'"),
+                        _ => filenames.push(arg),
+                    }
+                }
+                if filenames.is_empty() {
+                    return Err("'lint' requires at least one file");
+                }
+                Command::Lint { json, config, filenames }
+            },
+            Some(ref subcommand) if subcommand == "lsp" => Command::LanguageServer,
+            Some(ref subcommand) if subcommand == "dap" => Command::DebugAdapter,
+            Some(ref subcommand) if subcommand == "debug" => {
+                let mut source_filename = None;
+                let mut module_path = Vec::new();
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "--module-path" => {
+                            let dirs = args.next().ok_or("'--module-path' requires a list of directories")?;
+                            module_path.extend(env::split_paths(&dirs));
+                        },
+                        s if s.starts_with("-") => return Err("unknown option for 'debug'"),
+                        _ => {
+                            source_filename = Some(arg);
+                            break;
+                        },
+                    }
+                }
+                let source_filename = source_filename.ok_or("'debug' requires a file")?;
+                let script_args = args.collect();
+                Command::Debug { source_filename, module_path, script_args }
+            },
+            Some(ref subcommand) if subcommand == "highlight" => {
+                let mut html = false;
+                let mut source_filename = None;
+                for arg in args {
+                    match arg.as_str() {
+                        "--html" => html = true,
+                        s if s.starts_with("-") => return Err("unknown option for 'highlight'"),
+                        _ => source_filename = Some(arg),
+                    }
+                }
+                let source_filename = source_filename.ok_or("'highlight' requires a file")?;
+                Command::Highlight { html, source_filename }
+            },
+            first => {
+                let mut source_filename = None;
+                let mut module_path = Vec::new();
+                let mut next = first;
+                while let Some(arg) = next {
+                    match arg.as_str() {
+                        "--module-path" => {
+                            let dirs = args.next().ok_or("'--module-path' requires a list of directories")?;
+                            module_path.extend(env::split_paths(&dirs));
+                        },
+                        s if s.starts_with("-") => return Err("unknown option"),
+                        _ => {
+                            source_filename = Some(arg);
+                            break;
+                        },
+                    }
+                    next = args.next();
+                }
+                // anything after the script is for the script
+                let script_args = args.collect();
+                Command::Run { source_filename, module_path, script_args }
+            },
+        };
+        Ok(Arguments { command })
     }
 }
 
@@ -35,9 +147,194 @@ fn main() {
         process::exit(1);
     });
 
+    // the interpreter recurses for every call, so it needs a much bigger
+    // stack than the main thread's
+    let command = arguments.command;
+    let interpreter = spawn_with_stack(move || execute(command)).unwrap_or_else(|err| {
+        eprintln!("Failed to start the interpreter: {}", err);
+        process::exit(1);
+    });
+    // the panic has already been reported by the thread
+    if interpreter.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn execute(command: Command) {
+    match command {
+        Command::Run { source_filename, module_path, script_args } => run(source_filename, module_path, script_args),
+        Command::Format { check, filenames } => format(check, filenames),
+        Command::Lint { json, config, filenames } => lint(json, config, filenames),
+        Command::LanguageServer => {
+            let stdin = io::stdin();
+            if let Err(err) = rlox::lsp::serve(stdin.lock(), io::stdout()) {
+                eprintln!("Language server failed: {}", err);
+                process::exit(1);
+            }
+        },
+        Command::DebugAdapter => {
+            let stdin = io::stdin();
+            if let Err(err) = rlox::dap::serve(stdin.lock(), io::stdout()) {
+                eprintln!("Debug adapter failed: {}", err);
+                process::exit(1);
+            }
+        },
+        Command::Highlight { html, source_filename } => highlight(html, source_filename),
+        Command::Debug { source_filename, module_path, script_args } => debug(source_filename, module_path, script_args),
+    }
+}
+
+fn format(check: bool, filenames: Vec<String>) {
+    if filenames.is_empty() {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).unwrap_or_else(|err| {
+            eprintln!("Failed to read from stdin: {}", err);
+            process::exit(1);
+        });
+        match rlox::formatter::format(&source) {
+            Ok(formatted) => if !check {
+                print!("{}", formatted);
+            } else if formatted != source {
+                process::exit(1);
+            },
+            Err(errors) => {
+                for error in errors.iter() {
+                    eprintln!("<stdin>: {}", error);
+                }
+                process::exit(1);
+            },
+        }
+        return;
+    }
+
+    let mut failed = false;
+    for filename in filenames.iter() {
+        let source = match read_source_file(filename) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("Failed to read file '{}': {}", filename, err);
+                failed = true;
+                continue;
+            },
+        };
+        match rlox::formatter::format(&source) {
+            Ok(ref formatted) if *formatted == source => (),
+            Ok(_) if check => {
+                println!("{}", filename);
+                failed = true;
+            },
+            Ok(formatted) => if let Err(err) = File::create(filename).and_then(|mut f| f.write_all(formatted.as_bytes())) {
+                eprintln!("Failed to write file '{}': {}", filename, err);
+                failed = true;
+            },
+            Err(errors) => {
+                for error in errors.iter() {
+                    eprintln!("{}: {}", filename, error);
+                }
+                failed = true;
+            },
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn lint(json: bool, config: linter::Config, filenames: Vec<String>) {
+    let mut failed = false;
+    for filename in filenames.iter() {
+        let source = match read_source_file(filename) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("Failed to read file '{}': {}", filename, err);
+                failed = true;
+                continue;
+            },
+        };
+        match rlox::scanner::scan(&source).map_err(|error| vec![error]).and_then(rlox::parser::parse) {
+            Ok(statements) => for warning in linter::lint(&statements, &config).iter() {
+                failed = true;
+                if json {
+                    println!("{}", warning.to_json(filename));
+                } else {
+                    println!("{}: {}", filename, warning);
+                }
+            },
+            Err(errors) => {
+                for error in errors.iter() {
+                    eprintln!("{}: {}", filename, error);
+                }
+                failed = true;
+            },
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn highlight(html: bool, source_filename: String) {
+    let source = read_source_file(&source_filename).unwrap_or_else(|err| {
+        eprintln!("Failed to read file '{}': {}", source_filename, err);
+        process::exit(1);
+    });
+    let highlighted = if html {
+        rlox::highlight::to_html(&source).map(|html| format!("{}\n", html))
+    } else {
+        rlox::highlight::to_ansi(&source)
+    };
+    match highlighted {
+        Ok(highlighted) => print!("{}", highlighted),
+        Err(err) => {
+            eprintln!("{}: {}", source_filename, err);
+            process::exit(1);
+        },
+    }
+}
+
+fn debug(source_filename: String, module_path: Vec<PathBuf>, script_args: Vec<String>) {
+    let source = read_source_file(&source_filename).unwrap_or_else(|err| {
+        eprintln!("Failed to read file '{}': {}", source_filename, err);
+        process::exit(1);
+    });
+    let statements = rlox::scanner::scan(&source).map_err(|error| vec![error]).and_then(rlox::parser::parse).unwrap_or_else(|errors| {
+        for error in errors.iter() {
+            eprintln!("{}", error);
+        }
+        process::exit(1);
+    });
+
+    println!("Debugging {}; type 'help' for a list of commands.", source_filename);
+    let stdin = io::stdin();
+    let mut debugger = ConsoleDebugger::new(&source, stdin.lock(), io::stdout());
+    let environment = Rc::new(RefCell::new(Environment::new()));
+    let loader = Rc::new(RefCell::new(Loader::new(module_path)));
+    loader.borrow_mut().set_args(script_args);
+    match interpret_with_debugger(environment, statements, Some(&source_filename), loader, &mut debugger) {
+        Ok(_) => println!("Program finished."),
+        Err(error) => {
+            print_error(&error);
+            process::exit(1);
+        },
+    }
+}
+
+/// Prints an error, followed by its stack trace if it happened at runtime.
+fn print_error(error: &Box<Error>) {
+    eprintln!("{}", error);
+    if let Some(runtime_error) = error.downcast_ref::<RuntimeError>() {
+        for frame in runtime_error.trace().iter() {
+            eprintln!("{}", frame);
+        }
+    }
+}
+
+fn run(source_filename: Option<String>, module_path: Vec<PathBuf>, script_args: Vec<String>) {
     let environment = Rc::new(RefCell::new(Environment::new()));
+    let loader = Rc::new(RefCell::new(Loader::new(module_path)));
+    loader.borrow_mut().set_args(script_args);
 
-    match arguments.source_filename {
+    match source_filename {
         Some(source_filename) => {
             println!("Running Lox file {}", source_filename);
 
@@ -45,13 +342,18 @@ fn main() {
                 eprintln!("Failed to read file '{}': {}", source_filename, err);
                 process::exit(1);
             });
-            println!("Running Lox source\n{}", source);
+            if io::stdout().is_terminal() {
+                let highlighted = rlox::highlight::to_ansi(&source).unwrap_or(source.clone());
+                println!("Running Lox source\n{}", highlighted);
+            } else {
+                println!("Running Lox source\n{}", source);
+            }
 
-            match rlox::run(environment, &source) {
+            match rlox::run_file(environment, &source, &source_filename, &loader) {
                 Ok(_) => (),
                 Err(errors) => {
                     for error in errors.iter() {
-                        eprintln!("{}", error);
+                        print_error(error);
                     }
                     process::exit(1);
                 },
@@ -63,10 +365,10 @@ fn main() {
             io::stdout().flush().unwrap();
             for line in stdin.lock().lines() {
                 match line {
-                    Ok(source) => match rlox::run(Rc::clone(&environment), &source) {
+                    Ok(source) => match rlox::run(Rc::clone(&environment), &source, &loader) {
                         Ok(_) => (),
                         Err(errors) => for error in errors.iter() {
-                            eprintln!("{}", error);
+                            print_error(error);
                         },
                     },
                     Err(e) => {
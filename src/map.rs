@@ -0,0 +1,201 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, ToPrimitive};
+
+use callable::NativeFunction;
+use list;
+use value::Value;
+
+/// The kinds of value that can be used as map keys.  Whole floats and
+/// decimals are keyed as integers, so that `1`, `1.0` and `1.00d` are the
+/// same key; other floats are keyed on their bits.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    BigInt(BigInt),
+    Decimal(BigDecimal),
+    Number(u64),
+    Str(String),
+}
+
+impl Key {
+    pub fn from_value(value: &Value) -> Result<Key, String> {
+        match *value {
+            Value::Nil => Ok(Key::Nil),
+            Value::Boolean(b) => Ok(Key::Boolean(b)),
+            Value::Number(n) if n.is_nan() => Err(String::from("NaN can't be used as a map key")),
+            Value::Number(n) if n.fract() == 0.0 => Ok(integer_key(BigInt::from_f64(n).unwrap())),
+            Value::Integer(i) => Ok(Key::Integer(i)),
+            Value::BigInt(ref n) => Ok(Key::BigInt(n.clone())),
+            Value::Decimal(ref d) if d.is_integer() => Ok(integer_key(d.with_scale(0).into_bigint_and_exponent().0)),
+            Value::Decimal(ref d) => Ok(Key::Decimal(d.normalized())),
+            Value::Number(n) => Ok(Key::Number(n.to_bits())),
+            Value::Str(ref s) => Ok(Key::Str(s.clone())),
+            _ => Err(format!("Only strings, numbers, booleans and nil can be map keys, not {}", value)),
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match *self {
+            Key::Nil => Value::Nil,
+            Key::Boolean(b) => Value::Boolean(b),
+            Key::Integer(i) => Value::Integer(i),
+            Key::BigInt(ref n) => Value::BigInt(n.clone()),
+            Key::Decimal(ref d) => Value::Decimal(d.clone()),
+            Key::Number(bits) => Value::Number(f64::from_bits(bits)),
+            Key::Str(ref s) => Value::Str(s.clone()),
+        }
+    }
+}
+
+fn integer_key(n: BigInt) -> Key {
+    match n.to_i64() {
+        Some(i) => Key::Integer(i),
+        None => Key::BigInt(n),
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Key::Str(ref s) => write!(f, "\"{}\"", s),
+            ref key => write!(f, "{}", key.to_value()),
+        }
+    }
+}
+
+/// A hash map that remembers the order its keys were first inserted in, so
+/// that printing and iterating over it is predictable.
+#[derive(Debug, Clone)]
+pub struct LoxMap {
+    entries: Vec<(Key, Rc<Value>)>,
+    positions: HashMap<Key, usize>,
+}
+
+impl LoxMap {
+    pub fn new() -> LoxMap {
+        LoxMap {
+            entries: Vec::new(),
+            positions: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, key: &Key) -> Option<Rc<Value>> {
+        self.positions.get(key).map(|&i| Rc::clone(&self.entries[i].1))
+    }
+
+    pub fn insert(&mut self, key: Key, value: Rc<Value>) {
+        match self.positions.get(&key) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            },
+        }
+    }
+
+    pub fn remove(&mut self, key: &Key) -> Option<Rc<Value>> {
+        self.positions.remove(key).map(|i| {
+            let (_, value) = self.entries.remove(i);
+            for position in self.positions.values_mut() {
+                if *position > i {
+                    *position -= 1;
+                }
+            }
+            value
+        })
+    }
+
+    pub fn entries(&self) -> &Vec<(Key, Rc<Value>)> {
+        &self.entries
+    }
+}
+
+pub fn new(map: LoxMap) -> Value {
+    Value::Map(Rc::new(RefCell::new(map)))
+}
+
+/// Looks up one of the methods every map has, such as `has`.
+pub fn method(name: &str) -> Option<NativeFunction> {
+    match name {
+        "length" | "len" => Some(NativeFunction::new(name, 0, length)),
+        "has" => Some(NativeFunction::new(name, 1, has)),
+        "remove" => Some(NativeFunction::new(name, 1, remove)),
+        "keys" => Some(NativeFunction::new(name, 0, keys)),
+        "values" => Some(NativeFunction::new(name, 0, values)),
+        _ => None,
+    }
+}
+
+fn map(value: &Value) -> &Rc<RefCell<LoxMap>> {
+    match *value {
+        Value::Map(ref map) => map,
+        _ => panic!("BUG: map method called on {}", value),
+    }
+}
+
+fn length(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let length = map(&arguments[0]).borrow().len();
+    Ok(Rc::new(Value::Integer(length as i64)))
+}
+
+fn has(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let key = Key::from_value(&arguments[1])?;
+    let found = map(&arguments[0]).borrow().get(&key).is_some();
+    Ok(Rc::new(Value::Boolean(found)))
+}
+
+fn remove(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let key = Key::from_value(&arguments[1])?;
+    let removed = map(&arguments[0]).borrow_mut().remove(&key);
+    Ok(removed.unwrap_or(Rc::new(Value::Nil)))
+}
+
+fn keys(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let keys = map(&arguments[0]).borrow().entries().iter().map(|&(ref key, _)| Rc::new(key.to_value())).collect();
+    Ok(Rc::new(list::new(keys)))
+}
+
+fn values(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let values = map(&arguments[0]).borrow().entries().iter().map(|&(_, ref value)| Rc::clone(value)).collect();
+    Ok(Rc::new(list::new(values)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_simple_values_are_keys() {
+        assert_eq!(Ok(Key::Integer(0)), Key::from_value(&Value::Number(-0.0)));
+        assert_eq!(Ok(Key::Integer(1)), Key::from_value(&Value::Number(1.0)));
+        assert_eq!(Ok(Key::Integer(1)), Key::from_value(&Value::Decimal(BigDecimal::from(100) / BigDecimal::from(100))));
+        assert!(Key::from_value(&Value::Number(::std::f64::NAN)).is_err());
+        assert!(Key::from_value(&list::new(vec![])).is_err());
+    }
+
+    #[test]
+    fn keeps_insertion_order_across_removals() {
+        let mut m = LoxMap::new();
+        for name in ["a", "b", "c"].iter() {
+            m.insert(Key::Str(String::from(*name)), Rc::new(Value::Nil));
+        }
+        m.remove(&Key::Str(String::from("a")));
+        m.insert(Key::Str(String::from("a")), Rc::new(Value::Boolean(true)));
+        m.insert(Key::Str(String::from("c")), Rc::new(Value::Boolean(false)));
+        let keys: Vec<Value> = m.entries().iter().map(|e| e.0.to_value()).collect();
+        assert_eq!(vec![Value::Str(String::from("b")), Value::Str(String::from("c")), Value::Str(String::from("a"))], keys);
+        assert_eq!(Some(Rc::new(Value::Boolean(false))), m.get(&Key::Str(String::from("c"))));
+    }
+}
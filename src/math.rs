@@ -0,0 +1,229 @@
+use std::cmp::Ordering;
+use std::f64::consts;
+use std::rc::Rc;
+
+use bigdecimal::RoundingMode;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Signed, ToPrimitive};
+
+use callable::{LoxCallable, NativeFunction, NativeFn};
+use module::Module;
+use number;
+use value::Value;
+
+const FUNCTIONS: &'static [(&'static str, usize, NativeFn)] = &[
+    ("sqrt", 1, sqrt),
+    ("pow", 2, pow),
+    ("abs", 1, abs),
+    ("floor", 1, floor),
+    ("ceil", 1, ceil),
+    ("round", 1, round),
+    ("min", 2, min),
+    ("max", 2, max),
+    ("sin", 1, sin),
+    ("cos", 1, cos),
+    ("tan", 1, tan),
+    ("asin", 1, asin),
+    ("acos", 1, acos),
+    ("atan", 1, atan),
+    ("atan2", 2, atan2),
+    ("exp", 1, exp),
+    ("log", 1, log),
+    ("log2", 1, log2),
+    ("log10", 1, log10),
+    ("is_nan", 1, is_nan),
+    ("is_finite", 1, is_finite),
+];
+
+/// The `math` module, imported with `import "math" as math;`.
+pub fn module() -> Module {
+    let mut values: Vec<(&str, Value)> = FUNCTIONS.iter()
+        .map(|&(name, arity, function)| (name, Value::Callable(LoxCallable::Native(NativeFunction::new(name, arity, function)))))
+        .collect();
+    values.push(("pi", Value::Number(consts::PI)));
+    values.push(("e", Value::Number(consts::E)));
+    Module::native("math", values)
+}
+
+fn number(value: &Value) -> Result<&Value, String> {
+    match number::kind(value) {
+        Some(_) => Ok(value),
+        None => Err(format!("Expected a number, not {}", value)),
+    }
+}
+
+fn float(value: &Value) -> Result<f64, String> {
+    number(value)?.as_float().ok_or(format!("{} is too large to be a float", value))
+}
+
+fn new(value: Value) -> Result<Rc<Value>, String> {
+    Ok(Rc::new(value))
+}
+
+// functions that only make sense for floats take any number, but always
+// give a float
+fn float_fn(arguments: Vec<Rc<Value>>, f: fn(f64) -> f64) -> Result<Rc<Value>, String> {
+    new(Value::Number(f(float(&arguments[0])?)))
+}
+
+fn sqrt(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    float_fn(arguments, f64::sqrt)
+}
+
+fn sin(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    float_fn(arguments, f64::sin)
+}
+
+fn cos(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    float_fn(arguments, f64::cos)
+}
+
+fn tan(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    float_fn(arguments, f64::tan)
+}
+
+fn asin(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    float_fn(arguments, f64::asin)
+}
+
+fn acos(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    float_fn(arguments, f64::acos)
+}
+
+fn atan(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    float_fn(arguments, f64::atan)
+}
+
+fn exp(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    float_fn(arguments, f64::exp)
+}
+
+fn log(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    float_fn(arguments, f64::ln)
+}
+
+fn log2(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    float_fn(arguments, f64::log2)
+}
+
+fn log10(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    float_fn(arguments, f64::log10)
+}
+
+fn atan2(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    new(Value::Number(float(&arguments[0])?.atan2(float(&arguments[1])?)))
+}
+
+// an integer raised to a whole power stays exact, as with `**`
+fn pow(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let (base, exponent) = (number(&arguments[0])?, number(&arguments[1])?);
+    match (number::to_bigint(base), number::to_bigint(exponent).and_then(|e| e.to_u32())) {
+        (Some(base), Some(exponent)) => new(number::integer(number::pow(&base, exponent)?)),
+        _ => new(Value::Number(float(base)?.powf(float(exponent)?))),
+    }
+}
+
+fn abs(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    match *number(&arguments[0])? {
+        Value::Integer(i) => new(number::integer(BigInt::from(i).abs())),
+        Value::BigInt(ref n) => new(Value::BigInt(n.abs())),
+        Value::Decimal(ref d) => new(Value::Decimal(d.abs())),
+        ref n => new(Value::Number(n.as_float().unwrap().abs())),
+    }
+}
+
+// rounding gives an integer, except for infinities and NaN, which are left
+// as they are
+fn round_with(value: &Value, mode: RoundingMode, f: fn(f64) -> f64) -> Result<Rc<Value>, String> {
+    match *number(value)? {
+        Value::Decimal(ref d) => {
+            let (rounded, _) = d.with_scale_round(0, mode).as_bigint_and_exponent();
+            new(number::integer(rounded))
+        },
+        Value::Number(n) => match BigInt::from_f64(f(n)) {
+            Some(rounded) => new(number::integer(rounded)),
+            None => new(Value::Number(n)),
+        },
+        ref n => new(n.clone()),
+    }
+}
+
+fn floor(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    round_with(&arguments[0], RoundingMode::Floor, f64::floor)
+}
+
+fn ceil(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    round_with(&arguments[0], RoundingMode::Ceiling, f64::ceil)
+}
+
+// halves round away from zero
+fn round(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    round_with(&arguments[0], RoundingMode::HalfUp, f64::round)
+}
+
+fn min(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let (a, b) = (number(&arguments[0])?, number(&arguments[1])?);
+    if number::compare(b, a) == Some(Some(Ordering::Less)) { new(b.clone()) } else { new(a.clone()) }
+}
+
+fn max(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let (a, b) = (number(&arguments[0])?, number(&arguments[1])?);
+    if number::compare(b, a) == Some(Some(Ordering::Greater)) { new(b.clone()) } else { new(a.clone()) }
+}
+
+fn is_nan(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    match *number(&arguments[0])? {
+        Value::Number(n) => new(Value::Boolean(n.is_nan())),
+        _ => new(Value::Boolean(false)),
+    }
+}
+
+fn is_finite(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    match *number(&arguments[0])? {
+        Value::Number(n) => new(Value::Boolean(n.is_finite())),
+        _ => new(Value::Boolean(true)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use bigdecimal::BigDecimal;
+
+    fn call(name: &str, arguments: Vec<Value>) -> Result<Value, String> {
+        let module = module();
+        match *module.get(name).unwrap() {
+            Value::Callable(LoxCallable::Native(ref f)) => f.call(arguments.into_iter().map(Rc::new).collect()).map(|v| (*v).clone()),
+            ref other => panic!("{} isn't a function: {}", name, other),
+        }
+    }
+
+    #[test]
+    fn keeps_exact_numbers_exact_where_it_can() {
+        assert_eq!(Ok(Value::Integer(1024)), call("pow", vec![Value::Integer(2), Value::Integer(10)]));
+        assert_eq!(Ok(Value::Number(0.5)), call("pow", vec![Value::Integer(2), Value::Integer(-1)]));
+        assert_eq!(Ok(Value::Integer(3)), call("abs", vec![Value::Integer(-3)]));
+        assert_eq!(Ok(Value::Number(2.0)), call("sqrt", vec![Value::Integer(4)]));
+        assert_eq!(Ok(Value::Integer(-2)), call("min", vec![Value::Number(1.5), Value::Integer(-2)]));
+    }
+
+    #[test]
+    fn rounds_to_integers() {
+        assert_eq!(Ok(Value::Integer(-3)), call("floor", vec![Value::Number(-2.5)]));
+        assert_eq!(Ok(Value::Integer(-2)), call("ceil", vec![Value::Number(-2.5)]));
+        assert_eq!(Ok(Value::Integer(-3)), call("round", vec![Value::Number(-2.5)]));
+        assert_eq!(Ok(Value::Integer(3)), call("round", vec![Value::Decimal(BigDecimal::from_str("2.50").unwrap())]));
+        assert!(match call("floor", vec![Value::Number(::std::f64::INFINITY)]) {
+            Ok(Value::Number(n)) => n.is_infinite(),
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn rejects_values_that_arent_numbers() {
+        assert_eq!(Err(String::from("Expected a number, not abc")), call("sqrt", vec![Value::Str(String::from("abc"))]));
+        assert!(call("max", vec![Value::Integer(1), Value::Nil]).is_err());
+        assert_eq!(Err(String::from("7 ** 4294967295 is too large")), call("pow", vec![Value::Integer(7), Value::Integer(4294967295)]));
+    }
+}
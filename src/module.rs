@@ -0,0 +1,264 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use environment::Environment;
+use io;
+use json;
+use math;
+use statement::Stmt;
+use token::{Token, TokenType};
+use value::Value;
+
+/// A module that has been run, with the names it exports.  Exports are
+/// looked up in the module's environment each time, so importers see
+/// changes the module makes to them.
+pub struct Module {
+    path: String,
+    environment: Rc<RefCell<Environment>>,
+    exports: Vec<String>,
+}
+
+impl Module {
+    pub fn new(path: String, environment: Rc<RefCell<Environment>>, statements: &Vec<Stmt>) -> Module {
+        let exports = statements.iter().filter_map(|stmt| match stmt {
+            &Stmt::Export { ref declaration, .. } => match **declaration {
+                Stmt::Var { ref name, .. } | Stmt::Function { ref name, .. } => Some(name.lexeme.clone()),
+                _ => None,
+            },
+            _ => None,
+        }).collect();
+        Module {
+            path,
+            environment,
+            exports,
+        }
+    }
+
+    /// Makes a module implemented in rust, which exports all of its values.
+    pub fn native(name: &str, values: Vec<(&str, Value)>) -> Module {
+        let mut environment = Environment::new();
+        let mut exports = Vec::new();
+        for (name, value) in values.into_iter() {
+            environment.define(String::from(name), Rc::new(value));
+            exports.push(String::from(name));
+        }
+        Module {
+            path: String::from(name),
+            environment: Rc::new(RefCell::new(environment)),
+            exports,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get(&self, name: &str) -> Option<Rc<Value>> {
+        if !self.exports.iter().any(|e| e == name) {
+            return None;
+        }
+        self.environment.borrow().get(&Token::with_lexeme(TokenType::Identifier, String::from(name), 0))
+    }
+}
+
+impl PartialEq for Module {
+    fn eq(&self, other: &Module) -> bool {
+        Rc::ptr_eq(&self.environment, &other.environment)
+    }
+}
+
+impl fmt::Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Module({})", self.path)
+    }
+}
+
+/// Finds module files, and keeps track of the modules that have been
+/// loaded, so each runs only once, and of those still loading, so that
+/// import cycles can be reported.
+pub struct Loader {
+    search_path: Vec<PathBuf>,
+    modules: HashMap<PathBuf, Rc<Module>>,
+    natives: HashMap<String, Rc<Module>>,
+    loading: Vec<PathBuf>,
+    /// The script's command-line arguments, for the `io` module.
+    args: Vec<String>,
+}
+
+impl Loader {
+    /// Makes a loader that looks for modules that aren't found next to the
+    /// importing file in each of the directories of the search path.
+    pub fn new(search_path: Vec<PathBuf>) -> Loader {
+        Loader {
+            search_path,
+            modules: HashMap::new(),
+            natives: HashMap::new(),
+            loading: Vec::new(),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    /// Gets the standard library module with the given name, such as
+    /// `math`, which is imported in place of any file of that name.
+    pub fn native(&mut self, name: &str) -> Option<Rc<Module>> {
+        if let Some(module) = self.natives.get(name) {
+            return Some(Rc::clone(module));
+        }
+        let module = Rc::new(match name {
+            "io" => io::module(&self.args),
+            "json" => json::module(),
+            "math" => math::module(),
+            _ => return None,
+        });
+        self.natives.insert(String::from(name), Rc::clone(&module));
+        Some(module)
+    }
+
+    /// Finds the canonical path of a module imported by the given file (or
+    /// from the current directory, if there's no file).  Paths starting
+    /// with `./` or `../` are only looked for next to the importer.
+    pub fn resolve(&self, path: &str, importer: Option<&str>) -> Result<PathBuf, String> {
+        let base = importer.and_then(|file| Path::new(file).parent()).unwrap_or(Path::new(""));
+        let mut candidates = vec![base.join(path)];
+        if !path.starts_with("./") && !path.starts_with("../") {
+            candidates.extend(self.search_path.iter().map(|dir| dir.join(path)));
+        }
+        candidates.into_iter()
+            .filter_map(|candidate| candidate.canonicalize().ok())
+            .find(|candidate| candidate.is_file())
+            .ok_or(format!("Can't find module '{}'", path))
+    }
+
+    pub fn get(&self, path: &PathBuf) -> Option<Rc<Module>> {
+        self.modules.get(path).cloned()
+    }
+
+    /// Notes that a module has started loading, failing if it's already
+    /// loading, since then it has imported itself.
+    pub fn begin(&mut self, path: &PathBuf) -> Result<(), String> {
+        if let Some(start) = self.loading.iter().position(|p| p == path) {
+            let cycle: Vec<String> = self.loading[start..].iter().chain(Some(path))
+                .map(|p| p.display().to_string())
+                .collect();
+            return Err(format!("Import cycle: {}", cycle.join(" -> ")));
+        }
+        self.loading.push(path.clone());
+        Ok(())
+    }
+
+    /// Notes that a module has finished loading, and caches it if it ran
+    /// without error.
+    pub fn finish(&mut self, path: &PathBuf, module: Option<Rc<Module>>) {
+        self.loading.retain(|p| p != path);
+        if let Some(module) = module {
+            self.modules.insert(path.clone(), module);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::ops::Deref;
+    use std::process;
+    use interpreter::interpret_with_loader;
+    use parser::parse;
+    use scanner::scan;
+
+    // a directory of test files, which is removed when the test is done
+    // with it
+    struct TempDir(PathBuf);
+
+    impl Deref for TempDir {
+        type Target = PathBuf;
+
+        fn deref(&self) -> &PathBuf {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // writes the files into a fresh directory, named for this process so
+    // that test runs happening at once don't share it
+    fn write_files(name: &str, files: &[(&str, &str)]) -> TempDir {
+        let dir = TempDir(env::temp_dir().join(format!("rlox-module-{}-{}", process::id(), name)));
+        let _ = fs::remove_dir_all(&*dir);
+        for &(path, source) in files.iter() {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, source).unwrap();
+        }
+        dir
+    }
+
+    fn run(dir: &PathBuf, search_path: Vec<PathBuf>) -> Result<Rc<RefCell<Environment>>, String> {
+        let main = dir.join("main.lox");
+        let source = fs::read_to_string(&main).unwrap();
+        let environment = Rc::new(RefCell::new(Environment::new()));
+        let loader = Rc::new(RefCell::new(Loader::new(search_path)));
+        let statements = parse(scan(&source).unwrap()).unwrap();
+        interpret_with_loader(Rc::clone(&environment), statements, Some(main.to_str().unwrap()), loader)
+            .map(|_| environment)
+            .map_err(|error| error.to_string())
+    }
+
+    fn get(environment: &Rc<RefCell<Environment>>, name: &str) -> String {
+        environment.borrow().get(&Token::with_lexeme(TokenType::Identifier, String::from(name), 1)).unwrap().to_string()
+    }
+
+    #[test]
+    fn imports_modules_once_from_relative_and_search_paths() {
+        let dir = write_files("imports", &[
+            ("main.lox", "import \"lib/counter.lox\" as c;\nimport { count, bump as b } from \"lib/counter.lox\";\nimport \"util.lox\" as u;\nb();\nvar r = [c.count, count, c.loads, u.name];"),
+            ("lib/counter.lox", "import \"./start.lox\" as s;\nexport var count = s.start;\nexport var loads = 0;\nloads += 1;\nexport fun bump() { count += 1; }"),
+            ("lib/start.lox", "export var start = 10;"),
+            ("search/util.lox", "export var name = \"util\";"),
+        ]);
+        let environment = run(&dir, vec![dir.join("search")]).unwrap();
+        assert_eq!("[11, 10, 1, \"util\"]", get(&environment, "r"));
+    }
+
+    #[test]
+    fn reports_missing_exports_and_import_cycles() {
+        let dir = write_files("errors", &[
+            ("main.lox", "import { hidden } from \"a.lox\";"),
+            ("a.lox", "var hidden = 1;"),
+        ]);
+        let error = run(&dir, Vec::new()).err().unwrap();
+        assert!(error.ends_with("a.lox' doesn't export 'hidden'"), "{}", error);
+
+        let dir = write_files("cycle", &[
+            ("main.lox", "import \"a.lox\";"),
+            ("a.lox", "import \"b.lox\";"),
+            ("b.lox", "import \"a.lox\";"),
+        ]);
+        let error = run(&dir, Vec::new()).err().unwrap();
+        let a = dir.join("a.lox").canonicalize().unwrap();
+        let b = dir.join("b.lox").canonicalize().unwrap();
+        assert_eq!(format!("ERR:1:Import cycle: {} -> {} -> {}", a.display(), b.display(), a.display()), error);
+
+        // the script being run counts as loading too, so it isn't run again
+        let dir = write_files("entry-cycle", &[
+            ("main.lox", "print \"main\";\nimport \"a.lox\";"),
+            ("a.lox", "import \"main.lox\";"),
+        ]);
+        let error = run(&dir, Vec::new()).err().unwrap();
+        let main = dir.join("main.lox").canonicalize().unwrap();
+        let a = dir.join("a.lox").canonicalize().unwrap();
+        assert_eq!(format!("ERR:1:Import cycle: {} -> {} -> {}", main.display(), a.display(), main.display()), error);
+    }
+}
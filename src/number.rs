@@ -0,0 +1,163 @@
+use std::cmp::Ordering;
+
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Signed, ToPrimitive};
+
+use value::Value;
+
+/// The kinds of number, from the narrowest to the widest.  Arithmetic on
+/// two numbers of different kinds is done in the wider kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Integer,
+    BigInt,
+    Decimal,
+    Float,
+}
+
+pub fn kind(value: &Value) -> Option<Kind> {
+    match *value {
+        Value::Integer(_) => Some(Kind::Integer),
+        Value::BigInt(_) => Some(Kind::BigInt),
+        Value::Decimal(_) => Some(Kind::Decimal),
+        Value::Number(_) => Some(Kind::Float),
+        _ => None,
+    }
+}
+
+/// The most bits the result of a power or a left shift can have, so that a
+/// program can't hang or run out of memory computing one.
+pub const MAX_BITS: u64 = 1 << 22;
+
+/// Makes a value from the result of big integer arithmetic, going back to
+/// an `Integer` if it fits, so that each whole number has only one
+/// representation.
+pub fn integer(n: BigInt) -> Value {
+    match n.to_i64() {
+        Some(i) => Value::Integer(i),
+        None => Value::BigInt(n),
+    }
+}
+
+/// A number as it would be written in a program, which for a decimal
+/// means with its `d` suffix, so that error messages about mixing kinds of
+/// number can tell them apart.
+pub fn literal(value: &Value) -> String {
+    match *value {
+        Value::Decimal(ref d) => format!("{}d", d),
+        _ => value.to_string(),
+    }
+}
+
+pub fn to_bigint(value: &Value) -> Option<BigInt> {
+    match *value {
+        Value::Integer(i) => Some(BigInt::from(i)),
+        Value::BigInt(ref n) => Some(n.clone()),
+        _ => None,
+    }
+}
+
+/// Raises an integer to a power, unless the result would have more than
+/// `MAX_BITS` bits.
+pub fn pow(base: &BigInt, exponent: u32) -> Result<BigInt, String> {
+    // 0, 1 and -1 stay small, however big the exponent
+    if base.abs() > BigInt::from(1) && base.bits().saturating_mul(exponent as u64) > MAX_BITS {
+        return Err(format!("{} ** {} is too large", base, exponent));
+    }
+    Ok(base.pow(exponent))
+}
+
+/// Raises a decimal to a whole power.  A positive power is exact, but,
+/// like division, a negative one may have to be rounded, in which case
+/// trailing zeros are dropped.
+pub fn decimal_pow(base: &BigDecimal, exponent: i64) -> Result<BigDecimal, String> {
+    let too_large = || format!("{}d ** {} is too large", base, exponent);
+    let (mantissa, scale) = base.normalized().as_bigint_and_exponent();
+    let n = exponent.abs().to_u32().ok_or_else(too_large)?;
+    let scale = scale.checked_mul(n as i64).filter(|s| s.unsigned_abs() <= MAX_BITS).ok_or_else(too_large)?;
+    let power = BigDecimal::new(pow(&mantissa, n).map_err(|_| too_large())?, scale);
+    if exponent < 0 {
+        Ok((BigDecimal::from(1) / power).normalized())
+    } else {
+        Ok(power)
+    }
+}
+
+/// Shifts an integer left, unless the result would have more than
+/// `MAX_BITS` bits.
+pub fn shift_left(n: &BigInt, bits: &BigInt) -> Result<BigInt, String> {
+    match bits.to_u64() {
+        _ if n.bits() == 0 => Ok(n.clone()),
+        Some(b) if n.bits().saturating_add(b) <= MAX_BITS => Ok(n << b as usize),
+        _ => Err(format!("{} << {} is too large", n, bits)),
+    }
+}
+
+/// Any number other than an infinite float or NaN as an exact decimal.
+pub fn to_decimal(value: &Value) -> Option<BigDecimal> {
+    match *value {
+        Value::Integer(i) => Some(BigDecimal::from(i)),
+        Value::BigInt(ref n) => Some(BigDecimal::from(n.clone())),
+        Value::Decimal(ref d) => Some(d.clone()),
+        Value::Number(n) => BigDecimal::from_f64(n),
+        _ => None,
+    }
+}
+
+/// Compares two numbers of any kind by their exact values, or returns
+/// `None` if either isn't a number.  The inner `None` is for NaN, which is
+/// neither less than, greater than nor equal to anything.
+pub fn compare(left: &Value, right: &Value) -> Option<Option<Ordering>> {
+    match (kind(left)?, kind(right)?) {
+        (Kind::Integer, Kind::Integer) | (Kind::Integer, Kind::BigInt) | (Kind::BigInt, Kind::Integer) | (Kind::BigInt, Kind::BigInt) => {
+            Some(to_bigint(left)?.partial_cmp(&to_bigint(right)?))
+        },
+        (Kind::Float, Kind::Float) => Some(left.as_float()?.partial_cmp(&right.as_float()?)),
+        _ => match (left, right) {
+            (&Value::Number(n), _) if !n.is_finite() => Some(n.partial_cmp(&0.0)),
+            (_, &Value::Number(n)) if !n.is_finite() => Some(0.0.partial_cmp(&n)),
+            _ => Some(to_decimal(left)?.partial_cmp(&to_decimal(right)?)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn big_integers_that_fit_become_integers() {
+        assert_eq!(Value::Integer(-5), integer(BigInt::from(-5)));
+        let big = BigInt::from(::std::i64::MAX) + BigInt::from(1);
+        assert_eq!(Value::BigInt(big.clone()), integer(big));
+    }
+
+    #[test]
+    fn refuses_to_make_huge_integers() {
+        assert_eq!(Ok(BigInt::from(1024)), pow(&BigInt::from(2), 10));
+        assert_eq!(Ok(BigInt::from(-1)), pow(&BigInt::from(-1), ::std::u32::MAX));
+        assert_eq!(Err(String::from("3 ** 4294967295 is too large")), pow(&BigInt::from(3), ::std::u32::MAX));
+        let decimal = |s| BigDecimal::from_str(s).unwrap();
+        assert_eq!(Ok(decimal("3.375")), decimal_pow(&decimal("1.5"), 3));
+        assert_eq!("0.5", decimal_pow(&decimal("2"), -1).unwrap().to_string());
+        assert_eq!(Ok(decimal("1e-30")), decimal_pow(&decimal("0.1"), 30));
+        assert_eq!(Err(String::from("10d ** 4000000000 is too large")), decimal_pow(&decimal("10"), 4000000000));
+        assert_eq!(Err(String::from("1.5d ** 10000000 is too large")), decimal_pow(&decimal("1.5"), 10000000));
+        assert_eq!(Ok(BigInt::from(0)), shift_left(&BigInt::from(0), &BigInt::from(100000000000u64)));
+        assert_eq!(Err(String::from("1 << 100000000000 is too large")), shift_left(&BigInt::from(1), &BigInt::from(100000000000u64)));
+    }
+
+    #[test]
+    fn compares_across_kinds() {
+        let tenth = Value::Decimal(BigDecimal::from_str("0.1").unwrap());
+        assert_eq!(Some(Some(Ordering::Equal)), compare(&Value::Integer(1), &Value::Number(1.0)));
+        assert_eq!(Some(Some(Ordering::Equal)), compare(&Value::Integer(1), &Value::Decimal(BigDecimal::from_str("1.00").unwrap())));
+        // the float 0.1 is slightly more than a tenth
+        assert_eq!(Some(Some(Ordering::Less)), compare(&tenth, &Value::Number(0.1)));
+        assert_eq!(Some(Some(Ordering::Less)), compare(&Value::BigInt(BigInt::from(10).pow(30)), &Value::Number(::std::f64::INFINITY)));
+        assert_eq!(Some(None), compare(&tenth, &Value::Number(::std::f64::NAN)));
+        assert_eq!(None, compare(&tenth, &Value::Nil));
+    }
+}
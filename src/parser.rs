@@ -6,14 +6,15 @@ use std::error::Error;
 use std::vec::IntoIter;
 
 use expression::Expr;
-use statement::Stmt;
+use resolver::resolve;
+use statement::{Parameter, Stmt};
 use token::TokenType::*;
 use token::{TokenType, Token};
 use value;
 
 lazy_static! {
     static ref EXPECT_PRIMARY: Vec<TokenType> = {
-        vec![Number, Str, True, False, Nil, LeftParen, Identifier]
+        vec![Number, Str, Interpolation, True, False, Nil, LeftParen, LeftBracket, LeftBrace, Identifier, Fun]
     };
 }
 
@@ -21,6 +22,7 @@ lazy_static! {
 pub struct ParseError {
     expected: Vec<TokenType>,
     found: Token,
+    message: String,
     description: String,
 }
 
@@ -28,10 +30,23 @@ impl ParseError {
     pub fn new(expected: &Vec<TokenType>, found: Option<Token>) -> Box<ParseError> {
         let expected_strings: Vec<&'static str> = expected.iter().map(|tt| tt.as_str()).collect();
         let token = found.unwrap_or(Token::simple(Eof, 0));
-        let description = format!("ERR:{}:unexpected token {}; expected {}", token.line, token.token_type, expected_strings.join(", "));
+        let message = format!("unexpected token {}; expected {}", token.token_type, expected_strings.join(", "));
+        let description = format!("ERR:{}:{}", token.line, message);
         Box::new(ParseError {
             expected: expected.to_vec(),
             found: token,
+            message,
+            description,
+        })
+    }
+
+    /// An error that's about something other than which token was expected.
+    fn with_message(found: Token, message: &str) -> Box<ParseError> {
+        let description = format!("ERR:{}:{}", found.line, message);
+        Box::new(ParseError {
+            expected: Vec::new(),
+            found,
+            message: String::from(message),
             description,
         })
     }
@@ -45,6 +60,15 @@ impl ParseError {
     pub fn line(&self) -> u32 {
         self.found.line
     }
+
+    pub fn token(&self) -> &Token {
+        &self.found
+    }
+
+    /// The error message without the location prefix.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl fmt::Display for ParseError {
@@ -63,6 +87,7 @@ pub fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>, Vec<Box<Error>>> {
     let mut stmts = Vec::new();
     let mut errors = Vec::new();
 
+    let tokens: Vec<Token> = tokens.into_iter().filter(|token| token.token_type != Comment).collect();
     let mut iter = tokens.into_iter().peekable();
     loop {
         if next_is(&mut iter, &[TokenType::Eof]) {
@@ -71,7 +96,7 @@ pub fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>, Vec<Box<Error>>> {
         } else if iter.peek().is_none() {
             break;
         } else {
-            match declaration(&mut iter) {
+            match top_level_declaration(&mut iter) {
                 Ok(stmt) => stmts.push(stmt),
                 Err(e) => {
                     errors.push(e);
@@ -81,6 +106,11 @@ pub fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>, Vec<Box<Error>>> {
         }
     }
 
+    if errors.is_empty() {
+        errors = resolve(&stmts).errors.into_iter()
+            .map(|(token, message)| ParseError::with_message(token, &message) as Box<Error>)
+            .collect();
+    }
     if errors.is_empty() {
         Ok(stmts)
     } else {
@@ -88,6 +118,16 @@ pub fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>, Vec<Box<Error>>> {
     }
 }
 
+/// Parses a single expression, such as one typed at a debugger prompt.  All
+/// of the tokens (apart from comments and the trailing EOF) must be used.
+pub fn parse_expr(tokens: Vec<Token>) -> Result<Expr, Box<Error>> {
+    let tokens: Vec<Token> = tokens.into_iter().filter(|token| token.token_type != Comment).collect();
+    let mut iter = tokens.into_iter().peekable();
+    let expr = parse_expression(&mut iter)?;
+    consume(&mut iter, &[Eof])?;
+    Ok(expr)
+}
+
 fn synchronize(iter: &mut Peekable<IntoIter<Token>>) {
     use token::TokenType::*;
 
@@ -96,15 +136,38 @@ fn synchronize(iter: &mut Peekable<IntoIter<Token>>) {
             break;
         }
 
-        if next_is(iter, &[Class, Fun, Var, For, If, While, Print, Return, Eof]) {
+        if next_is(iter, &[Class, Fun, Var, For, If, While, Print, Return, Throw, Try, Import, Export, Eof]) {
             break;
         }
     }
 }
 
+// only declarations at the top level of a module can be exported
+fn top_level_declaration(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
+    match maybe_consume(iter, &[TokenType::Export]) {
+        Some(keyword) => {
+            let declaration = if next_is(iter, &[TokenType::Fun]) {
+                function_declaration(iter)
+            } else if next_is(iter, &[TokenType::Var]) {
+                var_declaration(iter)
+            } else {
+                Err(ParseError::new_arr(&[TokenType::Fun, TokenType::Var], iter.next()) as Box<Error>)
+            }?;
+            Ok(Stmt::export(keyword, declaration))
+        },
+        None => declaration(iter),
+    }
+}
+
 fn declaration(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
     if next_is(iter, &[TokenType::Var]) {
         var_declaration(iter)
+    } else if next_is(iter, &[TokenType::Fun]) {
+        function_declaration(iter)
+    } else if next_is(iter, &[TokenType::Import]) {
+        import_declaration(iter)
+    } else if next_is(iter, &[TokenType::Export]) {
+        Err(ParseError::with_message(iter.next().unwrap(), "only declarations at the top level can be exported"))
     } else {
         statement(iter)
     }
@@ -113,6 +176,11 @@ fn declaration(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>>
 fn var_declaration(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
     consume(iter, &[TokenType::Var])?;
     let name = consume(iter, &[TokenType::Identifier])?;
+    var_declaration_rest(iter, name)
+}
+
+// the rest of a variable declaration, after its name
+fn var_declaration_rest(iter: &mut Peekable<IntoIter<Token>>, name: Token) -> Result<Stmt, Box<Error>> {
     let initializer = match maybe_consume(iter, &[TokenType::Equal]) {
         Some(_) => parse_expression(iter).map(|initializer| Some(initializer)),
         None => Ok(None),
@@ -121,6 +189,99 @@ fn var_declaration(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Err
     Ok(Stmt::var(name, initializer))
 }
 
+// `as` and `from` are only special here, so they can still be used as names
+fn import_declaration(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
+    let keyword = consume(iter, &[TokenType::Import])?;
+    let mut names = Vec::new();
+    if maybe_consume(iter, &[TokenType::LeftBrace]).is_some() {
+        while !next_is(iter, &[TokenType::RightBrace]) {
+            let name = consume(iter, &[TokenType::Identifier])?;
+            let local = match maybe_consume_word(iter, "as") {
+                Some(_) => consume(iter, &[TokenType::Identifier])?,
+                None => name.clone(),
+            };
+            names.push((name, local));
+            if maybe_consume(iter, &[TokenType::Comma]).is_none() {
+                break;
+            }
+        }
+        consume(iter, &[TokenType::RightBrace])?;
+        if maybe_consume_word(iter, "from").is_none() {
+            let found = iter.next().unwrap_or(Token::simple(Eof, 0));
+            return Err(ParseError::with_message(found, "expected 'from' after the imported names"));
+        }
+    }
+    let path = match consume(iter, &[TokenType::Str])?.literal {
+        Some(value::Value::Str(path)) => path,
+        _ => panic!("BUG: string token without a string literal"),
+    };
+    let alias = match maybe_consume_word(iter, "as") {
+        Some(_) if names.is_empty() => Some(consume(iter, &[TokenType::Identifier])?),
+        Some(token) => return Err(ParseError::new_arr(&[TokenType::Semicolon], Some(token))),
+        None => None,
+    };
+    consume(iter, &[TokenType::Semicolon])?;
+    Ok(Stmt::import(keyword, path, alias, names))
+}
+
+// a statement can't start with an anonymous function, so `fun` here is
+// always followed by a name
+fn function_declaration(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
+    consume(iter, &[TokenType::Fun])?;
+    let name = consume(iter, &[TokenType::Identifier])?;
+    let params = parameters(iter)?;
+    let body = function_body(iter)?;
+    Ok(Stmt::function(name, params, body))
+}
+
+// a parenthesized, comma-separated list of parameters, each a name with
+// an optional default, and perhaps a rest parameter at the end
+fn parameters(iter: &mut Peekable<IntoIter<Token>>) -> Result<Vec<Parameter>, Box<Error>> {
+    consume(iter, &[TokenType::LeftParen])?;
+    let mut params = Vec::new();
+    while !next_is(iter, &[TokenType::RightParen]) {
+        if maybe_consume(iter, &[TokenType::Ellipsis]).is_some() {
+            let name = consume(iter, &[TokenType::Identifier])?;
+            params.push(Parameter { name, default: None, rest: true });
+            break;
+        }
+        let name = consume(iter, &[TokenType::Identifier])?;
+        let default = match maybe_consume(iter, &[TokenType::Equal]) {
+            Some(_) => Some(parse_expression(iter)?),
+            None => None,
+        };
+        params.push(Parameter { name, default, rest: false });
+        if maybe_consume(iter, &[TokenType::Comma]).is_none() {
+            break;
+        }
+    }
+    consume(iter, &[TokenType::RightParen])?;
+    check_parameters(params)
+}
+
+// a parameter without a default after one with a default could only be
+// given by name, which is more likely to be a mistake than intended
+fn check_parameters(params: Vec<Parameter>) -> Result<Vec<Parameter>, Box<Error>> {
+    for (i, param) in params.iter().enumerate() {
+        if params[..i].iter().any(|earlier| earlier.name.lexeme == param.name.lexeme) {
+            return Err(ParseError::with_message(param.name.clone(), &format!("duplicate parameter '{}'", param.name.lexeme)));
+        }
+    }
+    if let Some(i) = params.iter().position(|param| param.default.is_some()) {
+        if let Some(param) = params[i..].iter().find(|param| param.default.is_none() && !param.rest) {
+            return Err(ParseError::with_message(param.name.clone(), "parameters without defaults can't follow ones with defaults"));
+        }
+    }
+    Ok(params)
+}
+
+fn function_body(iter: &mut Peekable<IntoIter<Token>>) -> Result<Vec<Stmt>, Box<Error>> {
+    if !next_is(iter, &[TokenType::LeftBrace]) {
+        return Err(ParseError::new_arr(&[TokenType::LeftBrace], iter.next()));
+    }
+    block_statement(iter)
+}
+
 fn statement(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
     if next_is(iter, &[TokenType::If]) {
         if_statement(iter)
@@ -130,14 +291,22 @@ fn statement(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
         while_statement(iter)
     } else if next_is(iter, &[TokenType::For]) {
         for_statement(iter)
+    } else if next_is(iter, &[TokenType::Return]) {
+        return_statement(iter)
+    } else if next_is(iter, &[TokenType::Throw]) {
+        throw_statement(iter)
+    } else if next_is(iter, &[TokenType::Try]) {
+        try_statement(iter)
     } else if next_is(iter, &[TokenType::LeftBrace]) {
-        block_statement(iter).map(|stmts| Stmt::block(stmts))
+        let line = next_line(iter);
+        block_statement(iter).map(|stmts| Stmt::block(stmts, line))
     } else {
         expression_statement(iter)
     }
 }
 
 fn if_statement(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
+    let line = next_line(iter);
     iter.next();
     consume(iter, &[TokenType::LeftParen])?;
     let expr = parse_expression(iter)?;
@@ -147,46 +316,113 @@ fn if_statement(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>
         Some(_) => statement(iter).map(|eb| Some(eb)),
         None => Ok(None),
     }?;
-    Ok(Stmt::if_(expr, then_branch, else_branch))
+    Ok(Stmt::if_(expr, then_branch, else_branch, line))
 }
 
 fn print_statement(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
+    let line = next_line(iter);
+    iter.next();
+    let expr = parse_expression(iter)?;
+    consume(iter, &[TokenType::Semicolon])?;
+    Ok(Stmt::print(expr, line))
+}
+
+fn return_statement(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
+    let keyword = consume(iter, &[TokenType::Return])?;
+    let value = if next_is(iter, &[TokenType::Semicolon]) {
+        None
+    } else {
+        Some(parse_expression(iter)?)
+    };
+    consume(iter, &[TokenType::Semicolon])?;
+    Ok(Stmt::return_(keyword, value))
+}
+
+fn throw_statement(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
+    let line = next_line(iter);
     iter.next();
     let expr = parse_expression(iter)?;
     consume(iter, &[TokenType::Semicolon])?;
-    Ok(Stmt::print(expr))
+    Ok(Stmt::throw(expr, line))
+}
+
+fn try_statement(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
+    let line = next_line(iter);
+    iter.next();
+    let body = block(iter)?;
+    let catch_branch = match maybe_consume(iter, &[TokenType::Catch]) {
+        Some(_) => {
+            consume(iter, &[TokenType::LeftParen])?;
+            let name = consume(iter, &[TokenType::Identifier])?;
+            consume(iter, &[TokenType::RightParen])?;
+            block(iter).map(|cb| Some((name, cb)))
+        },
+        None => Ok(None),
+    }?;
+    let finally_branch = match catch_branch {
+        // a try needs at least one of catch or finally
+        None => consume(iter, &[TokenType::Catch, TokenType::Finally]).and_then(|_| block(iter)).map(|fb| Some(fb)),
+        Some(_) => match maybe_consume(iter, &[TokenType::Finally]) {
+            Some(_) => block(iter).map(|fb| Some(fb)),
+            None => Ok(None),
+        },
+    }?;
+    Ok(Stmt::try_(body, catch_branch, finally_branch, line))
 }
 
 fn while_statement(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
+    let line = next_line(iter);
     iter.next();
     consume(iter, &[TokenType::LeftParen])?;
     let expression = parse_expression(iter)?;
     consume(iter, &[TokenType::RightParen])?;
     let body = statement(iter)?;
-    Ok(Stmt::for_(None, expression, None, body))
+    Ok(Stmt::for_(None, expression, None, body, line))
 }
 
 fn for_statement(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
+    let line = next_line(iter);
     iter.next();
     consume(iter, &[TokenType::LeftParen])?;
-    let initializer = match maybe_consume(iter, &[TokenType::Semicolon]) {
-        Some(_) => Ok(None),
-        None => if next_is(iter, &[TokenType::Var]) {
-            var_declaration(iter)
-        } else {
-            expression_statement(iter)
-        }.map(|i| Some(i)),
+    let initializer = match maybe_consume(iter, &[TokenType::Semicolon, TokenType::Var]) {
+        Some(ref token) if token.token_type == TokenType::Semicolon => Ok(None),
+        Some(_) => {
+            let name = consume(iter, &[TokenType::Identifier])?;
+            if let Some(_) = maybe_consume(iter, &[TokenType::In]) {
+                return for_in_statement(iter, name, line);
+            }
+            var_declaration_rest(iter, name).map(|i| Some(i))
+        },
+        None => expression_statement(iter).map(|i| Some(i)),
     }?;
     let condition = match maybe_consume(iter, &[TokenType::Semicolon]) {
         Some(_) => Ok(Expr::literal(value::TrueValue)),
         None => parse_expression(iter).and_then(|expr| consume(iter, &[TokenType::Semicolon]).map(|_| expr)),
     }?;
+    let increment_line = next_line(iter);
     let increment = match maybe_consume(iter, &[TokenType::RightParen]) {
         Some(_) => Ok(None),
-        None => parse_expression(iter).and_then(|expr| consume(iter, &[TokenType::RightParen]).map(|_| Some(Stmt::expression(expr)))),
+        None => parse_expression(iter).and_then(|expr| consume(iter, &[TokenType::RightParen]).map(|_| Some(Stmt::expression(expr, increment_line)))),
     }?;
     let body = statement(iter)?;
-    Ok(Stmt::block(vec![Stmt::for_(initializer, condition, increment, body)]))
+    Ok(Stmt::block(vec![Stmt::for_(initializer, condition, increment, body, line)], line))
+}
+
+// the rest of a `for (var name in iterable) body` loop, after the `in`
+fn for_in_statement(iter: &mut Peekable<IntoIter<Token>>, name: Token, line: u32) -> Result<Stmt, Box<Error>> {
+    let iterable = parse_expression(iter)?;
+    consume(iter, &[TokenType::RightParen])?;
+    let body = statement(iter)?;
+    Ok(Stmt::for_in(name, iterable, body, line))
+}
+
+// a braced block, where one is required rather than any statement
+fn block(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
+    let line = next_line(iter);
+    if !next_is(iter, &[TokenType::LeftBrace]) {
+        return Err(ParseError::new_arr(&[TokenType::LeftBrace], iter.next()));
+    }
+    block_statement(iter).map(|stmts| Stmt::block(stmts, line))
 }
 
 fn block_statement(iter: &mut Peekable<IntoIter<Token>>) -> Result<Vec<Stmt>, Box<Error>> {
@@ -203,9 +439,10 @@ fn block_statement(iter: &mut Peekable<IntoIter<Token>>) -> Result<Vec<Stmt>, Bo
 }
 
 fn expression_statement(iter: &mut Peekable<IntoIter<Token>>) -> Result<Stmt, Box<Error>> {
+    let line = next_line(iter);
     let expr = parse_expression(iter)?;
     consume(iter, &[TokenType::Semicolon])?;
-    Ok(Stmt::expression(expr))
+    Ok(Stmt::expression(expr, line))
 }
 
 
@@ -213,20 +450,49 @@ fn parse_expression(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Er
     parse_assignment(iter)
 }
 
+// compound assignments to variables, like `a += b`, are turned into
+// `a = a + b`; for indexed targets the operator is kept, so that the object
+// and index are only evaluated once
 fn parse_assignment(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
-    parse_or(iter).and_then(|expr| {
-        match maybe_consume(iter, &[TokenType::Equal]) {
-            Some(equal) => {
-                parse_assignment(iter).and_then(|value| {
-                    match expr {
-                        Expr::Variable { ref name } => Ok(Expr::assign((*name).clone(), value)),
-                        _ => Err(ParseError::new_arr(&[TokenType::Identifier], Some(equal))),
-                    }
-                })
-            }
-            None => Ok(expr),
+    parse_conditional(iter).and_then(|expr| finish_assignment(iter, expr))
+}
+
+// the rest of an assignment, if there is one, to an already parsed target
+fn finish_assignment(iter: &mut Peekable<IntoIter<Token>>, expr: Expr) -> Result<Expr, Box<Error>> {
+    match maybe_consume(iter, &[Equal, PlusEqual, MinusEqual, StarEqual, SlashEqual, PercentEqual]) {
+        Some(equal) => {
+            parse_assignment(iter).and_then(|value| {
+                let operator = compound_operator(&equal);
+                match expr {
+                    Expr::Variable { ref name } => {
+                        let value = match operator {
+                            Some(operator) => Expr::binary(expr.clone(), operator, value),
+                            None => value,
+                        };
+                        Ok(Expr::assign((*name).clone(), value))
+                    },
+                    Expr::Index { object, bracket, index } => Ok(Expr::set_index(*object, bracket, *index, operator, value)),
+                    _ => Err(ParseError::new_arr(&[TokenType::Identifier], Some(equal))),
+                }
+            })
         }
-    })
+        None => Ok(expr),
+    }
+}
+
+// the binary operator that a compound assignment applies, if it is one
+fn compound_operator(equal: &Token) -> Option<Token> {
+    let token_type = match equal.token_type {
+        PlusEqual => Plus,
+        MinusEqual => Minus,
+        StarEqual => Star,
+        SlashEqual => Slash,
+        PercentEqual => Percent,
+        _ => return None,
+    };
+    let mut operator = Token::simple(token_type, equal.line);
+    operator.offset = equal.offset;
+    Some(operator)
 }
 
 fn parse_binary(iter: &mut Peekable<IntoIter<Token>>,
@@ -239,6 +505,24 @@ fn parse_binary(iter: &mut Peekable<IntoIter<Token>>,
     Ok(expr)
 }
 
+// `a ? b : c ? d : e` groups from the right, as `a ? b : (c ? d : e)`
+fn parse_conditional(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
+    let condition = parse_coalesce(iter)?;
+    match maybe_consume(iter, &[Question]) {
+        Some(question) => {
+            let then_branch = parse_assignment(iter)?;
+            consume(iter, &[Colon])?;
+            let else_branch = parse_conditional(iter)?;
+            Ok(Expr::conditional(condition, question, then_branch, else_branch))
+        },
+        None => Ok(condition),
+    }
+}
+
+fn parse_coalesce(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
+    parse_binary(iter, &[QuestionQuestion], parse_or, Expr::logical)
+}
+
 fn parse_or(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
     parse_binary(iter, &[Or], parse_and, Expr::logical)
 }
@@ -252,7 +536,42 @@ fn parse_equality(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Erro
 }
 
 fn parse_comparison(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
-    parse_binary(iter, &[Greater, GreaterEqual, Less, LessEqual], parse_addition, Expr::binary)
+    parse_binary(iter, &[Greater, GreaterEqual, Less, LessEqual], parse_range, Expr::binary)
+}
+
+fn parse_range(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
+    let start = parse_bit_or(iter)?;
+    match maybe_consume(iter, &[DotDot, DotDotEqual]) {
+        Some(operator) => {
+            let end = parse_bit_or(iter)?;
+            // `step` is only special here, so it can still be used as a name
+            let has_step = iter.peek().map_or(false, |t| t.token_type == Identifier && t.lexeme == "step");
+            let step = if has_step {
+                iter.next();
+                Some(parse_bit_or(iter)?)
+            } else {
+                None
+            };
+            Ok(Expr::range(start, operator, end, step))
+        },
+        None => Ok(start),
+    }
+}
+
+fn parse_bit_or(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
+    parse_binary(iter, &[Pipe], parse_bit_xor, Expr::binary)
+}
+
+fn parse_bit_xor(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
+    parse_binary(iter, &[Caret], parse_bit_and, Expr::binary)
+}
+
+fn parse_bit_and(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
+    parse_binary(iter, &[Ampersand], parse_shift, Expr::binary)
+}
+
+fn parse_shift(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
+    parse_binary(iter, &[LessLess, GreaterGreater], parse_addition, Expr::binary)
 }
 
 fn parse_addition(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
@@ -260,24 +579,96 @@ fn parse_addition(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Erro
 }
 
 fn parse_multiplication(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
-    parse_binary(iter, &[Slash, Star], parse_unary, Expr::binary)
+    parse_binary(iter, &[Slash, Star, Percent], parse_unary, Expr::binary)
 }
 
 fn parse_unary(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
-    match maybe_consume(iter, &[Bang, Minus]) {
+    match maybe_consume(iter, &[Bang, Minus, Tilde]) {
         Some(operator) => parse_unary(iter).map(|right| Expr::unary(operator, right)),
-        None => parse_primary(iter),
+        None => parse_power(iter),
+    }
+}
+
+// `**` binds more tightly than a unary operator on its left, so `-2 ** 2`
+// is `-(2 ** 2)`, and groups from the right, so `2 ** 3 ** 2` is
+// `2 ** (3 ** 2)`
+fn parse_power(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
+    let base = parse_call(iter)?;
+    match maybe_consume(iter, &[StarStar]) {
+        Some(operator) => parse_unary(iter).map(|exponent| Expr::binary(base, operator, exponent)),
+        None => Ok(base),
+    }
+}
+
+fn parse_call(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
+    let mut expr = parse_primary(iter)?;
+    while let Some(token) = maybe_consume(iter, &[Dot, QuestionDot, LeftParen, LeftBracket]) {
+        expr = match token.token_type {
+            Dot => consume(iter, &[Identifier]).map(|name| Expr::get(expr, name, false)),
+            QuestionDot => consume(iter, &[Identifier]).map(|name| Expr::get(expr, name, true)),
+            LeftParen => parse_arguments(iter).map(|(arguments, named)| Expr::call(expr, token, arguments, named)),
+            _ => parse_expression(iter).and_then(|index| {
+                consume(iter, &[RightBracket]).map(|_| Expr::index(expr, token, index))
+            }),
+        }?;
+    }
+    Ok(expr)
+}
+
+// parses a call's arguments up to and including the closing parenthesis,
+// where `name: value` is a named argument
+fn parse_arguments(iter: &mut Peekable<IntoIter<Token>>) -> Result<(Vec<Expr>, Vec<(Token, Expr)>), Box<Error>> {
+    let mut arguments = Vec::new();
+    let mut named = Vec::new();
+    while !next_is(iter, &[RightParen]) {
+        let argument = parse_expression(iter)?;
+        match (argument, maybe_consume(iter, &[Colon])) {
+            (Expr::Variable { name }, Some(_)) => named.push((name, parse_expression(iter)?)),
+            (_, Some(colon)) => return Err(ParseError::new_arr(&[Comma, RightParen], Some(colon))),
+            (argument, None) => {
+                if !named.is_empty() {
+                    let token = iter.peek().cloned().unwrap_or(Token::simple(Eof, 0));
+                    return Err(ParseError::with_message(token, "positional arguments must come before named ones"));
+                }
+                arguments.push(argument);
+            },
+        }
+        if maybe_consume(iter, &[Comma]).is_none() {
+            break;
+        }
     }
+    consume(iter, &[RightParen])?;
+    Ok((arguments, named))
+}
+
+// parses comma-separated expressions up to and including the closing token;
+// a trailing comma is allowed
+fn parse_list(iter: &mut Peekable<IntoIter<Token>>, closing: TokenType) -> Result<Vec<Expr>, Box<Error>> {
+    let mut exprs = Vec::new();
+    while !next_is(iter, &[closing.clone()]) {
+        exprs.push(parse_expression(iter)?);
+        if maybe_consume(iter, &[Comma]).is_none() {
+            break;
+        }
+    }
+    consume(iter, &[closing])?;
+    Ok(exprs)
 }
 
 fn parse_primary(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
     iter.next().ok_or(ParseError::new(&*EXPECT_PRIMARY, None) as Box<Error>).and_then(|token| {
         if EXPECT_PRIMARY.contains(&token.token_type) {
             match token.token_type {
-                LeftParen => parse_expression(iter).and_then(|expr| {
-                    consume(iter, &[RightParen]).map(|_| Expr::grouping(expr))
+                LeftParen => parse_grouping_or_arrow(iter),
+                Fun => parameters(iter).and_then(|params| {
+                    function_body(iter).map(|body| Expr::lambda(token, params, body))
                 }),
+                LeftBracket => parse_list(iter, RightBracket).map(Expr::list),
+                // at the start of a statement a brace is a block, so this is
+                // only reached in the middle of an expression
+                LeftBrace => parse_map(iter).map(|entries| Expr::map(token, entries)),
                 Identifier => Ok(Expr::variable(token)),
+                Interpolation => parse_interpolation(iter, token),
                 _ => match token.literal {
                     Some(value) => Ok(Expr::literal(value)),
                     None => Err(ParseError::new(&*EXPECT_PRIMARY, Some(token))),
@@ -289,6 +680,89 @@ fn parse_primary(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error
     })
 }
 
+// `(a, b = 1) => a + b` looks like a grouping until the `=>`, so the
+// parameters are parsed as expressions and then checked to be names or
+// assignments of defaults; only a rest parameter can't be an expression
+fn parse_grouping_or_arrow(iter: &mut Peekable<IntoIter<Token>>) -> Result<Expr, Box<Error>> {
+    let mut exprs = Vec::new();
+    let mut rest = None;
+    let mut comma = false;
+    while !next_is(iter, &[RightParen]) {
+        if maybe_consume(iter, &[Ellipsis]).is_some() {
+            rest = Some(consume(iter, &[Identifier])?);
+            break;
+        }
+        // only a plain `=` gives a default; `a += 1` is turned into an
+        // assignment too, but isn't a parameter
+        let target = parse_conditional(iter)?;
+        let plain = next_is(iter, &[Equal]);
+        exprs.push((finish_assignment(iter, target)?, plain));
+        if maybe_consume(iter, &[Comma]).is_none() {
+            break;
+        }
+        comma = true;
+    }
+    consume(iter, &[RightParen])?;
+    let arrow = match maybe_consume(iter, &[Arrow]) {
+        Some(arrow) => arrow,
+        None if exprs.len() == 1 && rest.is_none() && !comma => return Ok(Expr::grouping(exprs.pop().unwrap().0)),
+        None => return Err(ParseError::new_arr(&[Arrow], iter.next())),
+    };
+    let mut params = Vec::new();
+    for (expr, plain) in exprs.into_iter() {
+        match expr {
+            Expr::Variable { name } => params.push(Parameter { name, default: None, rest: false }),
+            Expr::Assign { name, value } if plain => params.push(Parameter { name, default: Some(*value), rest: false }),
+            _ => return Err(ParseError::new_arr(&[Identifier], Some(arrow))),
+        }
+    }
+    if let Some(name) = rest {
+        params.push(Parameter { name, default: None, rest: true });
+    }
+    let params = check_parameters(params)?;
+    // the body is either a block or a single expression whose value is
+    // returned
+    let body = if next_is(iter, &[LeftBrace]) {
+        function_body(iter)?
+    } else {
+        vec![Stmt::return_(arrow.clone(), Some(parse_assignment(iter)?))]
+    };
+    Ok(Expr::lambda(arrow, params, body))
+}
+
+fn parse_interpolation(iter: &mut Peekable<IntoIter<Token>>, first: Token) -> Result<Expr, Box<Error>> {
+    let mut parts = Vec::new();
+    let mut token = first;
+    loop {
+        let end = token.token_type == InterpolationEnd;
+        match token.literal {
+            Some(value::Value::Str(ref s)) if s.is_empty() => (),
+            Some(literal) => parts.push(Expr::literal(literal)),
+            None => (),
+        }
+        if end {
+            break;
+        }
+        parts.push(parse_expression(iter)?);
+        token = consume(iter, &[Interpolation, InterpolationEnd])?;
+    }
+    Ok(Expr::interpolation(parts))
+}
+
+fn parse_map(iter: &mut Peekable<IntoIter<Token>>) -> Result<Vec<(Expr, Expr)>, Box<Error>> {
+    let mut entries = Vec::new();
+    while !next_is(iter, &[RightBrace]) {
+        let key = parse_expression(iter)?;
+        consume(iter, &[Colon])?;
+        let value = parse_expression(iter)?;
+        entries.push((key, value));
+        if maybe_consume(iter, &[Comma]).is_none() {
+            break;
+        }
+    }
+    consume(iter, &[RightBrace])?;
+    Ok(entries)
+}
 
 fn next_is(iter: &mut Peekable<IntoIter<Token>>, matches: &[TokenType]) -> bool {
     if let Some(next) = iter.peek() {
@@ -301,6 +775,20 @@ fn next_is(iter: &mut Peekable<IntoIter<Token>>, matches: &[TokenType]) -> bool
     false
 }
 
+fn next_line(iter: &mut Peekable<IntoIter<Token>>) -> u32 {
+    iter.peek().map(|token| token.line).unwrap_or(0)
+}
+
+// consumes the next token if it's an identifier that acts as a keyword in
+// this position
+fn maybe_consume_word(iter: &mut Peekable<IntoIter<Token>>, word: &str) -> Option<Token> {
+    if iter.peek().map_or(false, |t| t.token_type == Identifier && t.lexeme == word) {
+        iter.next()
+    } else {
+        None
+    }
+}
+
 fn maybe_consume(iter: &mut Peekable<IntoIter<Token>>, matches: &[TokenType]) -> Option<Token> {
     if next_is(iter, matches) {
         iter.next()
@@ -0,0 +1,311 @@
+use std::fmt;
+use std::rc::Rc;
+
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+use callable::NativeFunction;
+use list;
+use number::{self, Kind};
+use value::Value;
+
+// float ranges with more numbers than this can't tell consecutive numbers
+// apart, so they can't be counted
+const MAX_FLOAT_LENGTH: f64 = 9007199254740992.0;
+
+/// A lazy sequence of evenly spaced numbers, written `start..end` or
+/// `start..=end`, optionally followed by `step n`.  A range counts in the
+/// widest kind of number among its bounds and step, so only a range with a
+/// float in it counts in floats.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Range {
+    numbers: Numbers,
+    inclusive: bool,
+    /// How many numbers there are, worked out when the range is made, so
+    /// that ranges too long to count are rejected up front.
+    length: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Numbers {
+    Integers { start: i64, end: i64, step: i64 },
+    /// Big integers, or, if there's a scale, decimals stored as whole
+    /// numbers of `10^-scale`.
+    Exact { start: BigInt, end: BigInt, step: BigInt, scale: Option<i64> },
+    Floats { start: f64, end: f64, step: f64 },
+}
+
+impl Range {
+    pub fn new(start: f64, end: f64, step: f64, inclusive: bool) -> Result<Range, String> {
+        if step == 0.0 || step.is_nan() {
+            return Err(String::from("A range's step can't be zero"));
+        }
+        if !start.is_finite() || !end.is_finite() || !step.is_finite() {
+            return Err(String::from("A range's bounds and step must be finite"));
+        }
+        let span = (end - start) / step;
+        let length = if span < 0.0 {
+            0.0
+        } else if inclusive {
+            span.floor() + 1.0
+        } else {
+            span.ceil()
+        };
+        let numbers = Numbers::Floats { start, end, step };
+        if length > MAX_FLOAT_LENGTH {
+            return Err(format!("{} has too many numbers to count", Range { numbers, inclusive, length: 0 }));
+        }
+        Ok(Range { numbers, inclusive, length: length as usize })
+    }
+
+    pub fn integers(start: i64, end: i64, step: i64, inclusive: bool) -> Result<Range, String> {
+        if step == 0 {
+            return Err(String::from("A range's step can't be zero"));
+        }
+        // worked out in 128 bits, which can't overflow
+        let (span, step_size) = if step > 0 {
+            (end as i128 - start as i128, step as i128)
+        } else {
+            (start as i128 - end as i128, -(step as i128))
+        };
+        let length = if span < 0 || (span == 0 && !inclusive) {
+            0
+        } else if inclusive {
+            span / step_size + 1
+        } else {
+            (span + step_size - 1) / step_size
+        };
+        let numbers = Numbers::Integers { start, end, step };
+        if length > usize::max_value() as i128 {
+            return Err(format!("{} has too many numbers to count", Range { numbers, inclusive, length: 0 }));
+        }
+        Ok(Range { numbers, inclusive, length: length as usize })
+    }
+
+    /// A range of big integers, or, if `decimal` is set, of decimals.
+    pub fn exact(start: &BigDecimal, end: &BigDecimal, step: &BigDecimal, decimal: bool, inclusive: bool) -> Result<Range, String> {
+        if step.is_zero() {
+            return Err(String::from("A range's step can't be zero"));
+        }
+        let scale = if decimal {
+            start.fractional_digit_count().max(end.fractional_digit_count()).max(step.fractional_digit_count())
+        } else {
+            0
+        };
+        let whole = |n: &BigDecimal| n.with_scale(scale).as_bigint_and_exponent().0;
+        let (start, end, step) = (whole(start), whole(end), whole(step));
+        let (span, step_size) = if step.is_positive() {
+            (&end - &start, step.clone())
+        } else {
+            (&start - &end, -&step)
+        };
+        let length = if span.is_negative() || (span.is_zero() && !inclusive) {
+            BigInt::zero()
+        } else if inclusive {
+            &span / &step_size + 1
+        } else {
+            (&span + &step_size - 1) / &step_size
+        };
+        let numbers = Numbers::Exact { start, end, step, scale: if decimal { Some(scale) } else { None } };
+        match length.to_usize() {
+            Some(length) => Ok(Range { numbers, inclusive, length }),
+            None => Err(format!("{} has too many numbers to count", Range { numbers, inclusive, length: 0 })),
+        }
+    }
+
+    pub fn from_values(start: &Value, end: &Value, step: &Value, inclusive: bool) -> Result<Range, String> {
+        let widest = match (number::kind(start), number::kind(end), number::kind(step)) {
+            (Some(s), Some(e), Some(n)) => s.max(e).max(n),
+            _ => return Err(String::from("A range's bounds and step must be numbers")),
+        };
+        // all three are numbers, so they can be converted
+        match (start, end, step) {
+            (&Value::Integer(s), &Value::Integer(e), &Value::Integer(n)) => Range::integers(s, e, n, inclusive),
+            _ if widest == Kind::Float => Range::new(start.as_float().unwrap(), end.as_float().unwrap(), step.as_float().unwrap(), inclusive),
+            _ => {
+                let (s, e, n) = (number::to_decimal(start).unwrap(), number::to_decimal(end).unwrap(), number::to_decimal(step).unwrap());
+                Range::exact(&s, &e, &n, widest == Kind::Decimal, inclusive)
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn get(&self, i: usize) -> Option<Value> {
+        if i >= self.length {
+            return None;
+        }
+        match self.numbers {
+            // this is between the bounds, so it fits
+            Numbers::Integers { start, step, .. } => Some(Value::Integer((start as i128 + i as i128 * step as i128) as i64)),
+            Numbers::Exact { ref start, ref step, scale, .. } => Some(exact_value(start + step * BigInt::from(i), scale)),
+            Numbers::Floats { start, step, .. } => Some(Value::Number(start + i as f64 * step)),
+        }
+    }
+
+    /// The positions picked out of a list or string of the given length by
+    /// slicing it with this range.  As with indexing, negative bounds count
+    /// back from the end; bounds past either end are clamped.
+    pub fn slice_indices(&self, length: usize) -> Result<Vec<usize>, String> {
+        let (start, end, step) = match self.numbers {
+            Numbers::Integers { start, end, step } => (start, end, step),
+            Numbers::Exact { ref start, ref end, ref step, scale } => {
+                let decimal = |n: &BigInt| BigDecimal::new(n.clone(), scale.unwrap_or(0));
+                let (start, end, step) = (decimal(start), decimal(end), decimal(step));
+                if !start.is_integer() || !end.is_integer() || !step.is_integer() {
+                    return Err(format!("Can't slice with {}, which has fractional bounds", self));
+                }
+                (whole(&start), whole(&end), whole(&step))
+            },
+            Numbers::Floats { start, end, step } => {
+                if start.fract() != 0.0 || end.fract() != 0.0 || step.fract() != 0.0 {
+                    return Err(format!("Can't slice with {}, which has fractional bounds", self));
+                }
+                (start as i64, end as i64, step as i64)
+            },
+        };
+        if step < 0 {
+            return Err(format!("Can't slice with {}, which counts down", self));
+        }
+        let length = length as i128;
+        let resolve = |n: i64| if n < 0 { n as i128 + length } else { n as i128 };
+        let start = resolve(start).max(0).min(length) as usize;
+        let end = resolve(end) + if self.inclusive { 1 } else { 0 };
+        let end = end.max(0).min(length) as usize;
+        Ok((start..end).step_by(step as usize).collect())
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operator = if self.inclusive { "..=" } else { ".." };
+        let (start, end, step, one) = match self.numbers {
+            Numbers::Integers { start, end, step } => (Value::Integer(start), Value::Integer(end), Value::Integer(step), Value::Integer(1)),
+            Numbers::Exact { ref start, ref end, ref step, scale } => {
+                (exact_value(start.clone(), scale), exact_value(end.clone(), scale), exact_value(step.clone(), scale), exact_value(BigInt::from(1), scale.map(|_| 0)))
+            },
+            Numbers::Floats { start, end, step } => (Value::Number(start), Value::Number(end), Value::Number(step), Value::Number(1.0)),
+        };
+        write!(f, "{}{}{}", start, operator, end)?;
+        if step != one {
+            write!(f, " step {}", step)?;
+        }
+        Ok(())
+    }
+}
+
+fn exact_value(n: BigInt, scale: Option<i64>) -> Value {
+    match scale {
+        Some(scale) => Value::Decimal(BigDecimal::new(n, scale)),
+        None => number::integer(n),
+    }
+}
+
+/// A whole decimal as an `i64`, saturating, which is as good for clamping
+/// slice bounds.
+fn whole(n: &BigDecimal) -> i64 {
+    let n = n.with_scale(0).as_bigint_and_exponent().0;
+    n.to_i64().unwrap_or(if n.is_negative() { i64::min_value() } else { i64::max_value() })
+}
+
+/// Looks up one of the methods every range has, such as `to_list`.
+pub fn method(name: &str) -> Option<NativeFunction> {
+    match name {
+        "length" | "len" => Some(NativeFunction::new(name, 0, length)),
+        "to_list" => Some(NativeFunction::new(name, 0, to_list)),
+        _ => None,
+    }
+}
+
+fn range(value: &Value) -> &Range {
+    match *value {
+        Value::Range(ref range) => range,
+        _ => panic!("BUG: range method called on {}", value),
+    }
+}
+
+fn length(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    Ok(Rc::new(Value::Integer(range(&arguments[0]).len() as i64)))
+}
+
+fn to_list(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let range = range(&arguments[0]);
+    let elements = (0..range.len()).filter_map(|i| range.get(i)).map(Rc::new).collect();
+    Ok(Rc::new(list::new(elements)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_elements() {
+        assert_eq!(10, Range::new(0.0, 10.0, 1.0, false).unwrap().len());
+        assert_eq!(11, Range::new(0.0, 10.0, 1.0, true).unwrap().len());
+        assert_eq!(5, Range::new(0.0, 10.0, 2.0, false).unwrap().len());
+        assert_eq!(3, Range::new(3.0, 1.0, -1.0, true).unwrap().len());
+        assert_eq!(0, Range::new(3.0, 1.0, 1.0, false).unwrap().len());
+        assert!(Range::new(0.0, 1.0, 0.0, false).is_err());
+    }
+
+    #[test]
+    fn rejects_ranges_that_cant_be_counted() {
+        assert!(Range::new(0.0, ::std::f64::INFINITY, 1.0, true).is_err());
+        assert!(Range::new(0.0, 1e300, 1e-300, false).unwrap_err().ends_with(" has too many numbers to count"));
+        assert!(Range::integers(::std::i64::MIN, ::std::i64::MAX, 1, true).is_err());
+    }
+
+    #[test]
+    fn counts_big_integers_exactly() {
+        let big = Range::integers(9007199254740993, 9007199254740999, 3, true).unwrap();
+        assert_eq!(3, big.len());
+        assert_eq!(Some(Value::Integer(9007199254740996)), big.get(1));
+        assert_eq!(2, Range::integers(::std::i64::MAX - 1, ::std::i64::MIN, ::std::i64::MIN, true).unwrap().len());
+    }
+
+    #[test]
+    fn slices_with_negative_and_clamped_bounds() {
+        assert_eq!(vec![1, 2, 3], Range::new(1.0, -1.0, 1.0, false).unwrap().slice_indices(5).unwrap());
+        assert_eq!(vec![3, 4], Range::new(-2.0, -1.0, 1.0, true).unwrap().slice_indices(5).unwrap());
+        assert_eq!(vec![0, 2, 4], Range::new(0.0, 100.0, 2.0, false).unwrap().slice_indices(5).unwrap());
+        assert!(Range::new(0.5, 2.0, 1.0, false).unwrap().slice_indices(5).is_err());
+    }
+
+    #[test]
+    fn counts_in_integers_only_if_every_part_is_an_integer() {
+        let integers = Range::from_values(&Value::Integer(0), &Value::Integer(4), &Value::Integer(2), true).unwrap();
+        assert_eq!(Some(Value::Integer(2)), integers.get(1));
+        assert_eq!("0..=4 step 2", integers.to_string());
+        let floats = Range::from_values(&Value::Integer(0), &Value::Number(4.0), &Value::Integer(2), true).unwrap();
+        assert_eq!(Some(Value::Number(2.0)), floats.get(1));
+        assert_eq!("0.0..=4.0 step 2.0", floats.to_string());
+    }
+
+    #[test]
+    fn counts_decimals_exactly() {
+        let decimal = |s: &str| Value::Decimal(s.parse().unwrap());
+        let halves = Range::from_values(&decimal("1"), &decimal("2"), &decimal("0.5"), false).unwrap();
+        assert_eq!(2, halves.len());
+        assert_eq!(Some(decimal("1.5")), halves.get(1));
+        let tenths = Range::from_values(&Value::Integer(0), &decimal("0.3"), &decimal("0.1"), true).unwrap();
+        assert_eq!(Some(decimal("0.3")), tenths.get(3));
+        assert_eq!(vec![1, 2], Range::from_values(&decimal("1"), &decimal("3"), &Value::Integer(1), false).unwrap().slice_indices(5).unwrap());
+        assert!(halves.slice_indices(5).is_err());
+    }
+
+    #[test]
+    fn counts_big_integers_without_losing_precision() {
+        let big = |s: &str| Value::BigInt(s.parse().unwrap());
+        let range = Range::from_values(&big("100000000000000000000"), &big("100000000000000000004"), &Value::Integer(2), true).unwrap();
+        assert_eq!(3, range.len());
+        assert_eq!(Some(big("100000000000000000002")), range.get(1));
+        assert_eq!("100000000000000000000..=100000000000000000004 step 2", range.to_string());
+        let crossing = Range::from_values(&Value::Integer(::std::i64::MAX), &big("9223372036854775809"), &Value::Integer(1), true).unwrap();
+        assert_eq!(Some(Value::Integer(::std::i64::MAX)), crossing.get(0));
+        assert_eq!(Some(big("9223372036854775808")), crossing.get(1));
+        assert!(Range::from_values(&Value::Integer(0), &big("100000000000000000000000000000000"), &Value::Integer(1), false).unwrap_err().ends_with(" has too many numbers to count"));
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::{HashMap, HashSet};
+
+use expression::Expr;
+use statement::{Parameter, Stmt};
+use token::Token;
+
+/// What sort of thing a name was declared as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeclarationKind {
+    Variable,
+    Function,
+    Parameter,
+    Import,
+}
+
+#[derive(Debug, Clone)]
+pub struct Declaration {
+    pub name: Token,
+    pub kind: DeclarationKind,
+    /// How many blocks deep the declaration is; globals have depth 0.
+    pub depth: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub name: Token,
+    /// Index into `Resolution::declarations`, or `None` if the name was
+    /// never declared.
+    pub declaration: Option<usize>,
+}
+
+/// Maps every use of a variable in a program to the declaration it refers
+/// to.
+#[derive(Debug)]
+pub struct Resolution {
+    pub declarations: Vec<Declaration>,
+    pub references: Vec<Reference>,
+    /// Declarations that would change what a function declared before them
+    /// refers to, with why.  Variables are looked up by name when the code
+    /// runs, so a function that used an outer variable would see a later
+    /// local with the same name instead.
+    pub errors: Vec<(Token, String)>,
+}
+
+impl Resolution {
+    /// Finds the declaration for the variable whose name (either at a use
+    /// site or at the declaration itself) covers the given byte offset.
+    pub fn declaration_at(&self, offset: usize) -> Option<usize> {
+        let covers = |name: &Token| name.offset <= offset && offset <= name.end_offset();
+        match self.declarations.iter().position(|d| covers(&d.name)) {
+            Some(index) => Some(index),
+            None => self.references.iter().find(|r| covers(&r.name)).and_then(|r| r.declaration),
+        }
+    }
+
+    pub fn references_to(&self, declaration: usize) -> Vec<&Reference> {
+        self.references.iter().filter(|r| r.declaration == Some(declaration)).collect()
+    }
+}
+
+struct Scope {
+    names: HashMap<String, usize>,
+    // names used by functions inside this scope that refer to declarations
+    // outside it
+    captured: HashSet<String>,
+}
+
+struct State {
+    declarations: Vec<Declaration>,
+    references: Vec<Reference>,
+    errors: Vec<(Token, String)>,
+    globals: HashMap<String, usize>,
+    scopes: Vec<Scope>,
+    // the index of the scope each enclosing function's parameters are in
+    functions: Vec<usize>,
+}
+
+impl State {
+    fn push_scope(&mut self) {
+        self.scopes.push(Scope { names: HashMap::new(), captured: HashSet::new() });
+    }
+
+    fn declare(&mut self, name: &Token, kind: DeclarationKind) {
+        let index = self.declarations.len();
+        self.declarations.push(Declaration {
+            name: name.clone(),
+            kind,
+            depth: self.scopes.len(),
+        });
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                if scope.captured.contains(&name.lexeme) {
+                    let message = format!("can't declare '{}' after a function in the same scope has used an outer '{}'", name.lexeme, name.lexeme);
+                    self.errors.push((name.clone(), message));
+                }
+                scope.names.insert(name.lexeme.clone(), index)
+            },
+            None => self.globals.insert(name.lexeme.clone(), index),
+        };
+    }
+
+    fn reference(&mut self, name: &Token) {
+        let found = self.scopes.iter().rposition(|scope| scope.names.contains_key(&name.lexeme));
+        let declaration = match found {
+            Some(i) => self.scopes[i].names.get(&name.lexeme),
+            None => self.globals.get(&name.lexeme),
+        }.cloned();
+        // the scopes between the declaration and the function using it
+        if let Some(&function) = self.functions.last() {
+            let outside = found.map(|i| i + 1).unwrap_or(0);
+            for scope in self.scopes[outside.min(function)..function].iter_mut() {
+                scope.captured.insert(name.lexeme.clone());
+            }
+        }
+        self.references.push(Reference {
+            name: name.clone(),
+            declaration,
+        });
+    }
+}
+
+pub fn resolve(statements: &Vec<Stmt>) -> Resolution {
+    let mut state = State {
+        declarations: Vec::new(),
+        references: Vec::new(),
+        errors: Vec::new(),
+        globals: HashMap::new(),
+        scopes: Vec::new(),
+        functions: Vec::new(),
+    };
+    for stmt in statements.iter() {
+        resolve_stmt(&mut state, stmt);
+    }
+
+    // globals are looked up when the code runs, so a use that comes before
+    // the declaration in the source can still refer to it
+    for reference in state.references.iter_mut().filter(|r| r.declaration.is_none()) {
+        reference.declaration = state.declarations.iter()
+            .position(|d| d.depth == 0 && d.name.lexeme == reference.name.lexeme);
+    }
+
+    Resolution {
+        declarations: state.declarations,
+        references: state.references,
+        errors: state.errors,
+    }
+}
+
+fn resolve_stmt(state: &mut State, stmt: &Stmt) {
+    match stmt {
+        &Stmt::Block { ref statements, .. } => {
+            state.push_scope();
+            for statement in statements.iter() {
+                resolve_stmt(state, statement);
+            }
+            state.scopes.pop();
+        },
+        &Stmt::Expression { ref expression, .. } => resolve_expr(state, expression),
+        &Stmt::For { ref initializer, ref condition, ref increment, ref body, .. } => {
+            if let &Some(ref i) = initializer {
+                resolve_stmt(state, i);
+            }
+            resolve_expr(state, condition);
+            resolve_stmt(state, body);
+            if let &Some(ref i) = increment {
+                resolve_stmt(state, i);
+            }
+        },
+        &Stmt::ForIn { ref name, ref iterable, ref body, .. } => {
+            resolve_expr(state, iterable);
+            state.push_scope();
+            state.declare(name, DeclarationKind::Variable);
+            resolve_stmt(state, body);
+            state.scopes.pop();
+        },
+        &Stmt::Function { ref name, ref params, ref body } => {
+            state.declare(name, DeclarationKind::Function);
+            resolve_function(state, params, body);
+        },
+        &Stmt::Import { ref alias, ref names, .. } => {
+            for name in alias.iter().chain(names.iter().map(|&(_, ref local)| local)) {
+                state.declare(name, DeclarationKind::Import);
+            }
+        },
+        &Stmt::Export { ref declaration, .. } => resolve_stmt(state, declaration),
+        &Stmt::If { ref expression, ref then_branch, ref else_branch, .. } => {
+            resolve_expr(state, expression);
+            resolve_stmt(state, then_branch);
+            if let &Some(ref eb) = else_branch {
+                resolve_stmt(state, eb);
+            }
+        },
+        &Stmt::Print { ref expression, .. } => resolve_expr(state, expression),
+        &Stmt::Return { ref value, .. } => if let &Some(ref v) = value {
+            resolve_expr(state, v);
+        },
+        &Stmt::Throw { ref expression, .. } => resolve_expr(state, expression),
+        &Stmt::Try { ref body, ref catch_branch, ref finally_branch, .. } => {
+            resolve_stmt(state, body);
+            if let &Some((ref name, ref cb)) = catch_branch {
+                state.push_scope();
+                state.declare(name, DeclarationKind::Variable);
+                resolve_stmt(state, cb);
+                state.scopes.pop();
+            }
+            if let &Some(ref fb) = finally_branch {
+                resolve_stmt(state, fb);
+            }
+        },
+        &Stmt::Var { ref name, ref initializer } => {
+            if let &Some(ref init) = initializer {
+                resolve_expr(state, init);
+            }
+            state.declare(name, DeclarationKind::Variable);
+        },
+    }
+}
+
+fn resolve_function(state: &mut State, params: &Vec<Parameter>, body: &Vec<Stmt>) {
+    state.push_scope();
+    state.functions.push(state.scopes.len() - 1);
+    for param in params.iter() {
+        if let Some(ref default) = param.default {
+            resolve_expr(state, default);
+        }
+        state.declare(&param.name, DeclarationKind::Parameter);
+    }
+    for statement in body.iter() {
+        resolve_stmt(state, statement);
+    }
+    state.functions.pop();
+    state.scopes.pop();
+}
+
+fn resolve_expr(state: &mut State, expr: &Expr) {
+    match expr {
+        &Expr::Assign { ref name, ref value } => {
+            resolve_expr(state, value);
+            state.reference(name);
+        },
+        &Expr::Binary { ref left, ref right, .. } => {
+            resolve_expr(state, left);
+            resolve_expr(state, right);
+        },
+        &Expr::Call { ref callee, ref arguments, ref named, .. } => {
+            resolve_expr(state, callee);
+            for argument in arguments.iter().chain(named.iter().map(|&(_, ref value)| value)) {
+                resolve_expr(state, argument);
+            }
+        },
+        &Expr::Conditional { ref condition, ref then_branch, ref else_branch, .. } => {
+            resolve_expr(state, condition);
+            resolve_expr(state, then_branch);
+            resolve_expr(state, else_branch);
+        },
+        &Expr::Get { ref object, .. } => resolve_expr(state, object),
+        &Expr::Grouping { ref expression } => resolve_expr(state, expression),
+        &Expr::Index { ref object, ref index, .. } => {
+            resolve_expr(state, object);
+            resolve_expr(state, index);
+        },
+        &Expr::Interpolation { ref parts } => for part in parts.iter() {
+            resolve_expr(state, part);
+        },
+        &Expr::Lambda { ref params, ref body, .. } => resolve_function(state, params, body),
+        &Expr::List { ref elements } => for element in elements.iter() {
+            resolve_expr(state, element);
+        },
+        &Expr::Literal { .. } => (),
+        &Expr::Logical { ref left, ref right, .. } => {
+            resolve_expr(state, left);
+            resolve_expr(state, right);
+        },
+        &Expr::Map { ref entries, .. } => for &(ref key, ref value) in entries.iter() {
+            resolve_expr(state, key);
+            resolve_expr(state, value);
+        },
+        &Expr::Range { ref start, ref end, ref step, .. } => {
+            resolve_expr(state, start);
+            resolve_expr(state, end);
+            if let &Some(ref s) = step {
+                resolve_expr(state, s);
+            }
+        },
+        &Expr::SetIndex { ref object, ref index, ref value, .. } => {
+            resolve_expr(state, object);
+            resolve_expr(state, index);
+            resolve_expr(state, value);
+        },
+        &Expr::Unary { ref right, .. } => resolve_expr(state, right),
+        &Expr::Variable { ref name } => state.reference(name),
+    }
+}
@@ -1,10 +1,18 @@
 use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
 use std::iter::Peekable;
-use std::str::Chars;
+use std::str::{Chars, FromStr};
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use number;
 use token::{TokenType, Token};
 use value::Value;
 
+// decimals with bigger exponents than this, in either direction, would
+// take too long to do arithmetic with, as every digit is kept
+const MAX_DECIMAL_EXPONENT: i64 = 1000;
+
 // unfortunately we can't store closure in a HashMap that's defined as
 // lazy_static!, so we have to create a bunch of one-line functions and
 // store pointers to those instead.
@@ -18,20 +26,27 @@ macro_rules! token_fn {
 
 token_fn!(create_and, And);
 token_fn!(create_break, Break);
+token_fn!(create_catch, Catch);
 token_fn!(create_class, Class);
 token_fn!(create_continue, Continue);
 token_fn!(create_else, Else);
+token_fn!(create_export, Export);
 token_fn!(create_false, False);
+token_fn!(create_finally, Finally);
 token_fn!(create_for, For);
 token_fn!(create_fun, Fun);
 token_fn!(create_if, If);
+token_fn!(create_import, Import);
+token_fn!(create_in, In);
 token_fn!(create_nil, Nil);
 token_fn!(create_or, Or);
 token_fn!(create_print, Print);
 token_fn!(create_return, Return);
 token_fn!(create_super, Super);
 token_fn!(create_this, This);
+token_fn!(create_throw, Throw);
 token_fn!(create_true, True);
+token_fn!(create_try, Try);
 token_fn!(create_var, Var);
 token_fn!(create_while, While);
 
@@ -40,27 +55,111 @@ lazy_static! {
         let mut m = HashMap::new();
         m.insert("and", create_and as fn(u32) -> Token);
         m.insert("break", create_break as fn(u32) -> Token);
+        m.insert("catch", create_catch as fn(u32) -> Token);
         m.insert("class", create_class as fn(u32) -> Token);
         m.insert("continue", create_continue as fn(u32) -> Token);
         m.insert("else", create_else as fn(u32) -> Token);
+        m.insert("export", create_export as fn(u32) -> Token);
         m.insert("false", create_false as fn(u32) -> Token);
+        m.insert("finally", create_finally as fn(u32) -> Token);
         m.insert("for", create_for as fn(u32) -> Token);
         m.insert("fun", create_fun as fn(u32) -> Token);
         m.insert("if", create_if as fn(u32) -> Token);
+        m.insert("import", create_import as fn(u32) -> Token);
+        m.insert("in", create_in as fn(u32) -> Token);
         m.insert("nil", create_nil as fn(u32) -> Token);
         m.insert("or", create_or as fn(u32) -> Token);
         m.insert("print", create_print as fn(u32) -> Token);
         m.insert("return", create_return as fn(u32) -> Token);
         m.insert("super", create_super as fn(u32) -> Token);
         m.insert("this", create_this as fn(u32) -> Token);
+        m.insert("throw", create_throw as fn(u32) -> Token);
         m.insert("true", create_true as fn(u32) -> Token);
+        m.insert("try", create_try as fn(u32) -> Token);
         m.insert("var", create_var as fn(u32) -> Token);
         m.insert("while", create_while as fn(u32) -> Token);
         m
     };
 }
 
-fn consume_next_if<'a>(iter: &mut Peekable<Chars>, line: u32, next_is: char, success: TokenType, failure: TokenType) -> Token {
+/// An error in the source that stops it from being scanned, such as a
+/// malformed number.
+#[derive(Debug)]
+pub struct ScanError {
+    line: u32,
+    message: String,
+    description: String,
+}
+
+impl ScanError {
+    fn new(line: u32, message: String) -> Box<ScanError> {
+        let description = format!("ERR:{}:{}", line, message);
+        Box::new(ScanError { line, message, description })
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// The error message without the location prefix.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+
+impl Error for ScanError {
+    fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+/// Returns all of the language's keywords, in alphabetical order.
+pub fn keywords() -> Vec<&'static str> {
+    let mut keywords: Vec<&'static str> = KEYWORDS.keys().cloned().collect();
+    keywords.sort();
+    keywords
+}
+
+// a peekable char iterator that also keeps track of the byte offset of the
+// next char, so tokens can record where they start in the source
+struct SourceChars<'a> {
+    chars: Peekable<Chars<'a>>,
+    offset: usize,
+}
+
+impl<'a> SourceChars<'a> {
+    fn new(source: &'a String) -> SourceChars<'a> {
+        SourceChars {
+            chars: source.chars().peekable(),
+            offset: 0,
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if let Some(c) = c {
+            self.offset += c.len_utf8();
+        }
+        c
+    }
+
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+
+    // the char after the next one
+    fn peek_second(&self) -> Option<char> {
+        self.chars.clone().nth(1)
+    }
+}
+
+fn consume_next_if(iter: &mut SourceChars, line: u32, next_is: char, success: TokenType, failure: TokenType) -> Token {
     if iter.peek() == Some(&next_is) {
         iter.next();
         Token::simple(success, line)
@@ -69,7 +168,18 @@ fn consume_next_if<'a>(iter: &mut Peekable<Chars>, line: u32, next_is: char, suc
     }
 }
 
-fn consume_slash_or_comment(iter: &mut Peekable<Chars>, line: u32) -> (Token, u32) {
+fn consume_dots(iter: &mut SourceChars, line: u32) -> Token {
+    if iter.peek() != Some(&'.') {
+        return Token::simple(TokenType::Dot, line);
+    }
+    iter.next();
+    match iter.peek() {
+        Some(&'.') => consume_next_if(iter, line, '.', TokenType::Ellipsis, TokenType::DotDot),
+        _ => consume_next_if(iter, line, '=', TokenType::DotDotEqual, TokenType::DotDot),
+    }
+}
+
+fn consume_slash_or_comment(iter: &mut SourceChars, line: u32) -> (Token, u32) {
     match iter.peek() {
         Some(&'/') => {
             let mut new_line = line;
@@ -89,11 +199,11 @@ fn consume_slash_or_comment(iter: &mut Peekable<Chars>, line: u32) -> (Token, u3
             let new_line = consume_block_comment(&mut comment, iter, line);
             (Token::with_lexeme(TokenType::Comment, comment, line), new_line)
         },
-        _ => (Token::simple(TokenType::Slash, line), line)
+        _ => (consume_next_if(iter, line, '=', TokenType::SlashEqual, TokenType::Slash), line)
     }
 }
 
-fn consume_block_comment(comment: &mut String, iter: &mut Peekable<Chars>, line: u32) -> u32 {
+fn consume_block_comment(comment: &mut String, iter: &mut SourceChars, line: u32) -> u32 {
     let mut new_line = line;
     while let Some(c) = iter.next() {
         match c {
@@ -102,10 +212,13 @@ fn consume_block_comment(comment: &mut String, iter: &mut Peekable<Chars>, line:
                 comment.push_str("/*");
                 let new_new_line = consume_block_comment(comment, iter, new_line);
                 new_line = new_new_line;
+            } else {
+                comment.push('/');
             },
             '*' => {
                 comment.push('*');
                 if let Some(&'/') = iter.peek() {
+                    iter.next();
                     comment.push('/');
                     break;
                 }
@@ -121,46 +234,148 @@ fn consume_block_comment(comment: &mut String, iter: &mut Peekable<Chars>, line:
     new_line
 }
 
-fn consume_string(iter: &mut Peekable<Chars>, line: u32) -> (Option<Token>, u32) {
+// scans a string literal, or the rest of one after an interpolated
+// expression (in which case `start` is the closing `}` rather than a quote)
+fn consume_string(iter: &mut SourceChars, line: u32, start: char) -> (Option<Token>, u32) {
     let mut new_line = line;
-    let mut s = String::from("\"");
+    let mut s = start.to_string();
+    let mut token_type = None;
     while let Some(c) = iter.next() {
+        let escaped = s.ends_with("\\") && !s.ends_with("\\\\");
         s.push(c);
-        if c == '\n' {
-            new_line += 1;
+        if c == '"' && !escaped {
+            token_type = Some(if start == '}' { TokenType::InterpolationEnd } else { TokenType::Str });
+            break;
         }
-        if c == '"' && (!s.ends_with("\\") || s.ends_with("\\\\")) {
+        if c == '$' && !escaped && iter.peek() == Some(&'{') {
+            s.push(iter.next().unwrap());
+            token_type = Some(TokenType::Interpolation);
             break;
         }
+        if c == '\n' {
+            new_line += 1;
+        }
     }
-    if iter.peek() != None {
-        let literal = Value::Str(s[1..s.len()-1].to_string());
-        (Some(Token::with_literal(TokenType::Str, s, literal, line)), new_line)
-    } else {
-        (None, new_line)
+    match token_type {
+        Some(token_type) => {
+            let end = if token_type == TokenType::Interpolation { s.len() - 2 } else { s.len() - 1 };
+            let literal = Value::Str(s[1..end].to_string());
+            (Some(Token::with_literal(token_type, s, literal, line)), new_line)
+        },
+        None => (None, new_line),
     }
 }
 
-fn consume_number(iter: &mut Peekable<Chars>, first_char: char, line: u32) -> Token {
+// scans a number literal: digits with optional `_` separators, either in
+// hex, binary or octal after a `0x`, `0b` or `0o` prefix, or in decimal with
+// an optional fraction, exponent and `d` suffix.  As in the Lox spec, a
+// number can't start or end with a `.`.
+fn consume_number(iter: &mut SourceChars, first_char: char) -> Result<(String, Value), String> {
     let mut n = first_char.to_string();
-    while let Some(_) = iter.peek().and_then(|c| {
-        if c.is_numeric() || *c == '.' {
-            n.push(*c);
-            Some(c)
-        } else {
-            None
+    let radix = match (first_char, iter.peek()) {
+        ('0', Some(&'x')) | ('0', Some(&'X')) => 16,
+        ('0', Some(&'b')) | ('0', Some(&'B')) => 2,
+        ('0', Some(&'o')) | ('0', Some(&'O')) => 8,
+        _ => 10,
+    };
+    if radix != 10 {
+        n.push(iter.next().unwrap());
+    }
+    consume_digits(iter, &mut n, radix);
+    let mut is_float = false;
+    let mut is_decimal = false;
+    if radix == 10 {
+        // a dot is only part of the number if a digit follows it, so that
+        // `0..10` is a range
+        if iter.peek() == Some(&'.') && iter.peek_second().map_or(false, |c| c.is_ascii_digit()) {
+            n.push(iter.next().unwrap());
+            consume_digits(iter, &mut n, 10);
+            is_float = true;
         }
-    }) {
-        iter.next();
+        let exponent = match iter.peek_second() {
+            Some(c) => c.is_ascii_digit() || c == '+' || c == '-',
+            None => false,
+        };
+        if (iter.peek() == Some(&'e') || iter.peek() == Some(&'E')) && exponent {
+            n.push(iter.next().unwrap());
+            if iter.peek() == Some(&'+') || iter.peek() == Some(&'-') {
+                n.push(iter.next().unwrap());
+            }
+            consume_digits(iter, &mut n, 10);
+            is_float = true;
+        }
+        // a `d` suffix makes an exact decimal, as in `1.10d`
+        is_decimal = iter.peek() == Some(&'d') && !iter.peek_second().map_or(false, |c| c.is_alphanumeric() || c == '_');
+    }
+
+    // anything else stuck to the end, like the `2` in `0b12` or the `.3` in
+    // `1.2.3`, makes the whole thing malformed
+    let mut malformed = false;
+    loop {
+        match iter.peek().cloned() {
+            Some(c) if !is_decimal && (c.is_alphanumeric() || c == '_') => (),
+            Some('.') if iter.peek_second().map_or(false, |c| c.is_ascii_digit()) => (),
+            _ => break,
+        }
+        n.push(iter.next().unwrap());
+        malformed = true;
+    }
+    let prefix = if radix == 10 { 0 } else { 2 };
+    let digits: String = n[prefix..].chars().filter(|c| *c != '_').collect();
+    if malformed || digits.is_empty() || digits.ends_with(|c| c == 'e' || c == 'E' || c == '+' || c == '-') {
+        return Err(format!("Malformed number '{}'", n));
+    }
+    if !separators_are_between_digits(&n[prefix..], radix) {
+        return Err(format!("Misplaced '_' in number '{}'", n));
+    }
+    // `012` looks like octal in other languages, so it's not allowed to
+    // quietly mean 12
+    let whole_part = digits.split(|c| c == '.' || c == 'e' || c == 'E').next().unwrap();
+    if radix == 10 && whole_part.len() > 1 && whole_part.starts_with('0') {
+        return Err(format!("Leading zero in number '{}'", n));
     }
-    let literal = Value::Number(n.parse().unwrap());
-    Token::with_literal(TokenType::Number, n, literal, line)
+
+    let value = if is_decimal {
+        n.push(iter.next().unwrap());
+        match BigDecimal::from_str(&digits) {
+            Ok(ref d) if d.as_bigint_and_exponent().1.abs() > MAX_DECIMAL_EXPONENT => {
+                return Err(format!("Decimal '{}' has too large an exponent", n));
+            },
+            Ok(d) => Value::Decimal(d),
+            Err(_) => return Err(format!("Decimal '{}' has too large an exponent", n)),
+        }
+    } else if is_float {
+        let f: f64 = digits.parse().unwrap();
+        if f.is_infinite() {
+            return Err(format!("Number '{}' is too large", n));
+        }
+        Value::Number(f)
+    } else {
+        number::integer(BigInt::parse_bytes(digits.as_bytes(), radix).unwrap())
+    };
+    Ok((n, value))
+}
+
+fn consume_digits(iter: &mut SourceChars, n: &mut String, radix: u32) {
+    while let Some(&c) = iter.peek() {
+        if !c.is_digit(radix) && c != '_' {
+            break;
+        }
+        n.push(iter.next().unwrap());
+    }
+}
+
+fn separators_are_between_digits(n: &str, radix: u32) -> bool {
+    let chars: Vec<char> = n.chars().collect();
+    (0..chars.len()).filter(|&i| chars[i] == '_').all(|i| {
+        i > 0 && chars[i - 1].is_digit(radix) && chars.get(i + 1).map_or(false, |c| c.is_digit(radix))
+    })
 }
 
-fn consume_identifier_or_keyword(iter: &mut Peekable<Chars>, first_char: char, line: u32) -> Token {
+fn consume_identifier_or_keyword(iter: &mut SourceChars, first_char: char, line: u32) -> Token {
     let mut s = first_char.to_string();
     while let Some(_) = iter.peek().and_then(|c| {
-        if c.is_alphanumeric() {
+        if c.is_alphanumeric() || *c == '_' {
             s.push(*c);
             Some(c)
         } else {
@@ -175,47 +390,151 @@ fn consume_identifier_or_keyword(iter: &mut Peekable<Chars>, first_char: char, l
     }
 }
 
+// scans (part of) a string, returning the new line number
+fn push_string(tokens: &mut Vec<Token>, interpolations: &mut Vec<u32>, iter: &mut SourceChars, line: u32, start: char) -> u32 {
+    let (token, new_line) = consume_string(iter, line, start);
+    if let Some(t) = token {
+        if t.token_type == TokenType::Interpolation {
+            interpolations.push(0);
+        }
+        tokens.push(t);
+    }
+    new_line
+}
+
 pub fn scan(source: &String) -> Result<Vec<Token>, Box<Error>> {
     let mut tokens = vec![];
     let mut line = 1;
-    let mut iter = source.chars().peekable();
+    let mut iter = SourceChars::new(source);
+    // for each interpolated expression being scanned, how many braces are
+    // open inside it, so we know which `}` ends it
+    let mut interpolations: Vec<u32> = Vec::new();
 
-    while let Some(c) = iter.next() {
+    loop {
+        let offset = iter.offset;
+        let first_new_token = tokens.len();
+        let c = match iter.next() {
+            Some(c) => c,
+            None => break,
+        };
         match c {
             '(' => tokens.push(Token::simple(TokenType::LeftParen, line)),
             ')' => tokens.push(Token::simple(TokenType::RightParen, line)),
-            '{' => tokens.push(Token::simple(TokenType::LeftBrace, line)),
-            '}' => tokens.push(Token::simple(TokenType::RightBrace, line)),
+            '{' => {
+                if let Some(depth) = interpolations.last_mut() {
+                    *depth += 1;
+                }
+                tokens.push(Token::simple(TokenType::LeftBrace, line))
+            },
+            '}' if interpolations.last() == Some(&0) => {
+                interpolations.pop();
+                line = push_string(&mut tokens, &mut interpolations, &mut iter, line, c);
+            },
+            '}' => {
+                if let Some(depth) = interpolations.last_mut() {
+                    *depth -= 1;
+                }
+                tokens.push(Token::simple(TokenType::RightBrace, line))
+            },
+            '[' => tokens.push(Token::simple(TokenType::LeftBracket, line)),
+            ']' => tokens.push(Token::simple(TokenType::RightBracket, line)),
             ',' => tokens.push(Token::simple(TokenType::Comma, line)),
-            '.' => tokens.push(Token::simple(TokenType::Dot, line)),
-            '-' => tokens.push(Token::simple(TokenType::Minus, line)),
-            '+' => tokens.push(Token::simple(TokenType::Plus, line)),
+            ':' => tokens.push(Token::simple(TokenType::Colon, line)),
+            '.' if iter.peek().map_or(false, |c| c.is_ascii_digit()) => {
+                return Err(ScanError::new(line, String::from("A number can't start with '.'; add a leading 0")));
+            },
+            '.' => tokens.push(consume_dots(&mut iter, line)),
+            '-' => tokens.push(consume_next_if(&mut iter, line, '=', TokenType::MinusEqual, TokenType::Minus)),
+            '+' => tokens.push(consume_next_if(&mut iter, line, '=', TokenType::PlusEqual, TokenType::Plus)),
+            '%' => tokens.push(consume_next_if(&mut iter, line, '=', TokenType::PercentEqual, TokenType::Percent)),
+            '&' => tokens.push(Token::simple(TokenType::Ampersand, line)),
+            '|' => tokens.push(Token::simple(TokenType::Pipe, line)),
+            '^' => tokens.push(Token::simple(TokenType::Caret, line)),
+            '~' => tokens.push(Token::simple(TokenType::Tilde, line)),
+            '?' => tokens.push(match iter.peek() {
+                Some(&'.') => consume_next_if(&mut iter, line, '.', TokenType::QuestionDot, TokenType::Question),
+                _ => consume_next_if(&mut iter, line, '?', TokenType::QuestionQuestion, TokenType::Question),
+            }),
             ';' => tokens.push(Token::simple(TokenType::Semicolon, line)),
             '/' => {
                 let (token, new_line) = consume_slash_or_comment(&mut iter, line);
                 line = new_line;
                 tokens.push(token)
             },
-            '*' => tokens.push(Token::simple(TokenType::Star, line)),
+            '*' => tokens.push(match iter.peek() {
+                Some(&'*') => consume_next_if(&mut iter, line, '*', TokenType::StarStar, TokenType::Star),
+                _ => consume_next_if(&mut iter, line, '=', TokenType::StarEqual, TokenType::Star),
+            }),
             '!' => tokens.push(consume_next_if(&mut iter, line, '=', TokenType::BangEqual, TokenType::Bang)),
-            '=' => tokens.push(consume_next_if(&mut iter, line, '=', TokenType::EqualEqual, TokenType::Equal)),
-            '>' => tokens.push(consume_next_if(&mut iter, line, '=', TokenType::GreaterEqual, TokenType::Greater)),
-            '<' => tokens.push(consume_next_if(&mut iter, line, '=', TokenType::LessEqual, TokenType::Less)),
-            '"' => {
-                let (token, new_line) = consume_string(&mut iter, line);
-                line = new_line;
-                match token {
-                    Some(t) => tokens.push(t),
-                    _ => (),
-                }
+            '=' => tokens.push(match iter.peek() {
+                Some(&'>') => consume_next_if(&mut iter, line, '>', TokenType::Arrow, TokenType::Equal),
+                _ => consume_next_if(&mut iter, line, '=', TokenType::EqualEqual, TokenType::Equal),
+            }),
+            '>' => tokens.push(match iter.peek() {
+                Some(&'>') => consume_next_if(&mut iter, line, '>', TokenType::GreaterGreater, TokenType::Greater),
+                _ => consume_next_if(&mut iter, line, '=', TokenType::GreaterEqual, TokenType::Greater),
+            }),
+            '<' => tokens.push(match iter.peek() {
+                Some(&'<') => consume_next_if(&mut iter, line, '<', TokenType::LessLess, TokenType::Less),
+                _ => consume_next_if(&mut iter, line, '=', TokenType::LessEqual, TokenType::Less),
+            }),
+            '"' => line = push_string(&mut tokens, &mut interpolations, &mut iter, line, c),
+            c if c.is_ascii_digit() => match consume_number(&mut iter, c) {
+                Ok((n, value)) => tokens.push(Token::with_literal(TokenType::Number, n, value, line)),
+                Err(message) => return Err(ScanError::new(line, message)),
             },
-            c if c.is_numeric() => tokens.push(consume_number(&mut iter, c, line)),
-            c if c.is_alphabetic() => tokens.push(consume_identifier_or_keyword(&mut iter, c, line)),
+            c if c.is_alphabetic() || c == '_' => tokens.push(consume_identifier_or_keyword(&mut iter, c, line)),
             '\n' => line += 1,
             c if c.is_whitespace() => (),
-            _ => (),  // not sure what we should do here... just skip it?  print a warning?
+            _ => tokens.push(Token::with_lexeme(TokenType::Invalid, c.to_string(), line)),
+        }
+        for token in tokens[first_new_token..].iter_mut() {
+            token.offset = offset;
         }
     }
-    tokens.push(Token::simple(TokenType::Eof, line));
+    let mut eof = Token::simple(TokenType::Eof, line);
+    eof.offset = source.len();
+    tokens.push(eof);
     Ok(tokens)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(source: &str) -> Result<Value, String> {
+        let tokens = scan(&String::from(source)).map_err(|e| e.to_string())?;
+        Ok(tokens[0].literal.clone().unwrap())
+    }
+
+    #[test]
+    fn scans_number_literals() {
+        assert_eq!(Ok(Value::Integer(31)), number("0x1F"));
+        assert_eq!(Ok(Value::Integer(10)), number("0b1010"));
+        assert_eq!(Ok(Value::Integer(15)), number("0o17"));
+        assert_eq!(Ok(Value::Integer(1000000)), number("1_000_000"));
+        assert_eq!(Ok(Value::Number(1e-9)), number("1e-9"));
+        assert_eq!(Ok(Value::Number(250.0)), number("2.5E2"));
+        assert_eq!(Ok(Value::Decimal(BigDecimal::from_str("1.10").unwrap())), number("1.10d"));
+    }
+
+    #[test]
+    fn reports_malformed_numbers() {
+        assert_eq!(Err(String::from("ERR:1:Malformed number '1.2.3'")), number("1.2.3"));
+        assert_eq!(Err(String::from("ERR:1:Malformed number '0b102'")), number("0b102"));
+        assert_eq!(Err(String::from("ERR:1:Malformed number '1e+'")), number("1e+"));
+        assert_eq!(Err(String::from("ERR:1:Misplaced '_' in number '1__0'")), number("1__0"));
+        assert_eq!(Err(String::from("ERR:1:Leading zero in number '00012'")), number("00012"));
+        assert_eq!(Err(String::from("ERR:1:Leading zero in number '0_1.5e3'")), number("0_1.5e3"));
+        assert_eq!(Ok(Value::Integer(0)), number("0"));
+        assert_eq!(Ok(Value::Number(0.5)), number("0.5"));
+        assert_eq!(Ok(Value::Number(0.0)), number("0e5"));
+        assert_eq!(Ok(Value::Integer(8)), number("0o010"));
+        assert!(number(".5").is_err());
+        assert_eq!(Err(String::from("ERR:1:Decimal '1e99999999999999999999d' has too large an exponent")), number("1e99999999999999999999d"));
+        assert_eq!(Err(String::from("ERR:1:Decimal '1e9999999999d' has too large an exponent")), number("1e9999999999d"));
+        assert_eq!(Err(String::from("ERR:1:Decimal '1e-1001d' has too large an exponent")), number("1e-1001d"));
+        // a trailing dot isn't part of the number
+        assert_eq!(Ok(Value::Integer(1)), number("1."));
+    }
+}
@@ -1,42 +1,116 @@
 use std::fmt;
+use std::rc::Rc;
 
 use expression::Expr;
 use token::Token;
 
+/// A parameter of a function.  The default, if there is one, is evaluated
+/// each time the function is called without a value for the parameter.  A
+/// rest parameter, written `...name`, comes last and collects any extra
+/// arguments into a list.
+#[derive(Clone)]
+pub struct Parameter {
+    pub name: Token,
+    pub default: Option<Expr>,
+    pub rest: bool,
+}
+
 #[derive(Clone)]
 pub enum Stmt {
-    Block { statements: Vec<Stmt> },
-    Expression { expression: Expr },
-    For { initializer: Option<Box<Stmt>>, condition: Expr, increment: Option<Box<Stmt>>, body: Box<Stmt> },
-    If { expression: Expr, then_branch: Box<Stmt>, else_branch: Option<Box<Stmt>> },
-    Print { expression: Expr },
+    Block { statements: Vec<Stmt>, line: u32 },
+    Expression { expression: Expr, line: u32 },
+    For { initializer: Option<Box<Stmt>>, condition: Expr, increment: Option<Box<Stmt>>, body: Box<Stmt>, line: u32 },
+    ForIn { name: Token, iterable: Expr, body: Box<Stmt>, line: u32 },
+    /// The body is shared with the functions made from the declaration.
+    Function { name: Token, params: Vec<Parameter>, body: Rc<Vec<Stmt>> },
+    If { expression: Expr, then_branch: Box<Stmt>, else_branch: Option<Box<Stmt>>, line: u32 },
+    /// `import "path" as alias;` or `import { name as local } from "path";`,
+    /// or a bare `import "path";` that only runs the module.
+    Import { keyword: Token, path: String, alias: Option<Token>, names: Vec<(Token, Token)> },
+    /// A top-level `var` or `fun` declaration that importers can see.
+    Export { keyword: Token, declaration: Box<Stmt> },
+    Print { expression: Expr, line: u32 },
+    Return { keyword: Token, value: Option<Expr> },
+    Throw { expression: Expr, line: u32 },
+    Try { body: Box<Stmt>, catch_branch: Option<(Token, Box<Stmt>)>, finally_branch: Option<Box<Stmt>>, line: u32 },
     Var { name: Token, initializer: Option<Expr> },
 }
 
 impl Stmt {
-    pub fn block(statements: Vec<Stmt>) -> Stmt {
+    pub fn block(statements: Vec<Stmt>, line: u32) -> Stmt {
         Stmt::Block {
             statements,
+            line,
         }
     }
 
-    pub fn expression(expression: Expr) -> Stmt {
+    pub fn expression(expression: Expr, line: u32) -> Stmt {
         Stmt::Expression {
             expression,
+            line,
         }
     }
 
-    pub fn if_(expression: Expr, then_branch: Stmt, else_branch: Option<Stmt>) -> Stmt {
+    pub fn function(name: Token, params: Vec<Parameter>, body: Vec<Stmt>) -> Stmt {
+        Stmt::Function {
+            name,
+            params,
+            body: Rc::new(body),
+        }
+    }
+
+    pub fn export(keyword: Token, declaration: Stmt) -> Stmt {
+        Stmt::Export {
+            keyword,
+            declaration: Box::new(declaration),
+        }
+    }
+
+    pub fn import(keyword: Token, path: String, alias: Option<Token>, names: Vec<(Token, Token)>) -> Stmt {
+        Stmt::Import {
+            keyword,
+            path,
+            alias,
+            names,
+        }
+    }
+
+    pub fn if_(expression: Expr, then_branch: Stmt, else_branch: Option<Stmt>, line: u32) -> Stmt {
         Stmt::If {
             expression,
             then_branch: Box::new(then_branch),
             else_branch: else_branch.map(|eb| Box::new(eb)),
+            line,
         }
     }
 
-    pub fn print(expression: Expr) -> Stmt {
+    pub fn print(expression: Expr, line: u32) -> Stmt {
         Stmt::Print {
             expression,
+            line,
+        }
+    }
+
+    pub fn return_(keyword: Token, value: Option<Expr>) -> Stmt {
+        Stmt::Return {
+            keyword,
+            value,
+        }
+    }
+
+    pub fn throw(expression: Expr, line: u32) -> Stmt {
+        Stmt::Throw {
+            expression,
+            line,
+        }
+    }
+
+    pub fn try_(body: Stmt, catch_branch: Option<(Token, Stmt)>, finally_branch: Option<Stmt>, line: u32) -> Stmt {
+        Stmt::Try {
+            body: Box::new(body),
+            catch_branch: catch_branch.map(|(name, cb)| (name, Box::new(cb))),
+            finally_branch: finally_branch.map(|fb| Box::new(fb)),
+            line,
         }
     }
 
@@ -47,12 +121,41 @@ impl Stmt {
         }
     }
 
-    pub fn for_(initializer: Option<Stmt>, condition: Expr, increment: Option<Stmt>, body: Stmt) -> Stmt {
+    pub fn for_(initializer: Option<Stmt>, condition: Expr, increment: Option<Stmt>, body: Stmt, line: u32) -> Stmt {
         Stmt::For {
             initializer: initializer.map(|i| Box::new(i)),
             condition,
             increment: increment.map(|i| Box::new(i)),
             body: Box::new(body),
+            line,
+        }
+    }
+
+    pub fn for_in(name: Token, iterable: Expr, body: Stmt, line: u32) -> Stmt {
+        Stmt::ForIn {
+            name,
+            iterable,
+            body: Box::new(body),
+            line,
+        }
+    }
+
+    /// The source line the statement starts on.
+    pub fn line(&self) -> u32 {
+        match *self {
+            Stmt::Block { line, .. } => line,
+            Stmt::Expression { line, .. } => line,
+            Stmt::For { line, .. } => line,
+            Stmt::ForIn { line, .. } => line,
+            Stmt::Function { ref name, .. } => name.line,
+            Stmt::If { line, .. } => line,
+            Stmt::Import { ref keyword, .. } => keyword.line,
+            Stmt::Export { ref keyword, .. } => keyword.line,
+            Stmt::Print { line, .. } => line,
+            Stmt::Return { ref keyword, .. } => keyword.line,
+            Stmt::Throw { line, .. } => line,
+            Stmt::Try { line, .. } => line,
+            Stmt::Var { ref name, .. } => name.line,
         }
     }
 }
@@ -64,8 +167,15 @@ impl fmt::Display for Stmt {
             Block { .. } => write!(f, "[block]"),
             Expression { .. } => write!(f, "[expression]"),
             For { .. } => write!(f, "[for/while-loop]"),
+            ForIn { ref name, .. } => write!(f, "[for-in {}]", name.lexeme),
+            Function { ref name, .. } => write!(f, "[fun {}]", name.lexeme),
             If { .. } => write!(f, "[if-then-else]"),
+            Import { ref path, .. } => write!(f, "[import {}]", path),
+            Export { ref declaration, .. } => write!(f, "[export {}]", declaration),
             Print { .. } => write!(f, "[print]"),
+            Return { .. } => write!(f, "[return]"),
+            Throw { .. } => write!(f, "[throw]"),
+            Try { .. } => write!(f, "[try-catch-finally]"),
             Var { ref name, .. } => write!(f, "[decl {}]", name.lexeme),
         }
     }
@@ -0,0 +1,170 @@
+use std::rc::Rc;
+
+use callable::NativeFunction;
+use list;
+use value::Value;
+
+// the longest string `repeat` will make, in bytes
+const MAX_REPEAT_LENGTH: usize = 1 << 28;
+
+/// Looks up one of the methods every string has, such as `split`.  Lengths
+/// and positions count characters, not bytes.
+pub fn method(name: &str) -> Option<NativeFunction> {
+    match name {
+        "length" | "len" => Some(NativeFunction::new(name, 0, length)),
+        "substring" => Some(NativeFunction::new(name, 2, substring)),
+        "split" => Some(NativeFunction::new(name, 1, split)),
+        "join" => Some(NativeFunction::new(name, 1, join)),
+        "trim" => Some(NativeFunction::new(name, 0, trim)),
+        "upper" => Some(NativeFunction::new(name, 0, upper)),
+        "lower" => Some(NativeFunction::new(name, 0, lower)),
+        "contains" => Some(NativeFunction::new(name, 1, contains)),
+        "starts_with" => Some(NativeFunction::new(name, 1, starts_with)),
+        "ends_with" => Some(NativeFunction::new(name, 1, ends_with)),
+        "replace" => Some(NativeFunction::new(name, 2, replace)),
+        "find" => Some(NativeFunction::new(name, 1, find)),
+        "repeat" => Some(NativeFunction::new(name, 1, repeat)),
+        _ => None,
+    }
+}
+
+fn string(value: &Value) -> Result<&str, String> {
+    match *value {
+        Value::Str(ref s) => Ok(s),
+        _ => Err(format!("Expected a string, not {}", value)),
+    }
+}
+
+fn new(s: String) -> Result<Rc<Value>, String> {
+    Ok(Rc::new(Value::Str(s)))
+}
+
+fn length(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let length = string(&arguments[0])?.chars().count();
+    Ok(Rc::new(Value::Integer(length as i64)))
+}
+
+fn substring(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let chars: Vec<char> = string(&arguments[0])?.chars().collect();
+    let start = list::bound(&arguments[1], chars.len())?;
+    let end = list::bound(&arguments[2], chars.len())?;
+    new(if start < end { chars[start..end].iter().collect() } else { String::new() })
+}
+
+fn split(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let s = string(&arguments[0])?;
+    let separator = string(&arguments[1])?;
+    let parts: Vec<Rc<Value>> = if separator.is_empty() {
+        s.chars().map(|c| Rc::new(Value::Str(c.to_string()))).collect()
+    } else {
+        s.split(separator).map(|part| Rc::new(Value::Str(String::from(part)))).collect()
+    };
+    Ok(Rc::new(list::new(parts)))
+}
+
+fn join(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let separator = string(&arguments[0])?;
+    match *arguments[1] {
+        Value::List(ref elements) => {
+            let parts: Vec<String> = elements.borrow().iter().map(|e| e.to_string()).collect();
+            new(parts.join(separator))
+        },
+        ref other => Err(format!("Expected a list to join, not {}", other)),
+    }
+}
+
+fn trim(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    new(String::from(string(&arguments[0])?.trim()))
+}
+
+fn upper(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    new(string(&arguments[0])?.to_uppercase())
+}
+
+fn lower(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    new(string(&arguments[0])?.to_lowercase())
+}
+
+fn contains(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let found = string(&arguments[0])?.contains(string(&arguments[1])?);
+    Ok(Rc::new(Value::Boolean(found)))
+}
+
+fn starts_with(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let found = string(&arguments[0])?.starts_with(string(&arguments[1])?);
+    Ok(Rc::new(Value::Boolean(found)))
+}
+
+fn ends_with(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let found = string(&arguments[0])?.ends_with(string(&arguments[1])?);
+    Ok(Rc::new(Value::Boolean(found)))
+}
+
+fn replace(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let from = string(&arguments[1])?;
+    if from.is_empty() {
+        return Err(String::from("Can't replace an empty string"));
+    }
+    new(string(&arguments[0])?.replace(from, string(&arguments[2])?))
+}
+
+// the character position of the first match, or -1 if there isn't one
+fn find(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    let s = string(&arguments[0])?;
+    let position = match s.find(string(&arguments[1])?) {
+        Some(byte_offset) => s[..byte_offset].chars().count() as i64,
+        None => -1,
+    };
+    Ok(Rc::new(Value::Integer(position)))
+}
+
+fn repeat(arguments: Vec<Rc<Value>>) -> Result<Rc<Value>, String> {
+    match *arguments[1] {
+        Value::Integer(n) if n >= 0 => {
+            let s = string(&arguments[0])?;
+            match s.len().checked_mul(n as usize) {
+                Some(length) if length <= MAX_REPEAT_LENGTH => new(s.repeat(n as usize)),
+                _ => Err(format!("Can't repeat a string {} times, as the result would be too long", n)),
+            }
+        },
+        ref other => Err(format!("Can't repeat a string {} times", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, receiver: &str, arguments: Vec<Value>) -> Result<String, String> {
+        let receiver = Rc::new(Value::Str(String::from(receiver)));
+        let arguments = arguments.into_iter().map(Rc::new).collect();
+        method(name).unwrap().bind(receiver).call(arguments).map(|v| v.to_string())
+    }
+
+    fn s(s: &str) -> Value {
+        Value::Str(String::from(s))
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        assert_eq!(Ok(String::from("4")), call("length", "héll", vec![]));
+        assert_eq!(Ok(String::from("él")), call("substring", "héllo", vec![Value::Integer(1), Value::Integer(-2)]));
+        assert_eq!(Ok(String::from("2")), call("find", "héllo", vec![s("l")]));
+        assert_eq!(Ok(String::from("-1")), call("find", "héllo", vec![s("z")]));
+    }
+
+    #[test]
+    fn splits_and_joins() {
+        assert_eq!(Ok(String::from("[\"a\", \"b\", \"\"]")), call("split", "a,b,", vec![s(",")]));
+        let parts = list::new(vec![Rc::new(s("a")), Rc::new(Value::Integer(1))]);
+        assert_eq!(Ok(String::from("a - 1")), call("join", " - ", vec![parts]));
+        assert!(call("join", ",", vec![s("ab")]).is_err());
+    }
+
+    #[test]
+    fn refuses_to_repeat_into_a_huge_string() {
+        assert_eq!(Ok(String::from("ababab")), call("repeat", "ab", vec![Value::Integer(3)]));
+        assert!(call("repeat", "ab", vec![Value::Integer(4611686018427387904)]).is_err());
+        assert_eq!(Ok(String::from("4")), call("len", "héll", vec![]));
+    }
+}
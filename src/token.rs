@@ -9,22 +9,45 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
     Dot,
+    DotDot,
+    DotDotEqual,
+    Ellipsis,
     Minus,
     Plus,
     Semicolon,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    Question,
 
     // single-or-double
     Slash,
     Star,
+    StarStar,
+    Percent,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
+    QuestionQuestion,
+    QuestionDot,
     BangEqual,
     Bang,
     EqualEqual,
     Equal,
+    Arrow,
     GreaterEqual,
+    GreaterGreater,
     Greater,
     LessEqual,
+    LessLess,
     Less,
 
     // keywords
@@ -34,6 +57,7 @@ pub enum TokenType {
     Fun,
     For,
     If,
+    In,
     Or,
     Print,
     Return,
@@ -43,6 +67,12 @@ pub enum TokenType {
     While,
     Continue,
     Break,
+    Throw,
+    Try,
+    Catch,
+    Finally,
+    Import,
+    Export,
 
     // const-literal keywords
     False,
@@ -54,6 +84,11 @@ pub enum TokenType {
     Str,
     Number,
     Comment,
+    /// The part of an interpolated string before a `${`, or between a `}`
+    /// and the next `${`.
+    Interpolation,
+    /// The part of an interpolated string after the last `}`.
+    InterpolationEnd,
 
     Eof,
     Invalid,
@@ -67,20 +102,43 @@ impl TokenType {
             RightParen => Some(")"),
             LeftBrace => Some("{"),
             RightBrace => Some("}"),
+            LeftBracket => Some("["),
+            RightBracket => Some("]"),
             Comma => Some(","),
+            Colon => Some(":"),
             Dot => Some("."),
+            DotDot => Some(".."),
+            DotDotEqual => Some("..="),
+            Ellipsis => Some("..."),
             Minus => Some("-"),
             Plus => Some("+"),
             Semicolon => Some(";"),
+            Ampersand => Some("&"),
+            Pipe => Some("|"),
+            Caret => Some("^"),
+            Tilde => Some("~"),
+            Question => Some("?"),
             Slash => Some("/"),
             Star => Some("*"),
+            StarStar => Some("**"),
+            Percent => Some("%"),
+            PlusEqual => Some("+="),
+            MinusEqual => Some("-="),
+            StarEqual => Some("*="),
+            SlashEqual => Some("/="),
+            PercentEqual => Some("%="),
+            QuestionQuestion => Some("??"),
+            QuestionDot => Some("?."),
             BangEqual => Some("!="),
             Bang => Some("!"),
             EqualEqual => Some("=="),
             Equal => Some("="),
+            Arrow => Some("=>"),
             GreaterEqual => Some(">="),
+            GreaterGreater => Some(">>"),
             Greater => Some(">"),
             LessEqual => Some("<="),
+            LessLess => Some("<<"),
             Less => Some("<"),
             And => Some("and"),
             Class => Some("class"),
@@ -88,6 +146,7 @@ impl TokenType {
             Fun => Some("fun"),
             For => Some("for"),
             If => Some("if"),
+            In => Some("in"),
             Or => Some("or"),
             Print => Some("print"),
             Return => Some("return"),
@@ -97,6 +156,12 @@ impl TokenType {
             While => Some("while"),
             Continue => Some("continue"),
             Break => Some("break"),
+            Throw => Some("throw"),
+            Try => Some("try"),
+            Catch => Some("catch"),
+            Finally => Some("finally"),
+            Import => Some("import"),
+            Export => Some("export"),
             Eof => Some("EOF"),
 
             False => Some("false"),
@@ -122,6 +187,7 @@ impl TokenType {
             None => match *self {
                 TokenType::Identifier => "[identifier]",
                 TokenType::Str => "[string]",
+                TokenType::Interpolation | TokenType::InterpolationEnd => "[interpolation]",
                 TokenType::Number => "[number]",
                 TokenType::Comment => "[comment]",
                 TokenType::Eof => "[EOF]",
@@ -144,6 +210,8 @@ pub struct Token {
     pub lexeme: String,
     pub literal: Option<Value>,
     pub line: u32,
+    /// Byte offset of the start of the token in the source.
+    pub offset: usize,
 }
 
 impl fmt::Display for Token {
@@ -165,6 +233,7 @@ impl Token {
             lexeme: String::from(lexeme),
             literal,
             line,
+            offset: 0,
         }
     }
 
@@ -181,12 +250,15 @@ impl Token {
             lexeme,
             literal,
             line,
+            offset: 0,
         }
     }
 
     pub fn with_literal(token_type: TokenType, lexeme: String, literal: Value, line: u32) -> Token {
         match token_type {
             TokenType::Str => (),
+            TokenType::Interpolation => (),
+            TokenType::InterpolationEnd => (),
             TokenType::Number => (),
             _ => panic!("Cannot use Token::with_literal() for token type {}", token_type),
         }
@@ -195,6 +267,15 @@ impl Token {
             lexeme,
             literal: Some(literal),
             line,
+            offset: 0,
+        }
+    }
+
+    /// Returns the byte offset just past the end of the token in the source.
+    pub fn end_offset(&self) -> usize {
+        match self.token_type {
+            TokenType::Eof => self.offset,
+            _ => self.offset + self.lexeme.len(),
         }
     }
 }
@@ -205,24 +286,25 @@ mod tests {
 
     #[test]
     fn const_lexeme_token_prints() {
-        let t = ConstLexemeTokenType::And.token(42);
-        println!("{}", t)
+        let t = Token::simple(TokenType::And, 42);
+        assert_eq!("<And@42 (and, (none))>", t.to_string())
     }
 
     #[test]
     fn const_literal_token_prints() {
-        let t = False.token(42);
-        println!("{}", t)
+        let t = Token::simple(TokenType::False, 42);
+        assert_eq!("<False@42 (false, false)>", t.to_string())
     }
 
     #[test]
     fn variable_token_prints() {
         let literal = Value::Str(String::from("hello world"));
-        let t = VariableTokenType::String.token(
+        let t = Token::with_literal(
+            TokenType::Str,
             String::from("\"hello world\""),
-            &literal,
+            literal,
             42
         );
-        println!("{}", t)
+        assert_eq!("<Str@42 (\"hello world\", hello world)>", t.to_string())
     }
 }
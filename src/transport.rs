@@ -0,0 +1,93 @@
+use std::io;
+use std::io::prelude::*;
+
+use serde_json;
+use serde_json::Value as Json;
+
+// Both the language server and the debug adapter protocols frame their JSON
+// messages the same way: a block of HTTP-style headers, of which we only
+// care about Content-Length, followed by a blank line and the body.
+
+// a client claiming more than this is broken, and trusting it would let it
+// make us allocate as much as it likes
+const MAX_CONTENT_LENGTH: usize = 64 * 1024 * 1024;
+
+/// Reads the next message, returning `None` at the end of the input.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Json>> {
+    match read_body(reader)? {
+        Some(body) => serde_json::from_slice(&body)
+            .map(|message| Some(message))
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error)),
+        None => Ok(None),
+    }
+}
+
+/// Reads the body of the next message without parsing it, returning `None`
+/// at the end of the input, so that a body that isn't valid JSON can be
+/// reported without giving up on the rest of the input.
+pub fn read_body<R: BufRead>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut content_length = None;
+    let mut has_headers = false;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            // blank lines between messages are harmless
+            if !has_headers {
+                continue;
+            }
+            break;
+        }
+        has_headers = true;
+        let mut parts = header.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim();
+        let value = parts.next().unwrap_or("").trim();
+        if name.eq_ignore_ascii_case("Content-Length") {
+            content_length = Some(value.parse::<usize>().map_err(|_| invalid(format!("Invalid Content-Length '{}'", value)))?);
+        }
+    }
+
+    let content_length = content_length.ok_or_else(|| invalid(String::from("Missing Content-Length header")))?;
+    if content_length > MAX_CONTENT_LENGTH {
+        return Err(invalid(format!("Content-Length {} is over the limit of {}", content_length, MAX_CONTENT_LENGTH)));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn messages_round_trip() {
+        let mut buf = Vec::new();
+        write_message(&mut buf, &json!({"id": 1, "method": "initialize"})).unwrap();
+        write_message(&mut buf, &json!({"id": 2, "text": "héllo"})).unwrap();
+
+        let mut reader = Cursor::new(buf);
+        assert_eq!(Some(json!({"id": 1, "method": "initialize"})), read_message(&mut reader).unwrap());
+        assert_eq!(Some(json!({"id": 2, "text": "héllo"})), read_message(&mut reader).unwrap());
+        assert_eq!(None, read_message(&mut reader).unwrap());
+    }
+
+    #[test]
+    fn rejects_missing_and_huge_lengths() {
+        let mut reader = Cursor::new("Content-Type: json\r\n\r\n{}");
+        assert_eq!("Missing Content-Length header", read_message(&mut reader).unwrap_err().to_string());
+        let mut reader = Cursor::new("Content-Length: 99999999999\r\n\r\n{}");
+        assert!(read_message(&mut reader).unwrap_err().to_string().contains("is over the limit"));
+    }
+}
@@ -1,15 +1,40 @@
+use std::cell::RefCell;
 use std::fmt;
+use std::rc::Rc;
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
 use callable::LoxCallable;
+use map::LoxMap;
+use module::Module;
+use range::Range;
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(Debug, Clone)]
 pub enum Value {
     Nil,
     Str(String),
     Number(f64),
+    /// Number literals without a decimal point are integers, which stay
+    /// exact until they're mixed with a float.
+    Integer(i64),
+    /// Integer arithmetic that overflows carries on with big integers.
+    BigInt(BigInt),
+    /// Exact decimals, written with a `d` suffix as in `1.10d`.
+    Decimal(BigDecimal),
     Boolean(bool),
     Identifier(String),
     Comment(String),
     Callable(LoxCallable),
+    /// Lists are shared, so changes made through one reference are seen
+    /// through all of them.
+    List(Rc<RefCell<Vec<Rc<Value>>>>),
+    /// Maps are shared in the same way as lists.
+    Map(Rc<RefCell<LoxMap>>),
+    Range(Range),
+    /// An exception raised by the interpreter, as seen by a `catch` block.
+    Error { message: String, line: u32 },
+    /// A module imported with `import "path" as name;`.
+    Module(Rc<Module>),
 }
 
 #[allow(non_upper_case_globals)]
@@ -17,24 +42,148 @@ pub const TrueValue: Value = Value::Boolean(true);
 #[allow(non_upper_case_globals)]
 pub const FalseValue: Value = Value::Boolean(false);
 
-impl fmt::Display for Value {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl Value {
+    /// The value as a float, if it's any kind of number.
+    pub fn as_float(&self) -> Option<f64> {
         match *self {
-            Value::Nil => f.write_str("nil"),
-            Value::Str(ref s) => f.write_str(s),
-            Value::Number(n) => f.write_str(&n.to_string()),
-            Value::Boolean(b) => f.write_str(&b.to_string()),
-            Value::Identifier(ref s) => f.write_str(s),
-            Value::Comment(ref s) => f.write_str(s),
-            Value::Callable(ref c) => f.write_str(c.name()),
+            Value::Number(n) => Some(n),
+            Value::Integer(n) => Some(n as f64),
+            Value::BigInt(ref n) => n.to_f64(),
+            Value::Decimal(ref d) => d.to_f64(),
+            _ => None,
         }
     }
 }
 
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        equal(self, other, &mut Vec::new())
+    }
+}
+
+fn pointer<T>(rc: &Rc<T>) -> *const () {
+    &**rc as *const T as *const ()
+}
+
+// `comparing` holds the pairs of lists and maps being compared further up,
+// so that ones which contain themselves are taken to be equal when the
+// same pair comes round again, rather than being compared forever
+fn equal(left: &Value, right: &Value, comparing: &mut Vec<(*const (), *const ())>) -> bool {
+    match (left, right) {
+        (&Value::List(ref l), &Value::List(ref r)) => equal_containers(l, r, comparing, |l, r, comparing| {
+            l.len() == r.len() && l.iter().zip(r.iter()).all(|(a, b)| equal(a, b, comparing))
+        }),
+        // maps are equal if they have the same entries, whatever their order
+        (&Value::Map(ref l), &Value::Map(ref r)) => equal_containers(l, r, comparing, |l, r, comparing| {
+            l.len() == r.len() && l.entries().iter().all(|&(ref key, ref value)| {
+                r.get(key).map_or(false, |other| equal(value, &other, comparing))
+            })
+        }),
+        (&Value::Nil, &Value::Nil) => true,
+        (&Value::Str(ref l), &Value::Str(ref r)) => l == r,
+        (&Value::Number(l), &Value::Number(r)) => l == r,
+        (&Value::Integer(l), &Value::Integer(r)) => l == r,
+        (&Value::BigInt(ref l), &Value::BigInt(ref r)) => l == r,
+        (&Value::Decimal(ref l), &Value::Decimal(ref r)) => l == r,
+        (&Value::Boolean(l), &Value::Boolean(r)) => l == r,
+        (&Value::Identifier(ref l), &Value::Identifier(ref r)) => l == r,
+        (&Value::Comment(ref l), &Value::Comment(ref r)) => l == r,
+        (&Value::Callable(ref l), &Value::Callable(ref r)) => l == r,
+        (&Value::Range(ref l), &Value::Range(ref r)) => l == r,
+        (&Value::Error { message: ref lm, line: ll }, &Value::Error { message: ref rm, line: rl }) => lm == rm && ll == rl,
+        (&Value::Module(ref l), &Value::Module(ref r)) => l == r,
+        _ => false,
+    }
+}
+
+fn equal_containers<T, F>(left: &Rc<RefCell<T>>, right: &Rc<RefCell<T>>, comparing: &mut Vec<(*const (), *const ())>, compare: F) -> bool
+    where F: FnOnce(&T, &T, &mut Vec<(*const (), *const ())>) -> bool {
+    let pair = (pointer(left), pointer(right));
+    if Rc::ptr_eq(left, right) || comparing.contains(&pair) {
+        return true;
+    }
+    comparing.push(pair);
+    let result = compare(&left.borrow(), &right.borrow(), comparing);
+    comparing.pop();
+    result
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_value(f, self, &mut Vec::new())
+    }
+}
+
+// `writing` holds the lists and maps being written further up, so that one
+// which contains itself is written as `[...]` or `{...}` the second time
+// round
+fn write_value(f: &mut fmt::Formatter, value: &Value, writing: &mut Vec<*const ()>) -> fmt::Result {
+    match *value {
+        Value::Nil => f.write_str("nil"),
+        Value::Str(ref s) => f.write_str(s),
+        // whole floats keep their `.0`, so they can be told apart from
+        // integers
+        Value::Number(n) if n.fract() == 0.0 => write!(f, "{:.1}", n),
+        Value::Number(n) => f.write_str(&n.to_string()),
+        Value::Integer(n) => f.write_str(&n.to_string()),
+        Value::BigInt(ref n) => f.write_str(&n.to_string()),
+        Value::Decimal(ref d) => f.write_str(&d.to_string()),
+        Value::Boolean(b) => f.write_str(&b.to_string()),
+        Value::Identifier(ref s) => f.write_str(s),
+        Value::Comment(ref s) => f.write_str(s),
+        Value::Callable(ref c) => f.write_str(c.name()),
+        Value::List(ref elements) => {
+            if writing.contains(&pointer(elements)) {
+                return f.write_str("[...]");
+            }
+            writing.push(pointer(elements));
+            f.write_str("[")?;
+            for (i, element) in elements.borrow().iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write_element(f, element, writing)?;
+            }
+            writing.pop();
+            f.write_str("]")
+        },
+        Value::Range(ref range) => write!(f, "{}", range),
+        Value::Map(ref map) => {
+            if writing.contains(&pointer(map)) {
+                return f.write_str("{...}");
+            }
+            writing.push(pointer(map));
+            f.write_str("{")?;
+            for (i, &(ref key, ref value)) in map.borrow().entries().iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write_element(f, &key.to_value(), writing)?;
+                f.write_str(": ")?;
+                write_element(f, value, writing)?;
+            }
+            writing.pop();
+            f.write_str("}")
+        },
+        Value::Error { ref message, line } => write!(f, "{} (line {})", message, line),
+        Value::Module(ref module) => write!(f, "<module {}>", module.path()),
+    }
+}
+
+// strings inside collections are quoted, so that `["a, b"]` can be told
+// apart from `["a", "b"]`
+fn write_element(f: &mut fmt::Formatter, element: &Value, writing: &mut Vec<*const ()>) -> fmt::Result {
+    match *element {
+        Value::Str(ref s) => write!(f, "\"{}\"", s),
+        ref e => write_value(f, e, writing),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fmt::Write;
+    use map::Key;
 
     fn write_value(value: &Value) -> String {
         let mut s = String::new();
@@ -59,4 +208,37 @@ mod tests {
             &s
         )
     }
+
+    #[test]
+    fn numbers_print_their_kind() {
+        assert_eq!("3", &write_value(&Value::Integer(3)));
+        assert_eq!("3.0", &write_value(&Value::Number(3.0)));
+        assert_eq!("0.5", &write_value(&Value::Number(0.5)));
+    }
+
+    #[test]
+    fn lists_that_contain_themselves_print_and_compare() {
+        let xs = Value::List(Rc::new(RefCell::new(vec![Rc::new(Value::Integer(1))])));
+        let ys = Value::List(Rc::new(RefCell::new(vec![Rc::new(Value::Integer(1))])));
+        for list in [&xs, &ys].iter() {
+            if let Value::List(ref elements) = **list {
+                elements.borrow_mut().push(Rc::new((**list).clone()));
+            }
+        }
+        assert_eq!("[1, [...]]", &write_value(&xs));
+        assert_eq!(xs, ys);
+    }
+
+    #[test]
+    fn maps_that_contain_themselves_print_and_compare() {
+        let make = || {
+            let map = Rc::new(RefCell::new(LoxMap::new()));
+            map.borrow_mut().insert(Key::Str(String::from("self")), Rc::new(Value::Map(Rc::clone(&map))));
+            map.borrow_mut().insert(Key::Integer(1), Rc::new(Value::List(Rc::new(RefCell::new(vec![Rc::new(Value::Map(Rc::clone(&map)))])))));
+            Value::Map(map)
+        };
+        let (m, n) = (make(), make());
+        assert_eq!("{\"self\": {...}, 1: [{...}]}", &write_value(&m));
+        assert_eq!(m, n);
+    }
 }